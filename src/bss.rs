@@ -1,3 +1,268 @@
+pub mod r#struct {
+    use std::fmt;
+
+    use regex::Regex;
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value;
+
+    /// Kernel parameter as found in BSS 'params' field. A parameter may be a flag without value
+    /// (eg 'quiet') or a key/value pair (eg 'console=ttyS0,115200')
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct KernelParam {
+        pub key: String,
+        pub value: Option<String>,
+    }
+
+    impl KernelParam {
+        pub fn new(key: &str, value: Option<&str>) -> Self {
+            Self {
+                key: key.to_string(),
+                value: value.map(|value| value.to_string()),
+            }
+        }
+    }
+
+    impl fmt::Display for KernelParam {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match &self.value {
+                // Values with spaces need to be quoted otherwise the kernel splits them
+                Some(value) if value.contains(char::is_whitespace) => {
+                    write!(f, "{}=\"{}\"", self.key, value)
+                }
+                Some(value) => write!(f, "{}={}", self.key, value),
+                None => write!(f, "{}", self.key),
+            }
+        }
+    }
+
+    /// Parses a kernel command line into a list of kernel parameters. Double quotes are
+    /// honoured (eg 'dyndbg="file foo.c +p"') and removed from the value. Order and duplicates are
+    /// kept since some parameters (eg 'console') are expected to be repeated
+    pub fn parse_kernel_params(params: &str) -> Vec<KernelParam> {
+        let mut token_vec: Vec<String> = Vec::new();
+        let mut token = String::new();
+        let mut in_quotes = false;
+
+        for c in params.chars() {
+            match c {
+                '"' => in_quotes = !in_quotes,
+                c if c.is_whitespace() && !in_quotes => {
+                    if !token.is_empty() {
+                        token_vec.push(token);
+                        token = String::new();
+                    }
+                }
+                _ => token.push(c),
+            }
+        }
+
+        if !token.is_empty() {
+            token_vec.push(token);
+        }
+
+        token_vec
+            .iter()
+            .map(|token| match token.split_once('=') {
+                Some((key, value)) => KernelParam::new(key, Some(value)),
+                None => KernelParam::new(token, None),
+            })
+            .collect()
+    }
+
+    /// Boot parameters ref --> https://github.com/Cray-HPE/docs-csm/blob/release/1.5/api/bss.md#bootparameters
+    #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+    pub struct BootParameters {
+        #[serde(default)]
+        pub hosts: Vec<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub macs: Option<Vec<String>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub nids: Option<Vec<u32>>,
+        #[serde(default)]
+        pub params: String,
+        #[serde(default)]
+        pub kernel: String,
+        #[serde(default)]
+        pub initrd: String,
+        #[serde(rename = "cloud-init")]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub cloud_init: Option<Value>,
+    }
+
+    impl BootParameters {
+        /// Kernel parameters which value points to the boot image
+        pub const IMAGE_KERNEL_PARAM_KEY_VEC: [&'static str; 3] =
+            ["root", "metal.server", "nmd_data"];
+
+        pub fn get_kernel_param_vec(&self) -> Vec<KernelParam> {
+            parse_kernel_params(&self.params)
+        }
+
+        pub fn set_kernel_param_vec(&mut self, kernel_param_vec: &[KernelParam]) {
+            self.params = kernel_param_vec
+                .iter()
+                .map(|kernel_param| kernel_param.to_string())
+                .collect::<Vec<String>>()
+                .join(" ");
+        }
+
+        /// Returns the value of a kernel parameter. If the parameter is duplicated, the last one
+        /// is returned since this is the one the kernel will use
+        pub fn get_kernel_param(&self, key: &str) -> Option<KernelParam> {
+            self.get_kernel_param_vec()
+                .into_iter()
+                .rev()
+                .find(|kernel_param| kernel_param.key == key)
+        }
+
+        /// Appends a kernel parameter even if the key already exists
+        pub fn add_kernel_param(&mut self, key: &str, value: Option<&str>) {
+            let mut kernel_param_vec = self.get_kernel_param_vec();
+            kernel_param_vec.push(KernelParam::new(key, value));
+            self.set_kernel_param_vec(&kernel_param_vec);
+        }
+
+        /// Sets a kernel parameter. If the key already exists, the first occurrence is updated
+        /// and any duplicate is removed, otherwise the parameter is appended
+        pub fn apply_kernel_param(&mut self, key: &str, value: Option<&str>) {
+            let mut kernel_param_vec = self.get_kernel_param_vec();
+
+            if let Some(position) = kernel_param_vec
+                .iter()
+                .position(|kernel_param| kernel_param.key == key)
+            {
+                kernel_param_vec[position] = KernelParam::new(key, value);
+
+                kernel_param_vec = kernel_param_vec
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(i, kernel_param)| *i == position || kernel_param.key != key)
+                    .map(|(_, kernel_param)| kernel_param)
+                    .collect();
+            } else {
+                kernel_param_vec.push(KernelParam::new(key, value));
+            }
+
+            self.set_kernel_param_vec(&kernel_param_vec);
+        }
+
+        /// Removes all occurrences of a kernel parameter. Returns 'true' if anything was removed
+        pub fn remove_kernel_param(&mut self, key: &str) -> bool {
+            let mut kernel_param_vec = self.get_kernel_param_vec();
+            let length = kernel_param_vec.len();
+
+            kernel_param_vec.retain(|kernel_param| kernel_param.key != key);
+
+            let removed = kernel_param_vec.len() != length;
+
+            self.set_kernel_param_vec(&kernel_param_vec);
+
+            removed
+        }
+
+        /// Get Image ID from kernel field, eg 's3://boot-images/<image id>/kernel'
+        pub fn get_boot_image_id(&self) -> Option<String> {
+            self.kernel
+                .strip_prefix("s3://boot-images/")
+                .and_then(|path| path.strip_suffix("/kernel"))
+                .map(|image_id| image_id.to_string())
+        }
+
+        /// Points kernel, initrd and the kernel parameters referencing the boot image (root,
+        /// metal.server and nmd_data) to a new image id. Returns 'false' if the current image id
+        /// could not be found in the kernel path
+        pub fn update_boot_image(&mut self, new_image_id: &str) -> bool {
+            let current_image_id = if let Some(current_image_id) = self.get_boot_image_id() {
+                current_image_id
+            } else {
+                return false;
+            };
+
+            self.kernel = self.kernel.replace(&current_image_id, new_image_id);
+            self.initrd = self.initrd.replace(&current_image_id, new_image_id);
+
+            let kernel_param_vec: Vec<KernelParam> = self
+                .get_kernel_param_vec()
+                .into_iter()
+                .map(|mut kernel_param| {
+                    if Self::IMAGE_KERNEL_PARAM_KEY_VEC.contains(&kernel_param.key.as_str()) {
+                        kernel_param.value = kernel_param
+                            .value
+                            .map(|value| value.replace(&current_image_id, new_image_id));
+                    }
+
+                    kernel_param
+                })
+                .collect();
+
+            self.set_kernel_param_vec(&kernel_param_vec);
+
+            true
+        }
+
+        /// Updates the rootfs etag in the kernel parameters referencing the boot image. CSM
+        /// expects the etag in 'root' (eg 'craycps-s3:s3://boot-images/<image id>/rootfs:<etag>:dvs:...')
+        /// and in 'nmd_data' (eg 'url=s3://boot-images/<image id>/rootfs,etag=<etag>')
+        pub fn update_rootfs_etag(&mut self, etag: &str) {
+            let root_etag_re = Regex::new(r"(s3://boot-images/[^:,]+/rootfs):[^:]+").unwrap();
+            let nmd_data_etag_re = Regex::new(r"etag=[^,]+").unwrap();
+
+            let kernel_param_vec: Vec<KernelParam> = self
+                .get_kernel_param_vec()
+                .into_iter()
+                .map(|mut kernel_param| {
+                    kernel_param.value = kernel_param.value.map(|value| match kernel_param.key.as_str() {
+                        "root" => root_etag_re
+                            .replace(&value, format!("${{1}}:{}", etag))
+                            .to_string(),
+                        "nmd_data" => nmd_data_etag_re
+                            .replace(&value, format!("etag={}", etag))
+                            .to_string(),
+                        _ => value,
+                    });
+
+                    kernel_param
+                })
+                .collect();
+
+            self.set_kernel_param_vec(&kernel_param_vec);
+        }
+
+        /// Returns the differences between 2 boot parameters in a 'diff' like format, lines
+        /// starting with '-' are values in self and lines starting with '+' are values in other
+        pub fn diff(&self, other: &BootParameters) -> Vec<String> {
+            let mut diff_vec = Vec::new();
+
+            if self.kernel != other.kernel {
+                diff_vec.push(format!("- kernel: {}", self.kernel));
+                diff_vec.push(format!("+ kernel: {}", other.kernel));
+            }
+
+            if self.initrd != other.initrd {
+                diff_vec.push(format!("- initrd: {}", self.initrd));
+                diff_vec.push(format!("+ initrd: {}", other.initrd));
+            }
+
+            let kernel_param_vec = self.get_kernel_param_vec();
+            let other_kernel_param_vec = other.get_kernel_param_vec();
+
+            for kernel_param in &kernel_param_vec {
+                if !other_kernel_param_vec.contains(kernel_param) {
+                    diff_vec.push(format!("- param: {}", kernel_param));
+                }
+            }
+
+            for kernel_param in &other_kernel_param_vec {
+                if !kernel_param_vec.contains(kernel_param) {
+                    diff_vec.push(format!("+ param: {}", kernel_param));
+                }
+            }
+
+            diff_vec
+        }
+    }
+}
+
 pub mod http_client {
 
     use serde_json::Value;
@@ -6,6 +271,8 @@ pub mod http_client {
 
     use core::result::Result;
 
+    use super::r#struct::BootParameters;
+
     /// Change nodes boot params, ref --> https://apidocs.svc.cscs.ch/iaas/bss/tag/bootparameters/paths/~1bootparameters/put/
     pub async fn put(
         shasta_base_url: &str,
@@ -33,6 +300,15 @@ pub mod http_client {
             client = client_builder.build()?;
         }
 
+        crate::common::journal::record_boot_parameters(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            xnames,
+            "bss put",
+        )
+        .await?;
+
         let api_url = format!("{}/bss/boot/v1/bootparameters", shasta_base_url);
 
         let resp = client
@@ -79,6 +355,15 @@ pub mod http_client {
             client = client_builder.build()?;
         }
 
+        crate::common::journal::record_boot_parameters(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            xnames,
+            "bss patch",
+        )
+        .await?;
+
         let api_url = format!("{}/bss/boot/v1/bootparameters", shasta_base_url);
 
         let resp = client
@@ -142,11 +427,162 @@ pub mod http_client {
             Err(response?.as_str().unwrap().into()) // Black magic conversion from Err(Box::new("my error msg")) which does not
         }
     }
+
+    /// Get node boot params as a list of BootParameters
+    pub async fn get_boot_parameters(
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+        xnames: &[String],
+    ) -> Result<Vec<BootParameters>, Box<dyn Error>> {
+        let boot_params_value_vec =
+            get_boot_params(shasta_token, shasta_base_url, shasta_root_cert, xnames).await?;
+
+        let mut boot_parameters_vec = Vec::new();
+
+        for boot_params_value in boot_params_value_vec {
+            boot_parameters_vec.push(serde_json::from_value::<BootParameters>(boot_params_value)?);
+        }
+
+        Ok(boot_parameters_vec)
+    }
+
+    /// Patch kernel, initrd and params for the hosts in boot_parameters
+    pub async fn patch_boot_parameters(
+        shasta_base_url: &str,
+        shasta_token: &str,
+        shasta_root_cert: &[u8],
+        boot_parameters: &BootParameters,
+    ) -> Result<Vec<Value>, Box<dyn Error>> {
+        patch(
+            shasta_base_url,
+            shasta_token,
+            shasta_root_cert,
+            &boot_parameters.hosts,
+            Some(&boot_parameters.params),
+            Some(&boot_parameters.kernel),
+            Some(&boot_parameters.initrd),
+        )
+        .await
+    }
 }
 
 pub mod utils {
+    use std::error::Error;
+
+    use serde::{Deserialize, Serialize};
     use serde_json::Value;
 
+    use super::{http_client, r#struct::BootParameters};
+
+    /// Change to apply to the boot parameters of a set of nodes. Both 'current' and 'new' have
+    /// the same hosts
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct BootParametersChange {
+        pub current: BootParameters,
+        pub new: BootParameters,
+    }
+
+    impl BootParametersChange {
+        /// Returns the list of differences (see BootParameters::diff)
+        pub fn diff(&self) -> Vec<String> {
+            self.current.diff(&self.new)
+        }
+
+        /// Returns the change needed to undo this change
+        pub fn reverse(&self) -> Self {
+            Self {
+                current: self.new.clone(),
+                new: self.current.clone(),
+            }
+        }
+    }
+
+    /// Calculates the changes to apply to the boot parameters of a list of nodes without
+    /// changing anything in BSS, this way the user can review the changes (see
+    /// BootParametersChange::diff) before calling `apply_boot_parameters_change`.
+    /// eg. to add a kernel parameter to all nodes:
+    /// plan_boot_parameters_change(.., &xnames, |boot_parameters| boot_parameters.apply_kernel_param("quiet", None))
+    pub async fn plan_boot_parameters_change<F>(
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+        xname_vec: &[String],
+        mut update: F,
+    ) -> Result<Vec<BootParametersChange>, Box<dyn Error>>
+    where
+        F: FnMut(&mut BootParameters),
+    {
+        // Accept NIDs and aliases too
+        let xname_vec = &crate::node::resolver::resolve_xname_vec(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            xname_vec,
+        )
+        .await?;
+
+        let boot_parameters_vec = http_client::get_boot_parameters(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            xname_vec,
+        )
+        .await?;
+
+        let mut boot_parameters_change_vec = Vec::new();
+
+        for mut boot_parameters in boot_parameters_vec {
+            // BSS groups nodes with same boot params, we only want to change the ones requested
+            boot_parameters
+                .hosts
+                .retain(|host| xname_vec.contains(host));
+
+            if boot_parameters.hosts.is_empty() {
+                continue;
+            }
+
+            let mut new_boot_parameters = boot_parameters.clone();
+
+            update(&mut new_boot_parameters);
+
+            if new_boot_parameters != boot_parameters {
+                boot_parameters_change_vec.push(BootParametersChange {
+                    current: boot_parameters,
+                    new: new_boot_parameters,
+                });
+            }
+        }
+
+        Ok(boot_parameters_change_vec)
+    }
+
+    /// Applies the changes calculated by `plan_boot_parameters_change` to BSS
+    pub async fn apply_boot_parameters_change(
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+        boot_parameters_change_vec: &[BootParametersChange],
+    ) -> Result<(), Box<dyn Error>> {
+        for boot_parameters_change in boot_parameters_change_vec {
+            log::info!(
+                "Updating boot parameters for nodes {:?}:\n{}",
+                boot_parameters_change.new.hosts,
+                boot_parameters_change.diff().join("\n")
+            );
+
+            http_client::patch_boot_parameters(
+                shasta_base_url,
+                shasta_token,
+                shasta_root_cert,
+                &boot_parameters_change.new,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
     pub fn find_boot_params_related_to_node(
         node_boot_params_list: &[Value],
         node: &String,
@@ -176,3 +612,55 @@ pub mod utils {
             .to_owned()
     }
 }
+
+#[cfg(test)]
+pub mod test {
+    use super::r#struct::{parse_kernel_params, BootParameters, KernelParam};
+
+    #[test]
+    fn test_parse_kernel_params() {
+        let kernel_param_vec =
+            parse_kernel_params("console=ttyS0 quiet dyndbg=\"file foo.c +p\" console=tty0");
+
+        assert_eq!(
+            kernel_param_vec,
+            vec![
+                KernelParam::new("console", Some("ttyS0")),
+                KernelParam::new("quiet", None),
+                KernelParam::new("dyndbg", Some("file foo.c +p")),
+                KernelParam::new("console", Some("tty0")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_boot_parameters_edit_kernel_params_and_image() {
+        let mut boot_parameters = BootParameters {
+            hosts: vec!["x1003c1s7b0n0".to_string()],
+            params: "console=ttyS0 quiet root=craycps-s3:s3://boot-images/old-id/rootfs:etag console=tty0".to_string(),
+            kernel: "s3://boot-images/old-id/kernel".to_string(),
+            initrd: "s3://boot-images/old-id/initrd".to_string(),
+            ..Default::default()
+        };
+        let original_boot_parameters = boot_parameters.clone();
+
+        boot_parameters.apply_kernel_param("console", Some("ttyS1"));
+        assert!(boot_parameters.remove_kernel_param("quiet"));
+        assert!(!boot_parameters.remove_kernel_param("quiet"));
+        boot_parameters.add_kernel_param("dyndbg", Some("file foo.c +p"));
+        assert!(boot_parameters.update_boot_image("new-id"));
+
+        assert_eq!(
+            boot_parameters.params,
+            "console=ttyS1 root=craycps-s3:s3://boot-images/new-id/rootfs:etag dyndbg=\"file foo.c +p\""
+        );
+        assert_eq!(
+            boot_parameters.get_boot_image_id(),
+            Some("new-id".to_string())
+        );
+        assert_eq!(boot_parameters.initrd, "s3://boot-images/new-id/initrd");
+        assert!(original_boot_parameters
+            .diff(&boot_parameters)
+            .contains(&"+ param: console=ttyS1".to_string()));
+    }
+}
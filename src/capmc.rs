@@ -70,6 +70,32 @@ pub mod http_client {
             reason_opt: Option<String>,
             force: bool,
         ) -> Result<Value, reqwest::Error> {
+            let resp = post_raw(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                xname_vec,
+                reason_opt,
+                force,
+            )
+            .await?;
+
+            match resp.error_for_status() {
+                Ok(response) => Ok(response.json::<Value>().await?),
+                Err(error) => Err(error),
+            }
+        }
+
+        /// Same as `post` but the response status is not checked. CAPMC returns non 2xx if
+        /// some of the nodes fail, the body still contains the result of each node
+        pub async fn post_raw(
+            shasta_token: &str,
+            shasta_base_url: &str,
+            shasta_root_cert: &[u8],
+            xname_vec: Vec<String>,
+            reason_opt: Option<String>,
+            force: bool,
+        ) -> Result<reqwest::Response, reqwest::Error> {
             log::info!("Power OFF nodes: {:?}", xname_vec);
 
             let power_off = PowerStatus::new(reason_opt, xname_vec, force, None);
@@ -93,17 +119,12 @@ pub mod http_client {
 
             let api_url = shasta_base_url.to_owned() + "/capmc/capmc/v1/xname_off";
 
-            let resp = client
+            client
                 .post(api_url)
                 .bearer_auth(shasta_token)
                 .json(&power_off)
                 .send()
-                .await?;
-
-            match resp.error_for_status() {
-                Ok(response) => Ok(response.json::<Value>().await?),
-                Err(error) => Err(error),
-            }
+                .await
         }
 
         /// Shut down a node
@@ -197,6 +218,30 @@ pub mod http_client {
             xname_vec: Vec<String>,
             reason: Option<String>,
         ) -> Result<Value, reqwest::Error> {
+            let resp = post_raw(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                xname_vec,
+                reason,
+            )
+            .await?;
+
+            match resp.error_for_status() {
+                Ok(response) => Ok(response.json::<Value>().await?),
+                Err(error) => Err(error),
+            }
+        }
+
+        /// Same as `post` but the response status is not checked. CAPMC returns non 2xx if
+        /// some of the nodes fail, the body still contains the result of each node
+        pub async fn post_raw(
+            shasta_token: &str,
+            shasta_base_url: &str,
+            shasta_root_cert: &[u8],
+            xname_vec: Vec<String>,
+            reason: Option<String>,
+        ) -> Result<reqwest::Response, reqwest::Error> {
             log::info!("Power ON nodes: {:?}", xname_vec);
 
             let power_on = PowerStatus::new(reason, xname_vec, false, None);
@@ -218,17 +263,12 @@ pub mod http_client {
 
             let api_url = shasta_base_url.to_owned() + "/capmc/capmc/v1/xname_on";
 
-            let resp = client
+            client
                 .post(api_url)
                 .bearer_auth(shasta_token)
                 .json(&power_on)
                 .send()
-                .await?;
-
-            match resp.error_for_status() {
-                Ok(response) => Ok(response.json::<Value>().await?),
-                Err(error) => Err(error),
-            }
+                .await
         }
 
         /// Power ON a group of nodes
@@ -405,26 +445,36 @@ pub mod http_client {
                 let reason_cloned = reason_opt.clone();
 
                 tasks.spawn(async move {
-                    post_sync(
+                    let node_power_status_rslt = post_sync(
                         &shasta_token_string,
                         &shasta_base_url_string,
                         &shasta_root_cert_vec,
-                        vec![xname],
+                        vec![xname.clone()],
                         reason_cloned,
                         force,
                     )
-                    .await
-                    .unwrap()
+                    .await;
+
+                    (xname, node_power_status_rslt)
                 });
             }
 
+            // Failures are reported per node so the caller can see which nodes were not reset
             while let Some(message) = tasks.join_next().await {
-                if let Ok(node_power_status) = message {
-                    nodes_reseted.push(node_power_status);
+                match message {
+                    Ok((_, Ok(node_power_status))) => nodes_reseted.push(node_power_status),
+                    Ok((xname, Err(error))) => {
+                        log::error!("Could not reset node '{}': {}", xname, error);
+                        nodes_reseted.push(serde_json::json!({
+                            "xname": xname,
+                            "error": error.to_string(),
+                        }));
+                    }
+                    Err(error) => log::error!("Node reset task failed: {}", error),
                 }
             }
 
-            Ok(serde_json::to_value(nodes_reseted).unwrap())
+            Ok(Value::Array(nodes_reseted))
         }
     }
 
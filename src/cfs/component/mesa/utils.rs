@@ -0,0 +1,327 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    time::{Duration, Instant},
+};
+
+use serde_json::Value;
+
+use crate::cfs::component::shasta::{http_client, r#struct::Component};
+
+/// Number of components patched per request
+pub const PATCH_BATCH_SIZE: usize = 30;
+
+/// Applies the same changes to a list of components. `component` contains the fields to update,
+/// its id is ignored. Components are patched in batches of PATCH_BATCH_SIZE
+pub async fn patch_component_vec(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+    component: &Component,
+) -> Result<Vec<Value>, Box<dyn Error>> {
+    // Accept NIDs and aliases too
+    let xname_vec = &crate::node::resolver::resolve_xname_vec(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+    )
+    .await?;
+
+    let mut component_value_vec = Vec::new();
+
+    for xname_batch in xname_vec.chunks(PATCH_BATCH_SIZE) {
+        let component_vec = xname_batch
+            .iter()
+            .map(|xname| Component {
+                id: Some(xname.clone()),
+                ..component.clone()
+            })
+            .collect();
+
+        component_value_vec.extend(
+            http_client::patch_component_list(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                component_vec,
+            )
+            .await?,
+        );
+    }
+
+    Ok(component_value_vec)
+}
+
+pub async fn set_enabled(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+    enabled: bool,
+) -> Result<Vec<Value>, Box<dyn Error>> {
+    let component = Component {
+        enabled: Some(enabled),
+        ..Default::default()
+    };
+
+    patch_component_vec(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+        &component,
+    )
+    .await
+}
+
+/// Clears the configuration state of the components, CFS will run all the layers of their
+/// desired configuration again
+pub async fn clear_state(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+) -> Result<Vec<Value>, Box<dyn Error>> {
+    let component = Component {
+        state: Some(Vec::new()),
+        ..Default::default()
+    };
+
+    patch_component_vec(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+        &component,
+    )
+    .await
+}
+
+/// Resets the error count so CFS retries components which reached their retry policy
+pub async fn reset_error_count(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+) -> Result<Vec<Value>, Box<dyn Error>> {
+    let component = Component {
+        error_count: Some(0),
+        ..Default::default()
+    };
+
+    patch_component_vec(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+        &component,
+    )
+    .await
+}
+
+pub async fn set_retry_policy(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+    retry_policy: u64,
+) -> Result<Vec<Value>, Box<dyn Error>> {
+    let component = Component {
+        retry_policy: Some(retry_policy),
+        ..Default::default()
+    };
+
+    patch_component_vec(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+        &component,
+    )
+    .await
+}
+
+/// Sets tags on the components. CFS merges them with the existing tags, a tag with an empty
+/// value is removed
+pub async fn set_tags(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+    tags: HashMap<String, String>,
+) -> Result<Vec<Value>, Box<dyn Error>> {
+    let component = Component {
+        tags: Some(tags),
+        ..Default::default()
+    };
+
+    patch_component_vec(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+        &component,
+    )
+    .await
+}
+
+/// Forces CFS to configure the nodes now: enables them, clears their state and resets their
+/// error count. Then waits for all of them to be 'configured' or 'failed' and returns the
+/// configuration status of each node.
+/// Returns an error if some nodes are not CFS components or have no desired configuration, since
+/// CFS would never configure them, or if some nodes are still pending after `timeout`
+pub async fn reconfigure(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+    poll_interval: Duration,
+    timeout: Duration,
+) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    // Accept NIDs and aliases too
+    let xname_vec = &crate::node::resolver::resolve_xname_vec(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+    )
+    .await?;
+
+    let cfs_component_vec = crate::cfs::component::mesa::http_client::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+    )
+    .await?;
+
+    let missing_xname_vec: Vec<&String> = xname_vec
+        .iter()
+        .filter(|xname| {
+            !cfs_component_vec
+                .iter()
+                .any(|component| component["id"].as_str() == Some(xname.as_str()))
+        })
+        .collect();
+
+    if !missing_xname_vec.is_empty() {
+        return Err(format!("Nodes {:?} are not CFS components", missing_xname_vec).into());
+    }
+
+    let unconfigurable_xname_vec: Vec<&str> = cfs_component_vec
+        .iter()
+        .filter(|component| {
+            component["desiredConfig"]
+                .as_str()
+                .is_none_or(|desired_config| desired_config.is_empty())
+        })
+        .filter_map(|component| component["id"].as_str())
+        .collect();
+
+    if !unconfigurable_xname_vec.is_empty() {
+        return Err(format!(
+            "Nodes {:?} have no desired configuration, assign one before reconfiguring them",
+            unconfigurable_xname_vec
+        )
+        .into());
+    }
+
+    let component = Component {
+        state: Some(Vec::new()),
+        error_count: Some(0),
+        enabled: Some(true),
+        ..Default::default()
+    };
+
+    patch_component_vec(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+        &component,
+    )
+    .await?;
+
+    let deadline = Instant::now() + timeout;
+
+    let mut cfs_configuration_status_map = HashMap::new();
+    let mut pending_xname_vec = xname_vec.to_vec();
+
+    while !pending_xname_vec.is_empty() {
+        if Instant::now() >= deadline {
+            return Err(format!(
+                "Timeout waiting for CFS to configure nodes {:?}",
+                pending_xname_vec
+            )
+            .into());
+        }
+
+        tokio::time::sleep(poll_interval).await;
+
+        cfs_configuration_status_map.extend(get_cfs_configuration_status_map(
+            &crate::cfs::component::mesa::http_client::get(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                &pending_xname_vec,
+            )
+            .await?,
+        ));
+
+        pending_xname_vec
+            .retain(|xname| !is_configuration_final(cfs_configuration_status_map.get(xname)));
+
+        log::info!(
+            "Waiting {} node(s) to be 'configured' or 'failed': {:?}",
+            pending_xname_vec.len(),
+            pending_xname_vec
+        );
+    }
+
+    Ok(cfs_configuration_status_map)
+}
+
+/// Returns a map with the CFS configuration status (eg 'configured', 'pending' or 'failed') of
+/// each node
+pub fn get_cfs_configuration_status_map(cfs_component_vec: &[Value]) -> HashMap<String, String> {
+    cfs_component_vec
+        .iter()
+        .filter_map(|component| {
+            Some((
+                component["id"].as_str()?.to_string(),
+                component["configurationStatus"].as_str()?.to_string(),
+            ))
+        })
+        .collect()
+}
+
+fn is_configuration_final(cfs_configuration_status_opt: Option<&String>) -> bool {
+    matches!(
+        cfs_configuration_status_opt.map(String::as_str),
+        Some("configured") | Some("failed")
+    )
+}
+
+#[cfg(test)]
+pub mod test {
+    use serde_json::json;
+
+    use crate::cfs::component::shasta::r#struct::Component;
+
+    #[test]
+    fn test_component_patch_serialization() {
+        let component = Component {
+            id: Some("x1000c0s0b0n0".to_string()),
+            state: Some(Vec::new()),
+            error_count: Some(0),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            serde_json::to_value(component).unwrap(),
+            json!({ "id": "x1000c0s0b0n0", "state": [], "errorCount": 0 })
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::error::Error;
+
 use serde_json::Value;
 
 use crate::cfs::component::shasta::r#struct::Component;
@@ -75,7 +77,7 @@ pub async fn patch_component(
     shasta_base_url: &str,
     shasta_root_cert: &[u8],
     component: Component,
-) -> Result<Vec<Value>, reqwest::Error> {
+) -> Result<Vec<Value>, Box<dyn Error>> {
     let client_builder = reqwest::Client::builder()
         .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
 
@@ -94,6 +96,15 @@ pub async fn patch_component(
     let api_url =
         shasta_base_url.to_owned() + "/cfs/v2/components/" + &component.clone().id.unwrap();
 
+    crate::common::journal::record_cfs_components(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &[component.id.clone().unwrap_or_default()],
+        "cfs component patch",
+    )
+    .await?;
+
     let response_rslt = client
         .patch(api_url)
         .bearer_auth(shasta_token)
@@ -101,10 +112,7 @@ pub async fn patch_component(
         .send()
         .await;
 
-    match response_rslt {
-        Ok(response) => response.json::<Vec<Value>>().await,
-        Err(error) => Err(error),
-    }
+    Ok(response_rslt?.json::<Vec<Value>>().await?)
 }
 
 pub async fn patch_component_list(
@@ -112,7 +120,7 @@ pub async fn patch_component_list(
     shasta_base_url: &str,
     shasta_root_cert: &[u8],
     component_list: Vec<Component>,
-) -> Result<Vec<Value>, reqwest::Error> {
+) -> Result<Vec<Value>, Box<dyn Error>> {
     let client_builder = reqwest::Client::builder()
         .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
 
@@ -130,6 +138,18 @@ pub async fn patch_component_list(
 
     let api_url = shasta_base_url.to_owned() + "/cfs/v2/components";
 
+    crate::common::journal::record_cfs_components(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &component_list
+            .iter()
+            .filter_map(|component| component.id.clone())
+            .collect::<Vec<String>>(),
+        "cfs component list patch",
+    )
+    .await?;
+
     let response_rslt = client
         .patch(api_url)
         .bearer_auth(shasta_token)
@@ -137,10 +157,10 @@ pub async fn patch_component_list(
         .send()
         .await;
 
-    match response_rslt {
-        Ok(response) => response.json::<Vec<Value>>().await,
-        Err(error) => Err(error),
-    }
+    Ok(response_rslt?
+        .error_for_status()?
+        .json::<Vec<Value>>()
+        .await?)
 }
 
 pub async fn delete_single_component(
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -10,13 +12,14 @@ pub struct State {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub commit: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    #[serde(rename = "sesisonName")]
+    #[serde(rename = "sessionName")]
     pub session_name: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Component {
     pub id: Option<String>,
+    /// An empty list clears the state of the component and forces CFS to reconfigure it
     #[serde(skip_serializing_if = "Option::is_none")]
     pub state: Option<Vec<State>>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -27,11 +30,14 @@ pub struct Component {
     pub desired_config: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "errorCount")]
-    pub error_count: Option<String>,
+    pub error_count: Option<u64>,
+    /// Max number of retries before CFS stops configuring the component
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "retryPolicy")]
-    pub retry_policy: Option<String>,
+    pub retry_policy: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub enabled: Option<bool>,
-    // tags: TODO: this is supposed to be an object??? https://csm12-apidocs.svc.cscs.ch/paas/cfs/operation/patch_component/#!path=tags&t=request
+    /// User defined key/value pairs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<HashMap<String, String>>,
 }
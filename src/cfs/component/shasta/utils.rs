@@ -16,6 +16,7 @@ pub async fn update_component_desired_configuration(
         error_count: None,
         retry_policy: None,
         enabled: Some(enabled),
+        tags: None,
     };
 
     let _ = crate::cfs::component::shasta::http_client::patch_component(
@@ -46,6 +47,7 @@ pub async fn update_component_list_desired_configuration(
             error_count: None,
             retry_policy: None,
             enabled: Some(enabled),
+            tags: None,
         };
 
         component_list.push(component);
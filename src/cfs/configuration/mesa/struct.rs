@@ -1,3 +1,4 @@
 pub mod cfs_configuration;
+pub mod cfs_configuration_builder;
 pub mod cfs_configuration_request;
 pub mod cfs_configuration_response;
@@ -0,0 +1,414 @@
+/// Builder to create CFS configurations from local git repos
+///
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local, SecondsFormat};
+
+use crate::common::{
+    gitea,
+    local_git_repo::{self, GitCredentials},
+};
+
+use super::cfs_configuration_request::{
+    AdditionalInventory, CfsConfigurationRequest, Layer, SpecialParameters,
+};
+
+/// How a layer refers to the code in a repo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LayerGitRef {
+    /// Last commit in the local repo
+    #[default]
+    Commit,
+    /// Current branch of the local repo, CFS resolves it to its last commit
+    Branch,
+}
+
+/// Layer built from a local git repo
+#[derive(Debug, Clone)]
+pub struct LocalRepoLayer {
+    pub repo_path: PathBuf,
+    pub playbook: String,
+    /// Defaults to '<repo name>-<timestamp>'
+    pub layer_name_opt: Option<String>,
+    pub layer_git_ref: LayerGitRef,
+    pub special_parameters_opt: Option<SpecialParameters>,
+}
+
+impl LocalRepoLayer {
+    pub fn new(repo_path: &Path) -> Self {
+        Self {
+            repo_path: repo_path.to_path_buf(),
+            playbook: "site.yml".to_string(),
+            layer_name_opt: None,
+            layer_git_ref: LayerGitRef::default(),
+            special_parameters_opt: None,
+        }
+    }
+
+    pub fn playbook(mut self, playbook: &str) -> Self {
+        self.playbook = playbook.to_string();
+        self
+    }
+
+    pub fn layer_name(mut self, layer_name: &str) -> Self {
+        self.layer_name_opt = Some(layer_name.to_string());
+        self
+    }
+
+    pub fn layer_git_ref(mut self, layer_git_ref: LayerGitRef) -> Self {
+        self.layer_git_ref = layer_git_ref;
+        self
+    }
+
+    pub fn special_parameters(mut self, special_parameters: SpecialParameters) -> Self {
+        self.special_parameters_opt = Some(special_parameters);
+        self
+    }
+}
+
+/// Local repo as found in VCS
+#[derive(Debug, Clone, PartialEq)]
+struct VcsRepo {
+    name: String,
+    clone_url: String,
+    commit: String,
+    branch: String,
+}
+
+/// Base URL CFS pods clone the layer repos from
+pub const VCS_CLONE_BASE_URL: &str = "https://api-gw-service-nmn.local/vcs";
+
+#[derive(Debug, Clone)]
+pub struct CfsConfigurationBuilder {
+    pub name: String,
+    /// Gitea URL used to query and push the repos
+    pub gitea_base_url: String,
+    /// Gitea URL in the layers `cloneUrl`, this is the URL CFS pods clone the repos from
+    pub vcs_clone_base_url: String,
+    /// Gitea organization the repos are in
+    pub vcs_org: String,
+    pub local_repo_layer_vec: Vec<LocalRepoLayer>,
+    pub additional_inventory_opt: Option<(PathBuf, LayerGitRef)>,
+}
+
+impl CfsConfigurationBuilder {
+    pub fn new(name: &str, gitea_base_url: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            gitea_base_url: gitea_base_url.trim_end_matches('/').to_string(),
+            vcs_clone_base_url: VCS_CLONE_BASE_URL.to_string(),
+            vcs_org: "cray".to_string(),
+            local_repo_layer_vec: Vec::new(),
+            additional_inventory_opt: None,
+        }
+    }
+
+    pub fn vcs_clone_base_url(mut self, vcs_clone_base_url: &str) -> Self {
+        self.vcs_clone_base_url = vcs_clone_base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn vcs_org(mut self, vcs_org: &str) -> Self {
+        self.vcs_org = vcs_org.to_string();
+        self
+    }
+
+    pub fn add_layer(mut self, local_repo_layer: LocalRepoLayer) -> Self {
+        self.local_repo_layer_vec.push(local_repo_layer);
+        self
+    }
+
+    pub fn additional_inventory(mut self, repo_path: &Path, layer_git_ref: LayerGitRef) -> Self {
+        self.additional_inventory_opt = Some((repo_path.to_path_buf(), layer_git_ref));
+        self
+    }
+
+    /// Pushes the current branch of the local repos whose last commit is not in VCS. Returns the
+    /// names of the repos pushed. Call it before `build` to create configurations from commits
+    /// not pushed yet
+    pub async fn push_missing_commits(
+        &self,
+        gitea_token: &str,
+        shasta_root_cert: &[u8],
+        git_credentials: &GitCredentials,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut pushed_repo_name_vec = Vec::new();
+
+        for repo_path in self.get_repo_path_vec() {
+            let vcs_repo = self.get_vcs_repo(repo_path)?;
+
+            if self
+                .is_commit_in_vcs(&vcs_repo, gitea_token, shasta_root_cert)
+                .await?
+            {
+                continue;
+            }
+
+            log::info!(
+                "Commit {} of repo {} not found in VCS, pushing it",
+                vcs_repo.commit,
+                vcs_repo.name
+            );
+
+            let repo = local_git_repo::get_repo(&repo_path.to_string_lossy())?;
+
+            local_git_repo::push_to_vcs(
+                &repo,
+                &self.gitea_base_url,
+                &self.vcs_org,
+                git_credentials,
+            )?;
+
+            pushed_repo_name_vec.push(vcs_repo.name);
+        }
+
+        Ok(pushed_repo_name_vec)
+    }
+
+    /// Checks the last commit of the local repos is in VCS, and for branch layers that the branch
+    /// in VCS points to it, and returns the CFS configuration. Nothing is pushed to VCS nor
+    /// created in CFS
+    pub async fn build(
+        &self,
+        gitea_token: &str,
+        shasta_root_cert: &[u8],
+    ) -> Result<CfsConfigurationRequest, Box<dyn Error>> {
+        let mut cfs_configuration = CfsConfigurationRequest::new();
+        cfs_configuration.name = self.name.clone();
+
+        let timestamp = Local::now();
+
+        for local_repo_layer in &self.local_repo_layer_vec {
+            let vcs_repo = self
+                .get_checked_vcs_repo(
+                    &local_repo_layer.repo_path,
+                    local_repo_layer.layer_git_ref,
+                    gitea_token,
+                    shasta_root_cert,
+                )
+                .await?;
+
+            cfs_configuration.add_layer(get_layer(local_repo_layer, &vcs_repo, timestamp));
+        }
+
+        if let Some((repo_path, layer_git_ref)) = &self.additional_inventory_opt {
+            let vcs_repo = self.get_vcs_repo(repo_path)?;
+
+            let (commit, branch) = match layer_git_ref {
+                LayerGitRef::Commit => (Some(vcs_repo.commit.clone()), None),
+                LayerGitRef::Branch => (None, Some(vcs_repo.branch.clone())),
+            };
+
+            cfs_configuration.additional_inventory = Some(AdditionalInventory::new(
+                vcs_repo.clone_url.clone(),
+                commit,
+                vcs_repo.name.clone(),
+                branch,
+            ));
+
+            let vcs_commit_opt = cfs_configuration
+                .validate_additional_inventory(&self.gitea_base_url, gitea_token, shasta_root_cert)
+                .await?;
+
+            if vcs_commit_opt.as_ref() != Some(&vcs_repo.commit) {
+                return Err(format!(
+                    "Additional inventory repo {} points to commit {:?} in VCS but local commit is {}, push it first",
+                    vcs_repo.name, vcs_commit_opt, vcs_repo.commit
+                )
+                .into());
+            }
+        }
+
+        Ok(cfs_configuration)
+    }
+
+    fn get_repo_path_vec(&self) -> Vec<&Path> {
+        self.local_repo_layer_vec
+            .iter()
+            .map(|local_repo_layer| local_repo_layer.repo_path.as_path())
+            .chain(
+                self.additional_inventory_opt
+                    .iter()
+                    .map(|(repo_path, _)| repo_path.as_path()),
+            )
+            .collect()
+    }
+
+    /// Returns the VCS details of a local repo
+    fn get_vcs_repo(&self, repo_path: &Path) -> Result<VcsRepo, Box<dyn Error>> {
+        let repo = local_git_repo::get_repo(&repo_path.to_string_lossy())
+            .map_err(|_| format!("Could not find a git repo in {}", repo_path.display()))?;
+
+        let repo_name = local_git_repo::get_repo_name(&repo)
+            .ok_or_else(|| format!("Could not get repo name for {}", repo_path.display()))?;
+
+        let head = repo.head()?;
+        let branch = head
+            .shorthand()
+            .ok_or_else(|| format!("Invalid branch in repo {}", repo_path.display()))?
+            .to_string();
+        let commit = local_git_repo::get_last_commit(&repo)?.id().to_string();
+
+        Ok(VcsRepo {
+            clone_url: format!(
+                "{}/{}/{}.git",
+                self.vcs_clone_base_url, self.vcs_org, repo_name
+            ),
+            name: repo_name,
+            commit,
+            branch,
+        })
+    }
+
+    async fn is_commit_in_vcs(
+        &self,
+        vcs_repo: &VcsRepo,
+        gitea_token: &str,
+        shasta_root_cert: &[u8],
+    ) -> Result<bool, Box<dyn Error>> {
+        let commit_details_opt = gitea::http_client::get_commit_details_from_repo_name(
+            &self.gitea_base_url,
+            &format!("{}/{}", self.vcs_org, vcs_repo.name),
+            &vcs_repo.commit,
+            gitea_token,
+            shasta_root_cert,
+        )
+        .await?;
+
+        Ok(commit_details_opt.is_some())
+    }
+
+    /// Returns the VCS details of a local repo, fails if its last commit is not in VCS or, for
+    /// `LayerGitRef::Branch`, if the branch in VCS does not point to it since CFS resolves the
+    /// branch in VCS
+    async fn get_checked_vcs_repo(
+        &self,
+        repo_path: &Path,
+        layer_git_ref: LayerGitRef,
+        gitea_token: &str,
+        shasta_root_cert: &[u8],
+    ) -> Result<VcsRepo, Box<dyn Error>> {
+        let vcs_repo = self.get_vcs_repo(repo_path)?;
+
+        if !self
+            .is_commit_in_vcs(&vcs_repo, gitea_token, shasta_root_cert)
+            .await?
+        {
+            return Err(format!(
+                "Commit {} of repo {} not found in VCS, push it first",
+                vcs_repo.commit, vcs_repo.name
+            )
+            .into());
+        }
+
+        if layer_git_ref == LayerGitRef::Branch {
+            let branch_details = gitea::http_client::get_branch_details(
+                &self.gitea_base_url,
+                &format!("{}/{}", self.vcs_org, vcs_repo.name),
+                &vcs_repo.branch,
+                gitea_token,
+                shasta_root_cert,
+            )
+            .await?;
+
+            let vcs_commit = branch_details["commit"]["id"].as_str().unwrap_or_default();
+
+            if vcs_commit != vcs_repo.commit {
+                return Err(format!(
+                    "Branch {} of repo {} points to commit {} in VCS but local commit is {}, push it first",
+                    vcs_repo.branch, vcs_repo.name, vcs_commit, vcs_repo.commit
+                )
+                .into());
+            }
+        }
+
+        Ok(vcs_repo)
+    }
+}
+
+fn get_layer(
+    local_repo_layer: &LocalRepoLayer,
+    vcs_repo: &VcsRepo,
+    timestamp: DateTime<Local>,
+) -> Layer {
+    let (commit, branch) = match local_repo_layer.layer_git_ref {
+        LayerGitRef::Commit => (Some(vcs_repo.commit.clone()), None),
+        LayerGitRef::Branch => (None, Some(vcs_repo.branch.clone())),
+    };
+
+    let layer_name = local_repo_layer.layer_name_opt.clone().unwrap_or_else(|| {
+        format!(
+            "{}-{}",
+            vcs_repo.name,
+            timestamp.to_rfc3339_opts(SecondsFormat::Secs, true)
+        )
+    });
+
+    let mut layer = Layer::new(
+        vcs_repo.clone_url.clone(),
+        commit,
+        layer_name,
+        local_repo_layer.playbook.clone(),
+        branch,
+        None,
+    );
+    layer.special_parameters = local_repo_layer.special_parameters_opt.clone();
+
+    layer
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::path::Path;
+
+    use chrono::Local;
+
+    use super::{get_layer, LayerGitRef, LocalRepoLayer, VcsRepo};
+    use crate::cfs::configuration::mesa::r#struct::cfs_configuration_request::SpecialParameters;
+
+    #[test]
+    fn test_get_layer() {
+        let vcs_repo = VcsRepo {
+            name: "uan-config-management".to_string(),
+            clone_url: "https://api-gw-service-nmn.local/vcs/cray/uan-config-management.git"
+                .to_string(),
+            commit: "8f6a9a0c0e4d4d3f1c4b2a0e8e1c7b1a7f9d2e3c".to_string(),
+            branch: "integration".to_string(),
+        };
+
+        let layer = get_layer(
+            &LocalRepoLayer::new(Path::new("/tmp/uan-config-management")),
+            &vcs_repo,
+            Local::now(),
+        );
+
+        assert_eq!(layer.commit, Some(vcs_repo.commit.clone()));
+        assert_eq!(layer.branch, None);
+        assert_eq!(layer.playbook, "site.yml");
+        assert!(layer.name.starts_with("uan-config-management-"));
+
+        let layer = get_layer(
+            &LocalRepoLayer::new(Path::new("/tmp/uan-config-management"))
+                .playbook("uan.yml")
+                .layer_name("uan")
+                .layer_git_ref(LayerGitRef::Branch)
+                .special_parameters(SpecialParameters {
+                    ims_require_dkms: Some(true),
+                }),
+            &vcs_repo,
+            Local::now(),
+        );
+
+        assert_eq!(layer.commit, None);
+        assert_eq!(layer.branch, Some("integration".to_string()));
+        assert_eq!(layer.name, "uan");
+        assert_eq!(layer.playbook, "uan.yml");
+        assert_eq!(
+            layer.special_parameters.and_then(|p| p.ims_require_dkms),
+            Some(true)
+        );
+    }
+}
@@ -1,32 +1,55 @@
 /// Structs related to CFS confguration with data related to most recent commit id like, author
 /// name, commit date, etc
 ///
-use std::path::PathBuf;
+use std::{error::Error, path::PathBuf};
 
-use k8s_openapi::chrono;
 use serde::{Deserialize, Serialize};
-use substring::Substring;
 
-use crate::common::{gitea, local_git_repo};
+use crate::common::gitea;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+use super::cfs_configuration_builder::{CfsConfigurationBuilder, LayerGitRef, LocalRepoLayer};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct SpecialParameters {
+    /// Install DKMS kernel modules when customizing images
+    #[serde(rename = "imsRequireDkms", skip_serializing_if = "Option::is_none")]
+    pub ims_require_dkms: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Layer {
     #[serde(rename = "cloneUrl")]
-    clone_url: String,
+    pub clone_url: String,
     #[serde(skip_serializing_if = "Option::is_none")] // Either commit or branch is passed
-    commit: Option<String>,
-    name: String,
-    playbook: String,
+    pub commit: Option<String>,
+    pub name: String,
+    pub playbook: String,
     #[serde(skip_serializing_if = "Option::is_none")] // Either commit or branch is passed
-    branch: Option<String>,
+    pub branch: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    tag: Option<String>,
+    pub tag: Option<String>,
+    #[serde(rename = "specialParameters", skip_serializing_if = "Option::is_none")]
+    pub special_parameters: Option<SpecialParameters>,
+}
+
+/// Repo with ansible inventory added to the inventory CFS generates
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AdditionalInventory {
+    #[serde(rename = "cloneUrl")]
+    pub clone_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")] // Either commit or branch is passed
+    pub commit: Option<String>,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")] // Either commit or branch is passed
+    pub branch: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)] // TODO: investigate why serde can Deserialize dynamically syzed structs `Vec<Layer>`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)] // TODO: investigate why serde can Deserialize dynamically syzed structs `Vec<Layer>`
 pub struct CfsConfigurationRequest {
     pub name: String,
     pub layers: Vec<Layer>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub additional_inventory: Option<AdditionalInventory>,
 }
 
 impl Layer {
@@ -45,6 +68,23 @@ impl Layer {
             playbook,
             branch,
             tag,
+            special_parameters: None,
+        }
+    }
+}
+
+impl AdditionalInventory {
+    pub fn new(
+        clone_url: String,
+        commit: Option<String>,
+        name: String,
+        branch: Option<String>,
+    ) -> Self {
+        Self {
+            clone_url,
+            commit,
+            name,
+            branch,
         }
     }
 }
@@ -60,6 +100,7 @@ impl CfsConfigurationRequest {
         Self {
             name: String::default(),
             layers: Vec::default(),
+            additional_inventory: None,
         }
     }
 
@@ -79,7 +120,7 @@ impl CfsConfigurationRequest {
                 // Git layer
                 let repo_name = layer_yaml["name"].as_str().unwrap().to_string();
                 let repo_url = layer_yaml["git"]["url"].as_str().unwrap().to_string();
-                let layer = Layer::new(
+                let mut layer = Layer::new(
                     repo_url,
                     layer_yaml["git"]["commit"]
                         .as_str()
@@ -94,6 +135,7 @@ impl CfsConfigurationRequest {
                         .map(|branch| branch.to_string()),
                     layer_yaml["git"]["tag"].as_str().map(|tag| tag.to_string()),
                 );
+                layer.special_parameters = get_special_parameters_from_sat(layer_yaml);
                 cfs_configuration.add_layer(layer);
             } else {
                 // Product layer
@@ -101,7 +143,7 @@ impl CfsConfigurationRequest {
                     "https://api-gw-service-nmn.local/vcs/cray/{}-config-management.git",
                     layer_yaml["name"].as_str().unwrap()
                 );
-                let layer = Layer::new(
+                let mut layer = Layer::new(
                     repo_url,
                     // Some(layer_json["product"]["commit"].as_str().unwrap_or_default().to_string()),
                     None,
@@ -118,98 +160,347 @@ impl CfsConfigurationRequest {
                     ),
                     None,
                 );
+                layer.special_parameters = get_special_parameters_from_sat(layer_yaml);
                 cfs_configuration.add_layer(layer);
             }
         }
+
+        let additional_inventory_yaml = &configuration_yaml["additional_inventory"];
+
+        if let Some(repo_url) = additional_inventory_yaml["url"].as_str() {
+            cfs_configuration.additional_inventory = Some(AdditionalInventory::new(
+                repo_url.to_string(),
+                additional_inventory_yaml["commit"]
+                    .as_str()
+                    .map(|commit| commit.to_string()),
+                additional_inventory_yaml["name"]
+                    .as_str()
+                    .or_else(|| get_repo_name_from_clone_url(repo_url))
+                    .unwrap_or_default()
+                    .to_string(),
+                additional_inventory_yaml["branch"]
+                    .as_str()
+                    .map(|branch| branch.to_string()),
+            ));
+        }
+
+        cfs_configuration
+    }
+
+    /// Returns the configuration in SAT file format. All layers are git layers
+    pub fn to_sat_file_serde_yaml(&self) -> Result<serde_yaml::Value, serde_yaml::Error> {
+        let layer_vec: Vec<serde_json::Value> = self
+            .layers
+            .iter()
+            .map(|layer| {
+                let mut layer_json = serde_json::json!({
+                    "name": layer.name,
+                    "playbook": layer.playbook,
+                    "git": { "url": layer.clone_url },
+                });
+
+                for (key, value_opt) in [
+                    ("commit", &layer.commit),
+                    ("branch", &layer.branch),
+                    ("tag", &layer.tag),
+                ] {
+                    if let Some(value) = value_opt {
+                        layer_json["git"][key] = serde_json::json!(value);
+                    }
+                }
+
+                if let Some(ims_require_dkms) = layer
+                    .special_parameters
+                    .as_ref()
+                    .and_then(|special_parameters| special_parameters.ims_require_dkms)
+                {
+                    layer_json["special_parameters"] =
+                        serde_json::json!({ "ims_require_dkms": ims_require_dkms });
+                }
+
+                layer_json
+            })
+            .collect();
+
+        let mut configuration_json = serde_json::json!({
+            "name": self.name,
+            "layers": layer_vec,
+        });
+
+        if let Some(additional_inventory) = &self.additional_inventory {
+            configuration_json["additional_inventory"] = serde_json::json!({
+                "url": additional_inventory.clone_url,
+                "name": additional_inventory.name,
+            });
+
+            for (key, value_opt) in [
+                ("commit", &additional_inventory.commit),
+                ("branch", &additional_inventory.branch),
+            ] {
+                if let Some(value) = value_opt {
+                    configuration_json["additional_inventory"][key] = serde_json::json!(value);
+                }
+            }
+        }
+
+        serde_yaml::to_value(configuration_json)
+    }
+
+    /// Same as `from_sat_file_serde_yaml` but also checks the additional inventory exists in
+    /// Gitea
+    pub async fn from_sat_file_serde_yaml_and_validate(
+        configuration_yaml: &serde_yaml::Value,
+        gitea_base_url: &str,
+        gitea_token: &str,
+        shasta_root_cert: &[u8],
+    ) -> Result<Self, Box<dyn Error>> {
+        let cfs_configuration = Self::from_sat_file_serde_yaml(configuration_yaml);
+
         cfs_configuration
+            .validate_additional_inventory(gitea_base_url, gitea_token, shasta_root_cert)
+            .await?;
+
+        Ok(cfs_configuration)
     }
 
+    /// Checks the additional inventory repo exists in Gitea with the commit or branch requested.
+    /// Returns the commit the additional inventory points to in Gitea
+    pub async fn validate_additional_inventory(
+        &self,
+        gitea_base_url: &str,
+        gitea_token: &str,
+        shasta_root_cert: &[u8],
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        let additional_inventory = match &self.additional_inventory {
+            Some(additional_inventory) => additional_inventory,
+            None => return Ok(None),
+        };
+
+        let (repo_name, layer_git_ref, git_ref) =
+            get_additional_inventory_git_ref(additional_inventory)?;
+
+        let commit = match layer_git_ref {
+            LayerGitRef::Commit => {
+                gitea::http_client::get_commit_details_from_repo_name(
+                    gitea_base_url,
+                    repo_name,
+                    git_ref,
+                    gitea_token,
+                    shasta_root_cert,
+                )
+                .await?
+                .ok_or_else(|| {
+                    format!(
+                        "Commit '{}' not found in repo '{}' in Shasta VCS",
+                        git_ref, repo_name
+                    )
+                })?;
+
+                git_ref.to_string()
+            }
+            LayerGitRef::Branch => {
+                let branch_details = gitea::http_client::get_branch_details(
+                    gitea_base_url,
+                    repo_name,
+                    git_ref,
+                    gitea_token,
+                    shasta_root_cert,
+                )
+                .await?;
+
+                branch_details["commit"]["id"]
+                    .as_str()
+                    .ok_or_else(|| {
+                        format!(
+                            "Could not get last commit of branch '{}' in repo '{}' in Shasta VCS",
+                            git_ref, repo_name
+                        )
+                    })?
+                    .to_string()
+            }
+        };
+
+        Ok(Some(commit))
+    }
+
+    /// Creates a CFS configuration with a layer per local repo using the defaults in
+    /// `CfsConfigurationBuilder`. Use the builder to customize the layers.
+    /// `gitea_base_url` is used to check the commits in Gitea and `vcs_clone_base_url` to build
+    /// the layers clone URL (eg 'https://api-gw-service-nmn.local/vcs')
     pub async fn create_from_repos(
         gitea_token: &str,
         gitea_base_url: &str,
+        vcs_clone_base_url: &str,
         shasta_root_cert: &[u8],
         repos: Vec<PathBuf>,
-        cfs_configuration_name: &String,
-    ) -> Self {
-        // Create CFS configuration
-        let mut cfs_configuration = CfsConfigurationRequest::new();
-        cfs_configuration.name = cfs_configuration_name.to_string();
-
-        for repo_path in &repos {
-            // Get repo from path
-            let repo = match local_git_repo::get_repo(&repo_path.to_string_lossy()) {
-                Ok(repo) => repo,
-                Err(_) => {
-                    eprintln!(
-                        "Could not find a git repo in {}",
-                        repo_path.to_string_lossy()
-                    );
-                    std::process::exit(1);
-                }
-            };
+        cfs_configuration_name: &str,
+    ) -> Result<Self, Box<dyn Error>> {
+        repos
+            .into_iter()
+            .fold(
+                CfsConfigurationBuilder::new(cfs_configuration_name, gitea_base_url)
+                    .vcs_clone_base_url(vcs_clone_base_url),
+                |cfs_configuration_builder, repo_path| {
+                    cfs_configuration_builder.add_layer(LocalRepoLayer::new(&repo_path))
+                },
+            )
+            .build(gitea_token, shasta_root_cert)
+            .await
+    }
+}
 
-            // Get last (most recent) commit
-            let local_last_commit = local_git_repo::get_last_commit(&repo).unwrap();
+/// Returns the repo name in VCS of the additional inventory and the commit or branch it points to
+fn get_additional_inventory_git_ref(
+    additional_inventory: &AdditionalInventory,
+) -> Result<(&str, LayerGitRef, &str), Box<dyn Error>> {
+    let repo_name =
+        get_repo_name_from_clone_url(&additional_inventory.clone_url).ok_or_else(|| {
+            format!(
+                "Additional inventory url '{}' is not a VCS url",
+                additional_inventory.clone_url
+            )
+        })?;
 
-            // Get repo name
-            let repo_ref_origin = repo.find_remote("origin").unwrap();
+    match (&additional_inventory.commit, &additional_inventory.branch) {
+        (Some(commit), None) => Ok((repo_name, LayerGitRef::Commit, commit)),
+        (None, Some(branch)) => Ok((repo_name, LayerGitRef::Branch, branch)),
+        _ => Err(format!(
+            "Additional inventory '{}' must have either a commit or a branch",
+            additional_inventory.name
+        )
+        .into()),
+    }
+}
 
-            log::info!("Repo ref origin URL: {}", repo_ref_origin.url().unwrap());
+fn get_special_parameters_from_sat(layer_yaml: &serde_yaml::Value) -> Option<SpecialParameters> {
+    layer_yaml
+        .get("special_parameters")
+        .map(|special_parameters_yaml| SpecialParameters {
+            ims_require_dkms: special_parameters_yaml["ims_require_dkms"].as_bool(),
+        })
+}
 
-            let repo_ref_origin_url = repo_ref_origin.url().unwrap();
+/// Returns the repo name in VCS, eg
+/// 'https://api-gw-service-nmn.local/vcs/cray/uan-config-management.git' -->
+/// 'cray/uan-config-management'
+pub fn get_repo_name_from_clone_url(clone_url: &str) -> Option<&str> {
+    clone_url
+        .split_once("/vcs/")
+        .map(|(_, repo_name)| repo_name.trim_end_matches(".git"))
+}
 
-            let repo_name = repo_ref_origin_url.substring(
-                repo_ref_origin_url.rfind(|c| c == '/').unwrap() + 1, // repo name should not include URI '/' separator
-                repo_ref_origin_url.len(), // repo_ref_origin_url.rfind(|c| c == '.').unwrap(),
-            );
+#[cfg(test)]
+pub mod test {
+    use super::{get_additional_inventory_git_ref, AdditionalInventory, CfsConfigurationRequest};
+    use crate::cfs::configuration::mesa::r#struct::cfs_configuration_builder::LayerGitRef;
 
-            let api_url = "cray/".to_owned() + repo_name;
+    #[test]
+    fn test_get_additional_inventory_git_ref() {
+        let clone_url = "https://api-gw-service-nmn.local/vcs/cray/inventory.git".to_string();
+        let commit = "8f6a9a0c0e4d4d3f1c4b2a0e8e1c7b1a7f9d2e3c".to_string();
 
-            // Check if repo and local commit id exists in Shasta cvs
-            let shasta_commitid_details_resp = gitea::http_client::get_commit_details(
-                &api_url,
-                // &format!("/cray/{}", repo_name),
-                &local_last_commit.id().to_string(),
-                gitea_token,
-                shasta_root_cert,
-            )
-            .await;
-
-            // Check sync status between user face and shasta VCS
-            let shasta_commitid_details: serde_json::Value = match shasta_commitid_details_resp {
-                Ok(_) => {
-                    log::debug!(
-                        "Local latest commit id {} for repo {} exists in shasta",
-                        local_last_commit.id(),
-                        repo_name
-                    );
-                    shasta_commitid_details_resp.unwrap()
-                }
-                Err(e) => {
-                    eprintln!("{}", e);
-                    std::process::exit(1);
-                }
-            };
-
-            let clone_url = gitea_base_url.to_owned() + "/cray/" + repo_name;
-
-            // Create CFS layer
-            let cfs_layer = Layer::new(
-                clone_url,
-                Some(shasta_commitid_details["sha"].as_str().unwrap().to_string()),
-                format!(
-                    "{}-{}",
-                    repo_name.substring(0, repo_name.len()),
-                    chrono::offset::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
-                ),
-                String::from("site.yml"),
-                None,
-                None,
-            );
-
-            CfsConfigurationRequest::add_layer(&mut cfs_configuration, cfs_layer);
-        }
+        let additional_inventory = AdditionalInventory::new(
+            clone_url.clone(),
+            Some(commit.clone()),
+            "inventory".to_string(),
+            None,
+        );
+        assert_eq!(
+            get_additional_inventory_git_ref(&additional_inventory).unwrap(),
+            ("cray/inventory", LayerGitRef::Commit, commit.as_str())
+        );
 
-        cfs_configuration
+        let additional_inventory = AdditionalInventory::new(
+            clone_url.clone(),
+            None,
+            "inventory".to_string(),
+            Some("main".to_string()),
+        );
+        assert_eq!(
+            get_additional_inventory_git_ref(&additional_inventory).unwrap(),
+            ("cray/inventory", LayerGitRef::Branch, "main")
+        );
+
+        let additional_inventory = AdditionalInventory::new(
+            clone_url.clone(),
+            Some(commit.clone()),
+            "inventory".to_string(),
+            Some("main".to_string()),
+        );
+        assert!(get_additional_inventory_git_ref(&additional_inventory).is_err());
+
+        let additional_inventory =
+            AdditionalInventory::new(clone_url, None, "inventory".to_string(), None);
+        assert!(get_additional_inventory_git_ref(&additional_inventory).is_err());
+
+        let additional_inventory = AdditionalInventory::new(
+            "https://github.com/cray/inventory.git".to_string(),
+            Some(commit),
+            "inventory".to_string(),
+            None,
+        );
+        assert!(get_additional_inventory_git_ref(&additional_inventory).is_err());
+    }
+
+    #[test]
+    fn test_sat_file_round_trip() {
+        let configuration_yaml: serde_yaml::Value = serde_yaml::from_str(
+            r#"
+name: uan-config
+layers:
+- name: uan
+  playbook: site.yml
+  git:
+    url: https://api-gw-service-nmn.local/vcs/cray/uan-config-management.git
+    branch: integration
+  special_parameters:
+    ims_require_dkms: true
+- name: cos
+  playbook: cos.yml
+  git:
+    url: https://api-gw-service-nmn.local/vcs/cray/cos-config-management.git
+    commit: 8f6a9a0c0e4d4d3f1c4b2a0e8e1c7b1a7f9d2e3c
+additional_inventory:
+  url: https://api-gw-service-nmn.local/vcs/cray/inventory.git
+  branch: main
+"#,
+        )
+        .unwrap();
+
+        let cfs_configuration =
+            CfsConfigurationRequest::from_sat_file_serde_yaml(&configuration_yaml);
+
+        assert_eq!(
+            cfs_configuration.layers[0]
+                .special_parameters
+                .as_ref()
+                .and_then(|special_parameters| special_parameters.ims_require_dkms),
+            Some(true)
+        );
+        assert_eq!(cfs_configuration.layers[1].special_parameters, None);
+        assert_eq!(
+            cfs_configuration
+                .additional_inventory
+                .as_ref()
+                .map(|additional_inventory| additional_inventory.name.as_str()),
+            Some("cray/inventory")
+        );
+
+        let cfs_configuration_json = serde_json::to_value(&cfs_configuration).unwrap();
+        assert_eq!(
+            cfs_configuration_json["layers"][0]["specialParameters"]["imsRequireDkms"],
+            true
+        );
+        assert_eq!(
+            cfs_configuration_json["additional_inventory"]["branch"],
+            "main"
+        );
+
+        assert_eq!(
+            CfsConfigurationRequest::from_sat_file_serde_yaml(
+                &cfs_configuration.to_sat_file_serde_yaml().unwrap()
+            ),
+            cfs_configuration
+        );
     }
 }
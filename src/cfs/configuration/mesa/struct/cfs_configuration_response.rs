@@ -1,11 +1,10 @@
 /// struct representing CSM API payload related to CFS configuration
 ///
-use std::path::PathBuf;
+use std::{error::Error, path::PathBuf};
 
 use serde::{Deserialize, Serialize};
-use substring::Substring;
 
-use crate::common::{gitea, local_git_repo};
+use super::cfs_configuration_request::{CfsConfigurationRequest, SpecialParameters};
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)] // TODO: investigate why serde can Deserialize dynamically syzed structs `Vec<Layer>`
 pub struct Layer {
@@ -17,6 +16,8 @@ pub struct Layer {
     pub playbook: String,
     #[serde(skip_serializing_if = "Option::is_none")] // Either commit or branch is passed
     pub branch: Option<String>,
+    #[serde(rename = "specialParameters", skip_serializing_if = "Option::is_none")]
+    pub special_parameters: Option<SpecialParameters>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)] // TODO: investigate why serde can Deserialize dynamically syzed structs `Vec<Layer>`
@@ -54,6 +55,7 @@ impl Layer {
             name,
             playbook,
             branch,
+            special_parameters: None,
         }
     }
 }
@@ -151,92 +153,53 @@ impl CfsConfigurationResponse {
         cfs_configuration
     }
 
+    /// Same as `CfsConfigurationRequest::create_from_repos`
     pub async fn create_from_repos(
         gitea_token: &str,
         gitea_base_url: &str,
+        vcs_clone_base_url: &str,
         shasta_root_cert: &[u8],
         repos: Vec<PathBuf>,
-        cfs_configuration_name: &String,
-    ) -> Self {
-        // Create CFS configuration
-        let mut cfs_configuration = CfsConfigurationResponse::new();
-        cfs_configuration.name = cfs_configuration_name.to_string();
-
-        for repo_path in &repos {
-            // Get repo from path
-            let repo = match local_git_repo::get_repo(&repo_path.to_string_lossy()) {
-                Ok(repo) => repo,
-                Err(_) => {
-                    eprintln!(
-                        "Could not find a git repo in {}",
-                        repo_path.to_string_lossy()
-                    );
-                    std::process::exit(1);
-                }
-            };
-
-            // Get last (most recent) commit
-            let local_last_commit = local_git_repo::get_last_commit(&repo).unwrap();
-
-            // Get repo name
-            let repo_ref_origin = repo.find_remote("origin").unwrap();
-
-            log::info!("Repo ref origin URL: {}", repo_ref_origin.url().unwrap());
-
-            let repo_ref_origin_url = repo_ref_origin.url().unwrap();
-
-            let repo_name = repo_ref_origin_url.substring(
-                repo_ref_origin_url.rfind(|c| c == '/').unwrap() + 1, // repo name should not include URI '/' separator
-                repo_ref_origin_url.len(), // repo_ref_origin_url.rfind(|c| c == '.').unwrap(),
-            );
+        cfs_configuration_name: &str,
+    ) -> Result<Self, Box<dyn Error>> {
+        let cfs_configuration_request = CfsConfigurationRequest::create_from_repos(
+            gitea_token,
+            gitea_base_url,
+            vcs_clone_base_url,
+            shasta_root_cert,
+            repos,
+            cfs_configuration_name,
+        )
+        .await?;
 
-            let api_url = "cray/".to_owned() + repo_name;
-
-            // Check if repo and local commit id exists in Shasta cvs
-            let shasta_commitid_details_resp = gitea::http_client::get_commit_details(
-                &api_url,
-                // &format!("/cray/{}", repo_name),
-                &local_last_commit.id().to_string(),
-                gitea_token,
-                shasta_root_cert,
-            )
-            .await;
-
-            // Check sync status between user face and shasta VCS
-            let shasta_commitid_details: serde_json::Value = match shasta_commitid_details_resp {
-                Ok(_) => {
-                    log::debug!(
-                        "Local latest commit id {} for repo {} exists in shasta",
-                        local_last_commit.id(),
-                        repo_name
-                    );
-                    shasta_commitid_details_resp.unwrap()
-                }
-                Err(e) => {
-                    eprintln!("{}", e);
-                    std::process::exit(1);
-                }
-            };
-
-            let clone_url = gitea_base_url.to_owned() + "/cray/" + repo_name;
-
-            // Create CFS layer
-            let cfs_layer = Layer::new(
-                clone_url,
-                Some(shasta_commitid_details["sha"].as_str().unwrap().to_string()),
-                format!(
-                    "{}-{}",
-                    repo_name.substring(0, repo_name.len()),
-                    chrono::offset::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
-                ),
-                String::from("site.yml"),
-                None,
+        let mut cfs_configuration = CfsConfigurationResponse::new();
+        cfs_configuration.name = cfs_configuration_request.name;
+
+        for layer in cfs_configuration_request.layers {
+            let mut cfs_layer = Layer::new(
+                layer.clone_url,
+                layer.commit,
+                layer.name,
+                layer.playbook,
+                layer.branch,
             );
+            cfs_layer.special_parameters = layer.special_parameters;
 
-            CfsConfigurationResponse::add_layer(&mut cfs_configuration, cfs_layer);
+            cfs_configuration.add_layer(cfs_layer);
         }
 
-        cfs_configuration
+        cfs_configuration.additional_inventory = cfs_configuration_request
+            .additional_inventory
+            .map(|additional_inventory| {
+                AdditionalInventory::new(
+                    additional_inventory.clone_url,
+                    additional_inventory.commit,
+                    additional_inventory.name,
+                    additional_inventory.branch,
+                )
+            });
+
+        Ok(cfs_configuration)
     }
 }
 
@@ -518,23 +518,39 @@ pub mod mesa {
             pub target: Target,
             #[serde(skip_serializing_if = "Option::is_none")]
             pub tags: Option<HashMap<String, String>>,
+            /// CFS v3 only. Keeps the session pod after a failure to debug it
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub debug_on_failure: Option<bool>,
         }
 
-        #[derive(Debug, Serialize, Deserialize, Clone)]
+        #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
         pub struct Group {
             pub name: String,
             pub members: Vec<String>,
         }
 
-        #[derive(Debug, Serialize, Deserialize, Clone, Default)]
+        /// CFS v3 only. Name of the image built from a base image
+        #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+        pub struct ImageMap {
+            pub source_id: String,
+            pub result_name: String,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
         pub struct Target {
             #[serde(skip_serializing_if = "Option::is_none")]
             pub definition: Option<String>,
             #[serde(skip_serializing_if = "Option::is_none")]
             pub groups: Option<Vec<Group>>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub image_map: Option<Vec<ImageMap>>,
         }
 
         impl CfsSessionPostRequest {
+            #[deprecated(
+                note = "use CfsSessionPostRequest::dynamic or CfsSessionPostRequest::image builders"
+            )]
+            #[allow(clippy::too_many_arguments)]
             pub fn new(
                 name: String,
                 configuration_name: String,
@@ -545,55 +561,400 @@ pub mod mesa {
                 groups_name: Option<Vec<String>>,
                 base_image_id: Option<String>,
             ) -> Self {
-                // This code is fine... the fact that I put Self behind a variable is ok, since image param
-                // is not a default param, then doing things differently is not an issue. I checked with
-                // other Rust developers in their discord https://discord.com/channels/442252698964721669/448238009733742612/1081686300182188207
-                let mut cfs_session = Self {
-                    name,
-                    configuration_name,
-                    ansible_limit,
-                    ansible_verbosity,
-                    ansible_passthrough,
-                    ..Default::default()
+                let cfs_session_builder = if is_target_definition_image {
+                    Self::image(
+                        &name,
+                        &configuration_name,
+                        &base_image_id.unwrap_or_default(),
+                        &groups_name.unwrap_or_default(),
+                    )
+                } else {
+                    Self::dynamic(&name, &configuration_name)
                 };
 
-                if is_target_definition_image {
-                    let target_groups: Vec<Group> = groups_name
-                        .unwrap()
-                        .into_iter()
-                        .map(|group_name| Group {
-                            name: group_name,
-                            members: vec![base_image_id.as_ref().unwrap().to_string()],
-                        })
-                        .collect();
-
-                    cfs_session.target.definition = Some("image".to_string());
-                    cfs_session.target.groups = Some(target_groups);
-                }
+                let mut cfs_session = cfs_session_builder.cfs_session;
+                cfs_session.ansible_limit = ansible_limit;
+                cfs_session.ansible_verbosity = ansible_verbosity;
+                cfs_session.ansible_passthrough = ansible_passthrough;
 
                 cfs_session
             }
 
-            pub fn from_sat_file_serde_yaml(session_yaml: &serde_yaml::Value) -> Self {
-                let groups_name = session_yaml["configuration_group_names"]
-                    .as_sequence()
-                    .unwrap()
+            /// Builder for a session configuring running nodes
+            pub fn dynamic(name: &str, configuration_name: &str) -> CfsSessionPostRequestBuilder {
+                CfsSessionPostRequestBuilder::new(Self {
+                    name: name.to_string(),
+                    configuration_name: configuration_name.to_string(),
+                    target: Target {
+                        definition: Some("dynamic".to_string()),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+            }
+
+            /// Builder for a session creating an image by configuring a base image. Groups are
+            /// the HSM groups whose ansible variables are applied to the image
+            pub fn image(
+                name: &str,
+                configuration_name: &str,
+                base_image_id: &str,
+                group_name_vec: &[String],
+            ) -> CfsSessionPostRequestBuilder {
+                let group_vec = group_name_vec
                     .iter()
-                    .map(|group_name| group_name.as_str().unwrap().to_string())
+                    .map(|group_name| Group {
+                        name: group_name.to_string(),
+                        members: vec![base_image_id.to_string()],
+                    })
                     .collect();
 
-                let cfs_session = CfsSessionPostRequest::new(
-                    session_yaml["name"].as_str().unwrap().to_string(),
-                    session_yaml["configuration"].as_str().unwrap().to_string(),
-                    None,
-                    None,
-                    None,
-                    true,
-                    Some(groups_name),
-                    // Some(base_image_id.to_string()),
-                    Some(session_yaml["ims"]["id"].as_str().unwrap().to_string()),
-                );
-                cfs_session
+                CfsSessionPostRequestBuilder::new(Self {
+                    name: name.to_string(),
+                    configuration_name: configuration_name.to_string(),
+                    target: Target {
+                        definition: Some("image".to_string()),
+                        groups: Some(group_vec),
+                        image_map: None,
+                    },
+                    ..Default::default()
+                })
+            }
+
+            pub fn is_target_def_image(&self) -> bool {
+                self.target.definition.as_deref() == Some("image")
+            }
+
+            /// Returns the base images of an image session
+            pub fn get_base_image_id_vec(&self) -> Vec<String> {
+                let mut base_image_id_vec: Vec<String> = self
+                    .target
+                    .groups
+                    .iter()
+                    .flatten()
+                    .flat_map(|group| group.members.clone())
+                    .collect();
+
+                base_image_id_vec.sort();
+                base_image_id_vec.dedup();
+
+                base_image_id_vec
+            }
+
+            pub fn from_sat_file_serde_yaml(
+                session_yaml: &serde_yaml::Value,
+            ) -> Result<Self, ApiError> {
+                let groups_name: Vec<String> = session_yaml["configuration_group_names"]
+                    .as_sequence()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|group_name| group_name.as_str())
+                    .map(str::to_string)
+                    .collect();
+
+                CfsSessionPostRequest::image(
+                    session_yaml["name"].as_str().unwrap_or_default(),
+                    session_yaml["configuration"].as_str().unwrap_or_default(),
+                    session_yaml["ims"]["id"].as_str().unwrap_or_default(),
+                    &groups_name,
+                )
+                .build()
+            }
+        }
+
+        /// Builds CFS sessions, use `CfsSessionPostRequest::dynamic` or
+        /// `CfsSessionPostRequest::image` to create it
+        #[derive(Debug, Clone)]
+        pub struct CfsSessionPostRequestBuilder {
+            cfs_session: CfsSessionPostRequest,
+            xname_vec: Vec<String>,
+            hsm_group_name_vec: Vec<String>,
+        }
+
+        impl CfsSessionPostRequestBuilder {
+            fn new(cfs_session: CfsSessionPostRequest) -> Self {
+                Self {
+                    cfs_session,
+                    xname_vec: Vec::new(),
+                    hsm_group_name_vec: Vec::new(),
+                }
+            }
+
+            /// Nodes to configure (dynamic sessions). NIDs and aliases are accepted too, they
+            /// are translated to xnames by `build_and_validate`
+            pub fn xnames(mut self, xname_vec: &[String]) -> Self {
+                self.xname_vec.extend_from_slice(xname_vec);
+                self
+            }
+
+            /// HSM groups to configure (dynamic sessions)
+            pub fn hsm_groups(mut self, hsm_group_name_vec: &[String]) -> Self {
+                self.hsm_group_name_vec
+                    .extend_from_slice(hsm_group_name_vec);
+                self
+            }
+
+            /// Runs only the configuration layers in the list of layer names or indexes
+            pub fn configuration_limit(mut self, configuration_limit: &str) -> Self {
+                self.cfs_session.configuration_limit = Some(configuration_limit.to_string());
+                self
+            }
+
+            /// Name of the k8s configmap with the ansible configuration
+            pub fn ansible_config(mut self, ansible_config: &str) -> Self {
+                self.cfs_session.ansible_config = Some(ansible_config.to_string());
+                self
+            }
+
+            pub fn ansible_verbosity(mut self, ansible_verbosity: u8) -> Self {
+                self.cfs_session.ansible_verbosity = Some(ansible_verbosity);
+                self
+            }
+
+            pub fn ansible_passthrough(mut self, ansible_passthrough: &str) -> Self {
+                self.cfs_session.ansible_passthrough = Some(ansible_passthrough.to_string());
+                self
+            }
+
+            pub fn tag(mut self, key: &str, value: &str) -> Self {
+                self.cfs_session
+                    .tags
+                    .get_or_insert_with(HashMap::new)
+                    .insert(key.to_string(), value.to_string());
+                self
+            }
+
+            /// CFS v3 only
+            pub fn debug_on_failure(mut self, debug_on_failure: bool) -> Self {
+                self.cfs_session.debug_on_failure = Some(debug_on_failure);
+                self
+            }
+
+            /// CFS v3 only. Name of the image created from a base image (image sessions)
+            pub fn image_map(mut self, source_id: &str, result_name: &str) -> Self {
+                self.cfs_session
+                    .target
+                    .image_map
+                    .get_or_insert_with(Vec::new)
+                    .push(ImageMap {
+                        source_id: source_id.to_string(),
+                        result_name: result_name.to_string(),
+                    });
+                self
+            }
+
+            /// Checks the session is consistent and returns it. Nothing is checked against CSM
+            pub fn build(self) -> Result<CfsSessionPostRequest, ApiError> {
+                let mut cfs_session = self.cfs_session;
+
+                validate_cfs_session_name(&cfs_session.name).map_err(ApiError::MesaError)?;
+
+                if cfs_session.configuration_name.is_empty() {
+                    return Err(ApiError::MesaError(
+                        "CFS session configuration name missing".to_string(),
+                    ));
+                }
+
+                if cfs_session
+                    .ansible_verbosity
+                    .is_some_and(|verbosity| verbosity > 4)
+                {
+                    return Err(ApiError::MesaError(
+                        "Ansible verbosity must be between 0 and 4".to_string(),
+                    ));
+                }
+
+                if cfs_session.is_target_def_image() {
+                    let base_image_id_vec = cfs_session.get_base_image_id_vec();
+
+                    if base_image_id_vec.is_empty() || base_image_id_vec.contains(&String::new()) {
+                        return Err(ApiError::MesaError(
+                            "Image CFS session needs a base image and at least one group"
+                                .to_string(),
+                        ));
+                    }
+
+                    if !self.xname_vec.is_empty() || !self.hsm_group_name_vec.is_empty() {
+                        return Err(ApiError::MesaError(
+                            "Image CFS session can't target nodes or HSM groups".to_string(),
+                        ));
+                    }
+
+                    if let Some(image_map) =
+                        cfs_session
+                            .target
+                            .image_map
+                            .as_ref()
+                            .and_then(|image_map_vec| {
+                                image_map_vec.iter().find(|image_map| {
+                                    !base_image_id_vec.contains(&image_map.source_id)
+                                })
+                            })
+                    {
+                        return Err(ApiError::MesaError(format!(
+                            "Image map source '{}' is not a base image of the CFS session",
+                            image_map.source_id
+                        )));
+                    }
+                } else {
+                    if cfs_session.target.image_map.is_some() {
+                        return Err(ApiError::MesaError(
+                            "Image map is only valid for image CFS sessions".to_string(),
+                        ));
+                    }
+
+                    let ansible_limit_vec: Vec<String> = self
+                        .xname_vec
+                        .iter()
+                        .chain(self.hsm_group_name_vec.iter())
+                        .cloned()
+                        .collect();
+
+                    if !ansible_limit_vec.is_empty() {
+                        cfs_session.ansible_limit = Some(ansible_limit_vec.join(","));
+                    }
+                }
+
+                Ok(cfs_session)
+            }
+
+            /// Builds the session and checks the configuration, target nodes, HSM groups and
+            /// base image exist, so errors show up before CFS rejects the session
+            pub async fn build_and_validate(
+                mut self,
+                shasta_token: &str,
+                shasta_base_url: &str,
+                shasta_root_cert: &[u8],
+            ) -> Result<CfsSessionPostRequest, ApiError> {
+                // Accept NIDs and aliases too
+                self.xname_vec = crate::node::resolver::resolve_xname_vec(
+                    shasta_token,
+                    shasta_base_url,
+                    shasta_root_cert,
+                    &self.xname_vec,
+                )
+                .await
+                .map_err(|error| ApiError::MesaError(error.to_string()))?;
+
+                let xname_vec = self.xname_vec.clone();
+                let hsm_group_name_vec = self.hsm_group_name_vec.clone();
+
+                let cfs_session = self.build()?;
+
+                crate::cfs::configuration::shasta::http_client::get(
+                    shasta_token,
+                    shasta_base_url,
+                    shasta_root_cert,
+                    Some(&cfs_session.configuration_name),
+                )
+                .await
+                .map_err(|error| {
+                    get_not_found_api_error(
+                        error,
+                        format!(
+                            "CFS configuration '{}' not found",
+                            cfs_session.configuration_name
+                        ),
+                    )
+                })?;
+
+                if !xname_vec.is_empty() {
+                    let hsm_component_status_value =
+                        crate::hsm::component_status::shasta::http_client::get(
+                            shasta_token,
+                            shasta_base_url,
+                            shasta_root_cert,
+                            &xname_vec,
+                        )
+                        .await
+                        .map_err(|error| ApiError::CsmError(error.to_string()))?;
+
+                    let hsm_state_map =
+                        crate::hsm::component_status::shasta::utils::get_hsm_state_map(
+                            &hsm_component_status_value,
+                        );
+
+                    let missing_xname_vec: Vec<&String> = xname_vec
+                        .iter()
+                        .filter(|xname| !hsm_state_map.contains_key(*xname))
+                        .collect();
+
+                    if !missing_xname_vec.is_empty() {
+                        return Err(ApiError::MesaError(format!(
+                            "Nodes {:?} not found in HSM",
+                            missing_xname_vec
+                        )));
+                    }
+                }
+
+                // Groups of image sessions are ansible inventory groups (often HSM roles), only
+                // the HSM groups targeted by dynamic sessions are checked
+                if cfs_session.is_target_def_image() {
+                    for base_image_id in cfs_session.get_base_image_id_vec() {
+                        crate::ims::image::shasta::http_client::get_raw(
+                            shasta_token,
+                            shasta_base_url,
+                            shasta_root_cert,
+                            Some(&base_image_id),
+                        )
+                        .await
+                        .map_err(|error| {
+                            get_not_found_api_error(
+                                error,
+                                format!("Base image '{}' not found in IMS", base_image_id),
+                            )
+                        })?;
+                    }
+                }
+
+                for hsm_group_name in &hsm_group_name_vec {
+                    crate::hsm::group::shasta::http_client::get_raw(
+                        shasta_token,
+                        shasta_base_url,
+                        shasta_root_cert,
+                        Some(hsm_group_name),
+                    )
+                    .await
+                    .map_err(|error| {
+                        get_not_found_api_error(
+                            error,
+                            format!("HSM group '{}' not found", hsm_group_name),
+                        )
+                    })?;
+                }
+
+                Ok(cfs_session)
+            }
+        }
+
+        /// Returns `not_found_message` if CSM answered 404, otherwise the CSM error. Avoids
+        /// reporting auth or network errors as missing resources
+        fn get_not_found_api_error(error: reqwest::Error, not_found_message: String) -> ApiError {
+            if error.status() == Some(reqwest::StatusCode::NOT_FOUND) {
+                ApiError::MesaError(not_found_message)
+            } else {
+                ApiError::CsmError(error.to_string())
+            }
+        }
+
+        /// CFS session names are k8s names, lowercase alphanumeric characters or '-', starting
+        /// and ending with an alphanumeric character and at most 45 characters long
+        pub fn validate_cfs_session_name(name: &str) -> Result<(), String> {
+            let is_valid = !name.is_empty()
+                && name.len() <= 45
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+                && !name.starts_with('-')
+                && !name.ends_with('-');
+
+            if is_valid {
+                Ok(())
+            } else {
+                Err(format!(
+                    "CFS session name '{}' not valid. Only lowercase alphanumeric characters and '-' allowed, max 45 characters",
+                    name
+                ))
             }
         }
 
@@ -1080,7 +1441,7 @@ pub mod mesa {
 
 #[cfg(test)]
 pub mod test {
-    use crate::cfs::session::mesa::r#struct::CfsSessionGetResponse;
+    use crate::cfs::session::mesa::r#struct::{CfsSessionGetResponse, CfsSessionPostRequest};
 
     #[tokio::test]
     async fn test_cfs_session_serde_json_to_struct_conversion() {
@@ -1119,4 +1480,50 @@ pub mod test {
 
         println!("{:#?}", cfs_session);
     }
+
+    #[test]
+    fn test_cfs_session_post_request_builder() {
+        let cfs_session = CfsSessionPostRequest::dynamic("batcher-test", "compute-config")
+            .xnames(&["x1005c1s2b0n0".to_string()])
+            .hsm_groups(&["zinal".to_string()])
+            .ansible_verbosity(2)
+            .tag("owner", "mesa")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            cfs_session.ansible_limit,
+            Some("x1005c1s2b0n0,zinal".to_string())
+        );
+        assert!(!cfs_session.is_target_def_image());
+
+        let cfs_session = CfsSessionPostRequest::image(
+            "image-test",
+            "compute-config",
+            "base-image-id",
+            &["zinal".to_string()],
+        )
+        .image_map("base-image-id", "compute-image")
+        .build()
+        .unwrap();
+
+        assert_eq!(cfs_session.get_base_image_id_vec(), vec!["base-image-id"]);
+
+        assert!(
+            CfsSessionPostRequest::dynamic("Invalid_Name", "compute-config")
+                .build()
+                .is_err()
+        );
+        assert!(
+            CfsSessionPostRequest::image("image-test", "compute-config", "base-image-id", &[])
+                .build()
+                .is_err()
+        );
+        assert!(
+            CfsSessionPostRequest::dynamic("batcher-test", "compute-config")
+                .image_map("base-image-id", "compute-image")
+                .build()
+                .is_err()
+        );
+    }
 }
@@ -1,6 +1,11 @@
+pub mod traits_structs;
+
 use std::{collections::HashMap, error::Error};
 
-use crate::hsm;
+use crate::{
+    common::power_ops::{self, PowerAction, PowerConfig, PowerReport},
+    hsm,
+};
 
 pub struct VCluster {
     pub name: String,
@@ -15,26 +20,29 @@ impl VCluster {
         hsm_group_name: &str,
         reason: Option<String>,
         force: bool,
-    ) -> Result<(), Box<dyn Error>> {
-        let hsm_group_node_list = hsm::group::shasta::utils::get_member_vec_from_hsm_group_name(
-            shasta_token,
-            shasta_base_url,
-            shasta_root_cert,
-            hsm_group_name,
-        )
-        .await;
-
-        let _ = crate::capmc::http_client::node_power_off::post_sync(
+    ) -> Result<PowerReport, Box<dyn Error>> {
+        let hsm_group_node_list =
+            hsm::group::shasta::utils::try_get_member_vec_from_hsm_group_name(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                hsm_group_name,
+            )
+            .await?;
+
+        power_ops::power(
             shasta_token,
             shasta_base_url,
             shasta_root_cert,
-            hsm_group_node_list,
-            reason,
-            force,
+            PowerAction::Off,
+            &hsm_group_node_list,
+            &PowerConfig {
+                reason_opt: reason,
+                force,
+                ..Default::default()
+            },
         )
-        .await;
-
-        Ok(())
+        .await
     }
 
     pub async fn power_on(
@@ -43,25 +51,28 @@ impl VCluster {
         shasta_root_cert: &[u8],
         hsm_group_name: &str,
         reason: Option<String>,
-    ) -> Result<(), Box<dyn Error>> {
-        let hsm_group_node_list = hsm::group::shasta::utils::get_member_vec_from_hsm_group_name(
+    ) -> Result<PowerReport, Box<dyn Error>> {
+        let hsm_group_node_list =
+            hsm::group::shasta::utils::try_get_member_vec_from_hsm_group_name(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                hsm_group_name,
+            )
+            .await?;
+
+        power_ops::power(
             shasta_token,
             shasta_base_url,
             shasta_root_cert,
-            hsm_group_name,
-        )
-        .await;
-
-        let _ = crate::capmc::http_client::node_power_on::post_sync(
-            shasta_token,
-            shasta_base_url,
-            shasta_root_cert,
-            hsm_group_node_list,
-            reason,
+            PowerAction::On,
+            &hsm_group_node_list,
+            &PowerConfig {
+                reason_opt: reason,
+                ..Default::default()
+            },
         )
-        .await;
-
-        Ok(())
+        .await
     }
 
     pub async fn power_reset(
@@ -71,26 +82,29 @@ impl VCluster {
         hsm_group_name: &str,
         reason: Option<String>,
         force: bool,
-    ) -> Result<(), Box<dyn Error>> {
-        let hsm_group_node_list = hsm::group::shasta::utils::get_member_vec_from_hsm_group_name(
-            shasta_token,
-            shasta_base_url,
-            shasta_root_cert,
-            hsm_group_name,
-        )
-        .await;
-
-        let _ = crate::capmc::http_client::node_power_reset::post_sync(
+    ) -> Result<PowerReport, Box<dyn Error>> {
+        let hsm_group_node_list =
+            hsm::group::shasta::utils::try_get_member_vec_from_hsm_group_name(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                hsm_group_name,
+            )
+            .await?;
+
+        power_ops::power(
             shasta_token,
             shasta_base_url,
             shasta_root_cert,
-            hsm_group_node_list,
-            reason,
-            force,
+            PowerAction::Reset,
+            &hsm_group_node_list,
+            &PowerConfig {
+                reason_opt: reason,
+                force,
+                ..Default::default()
+            },
         )
-        .await;
-
-        Ok(())
+        .await
     }
 
     /// Returns a map with the xnames and the cfs configuration used to boot image
@@ -100,13 +114,15 @@ impl VCluster {
         shasta_root_cert: &[u8],
         hsm_group_name: &str,
     ) -> Option<HashMap<String, String>> {
-        let hsm_group_node_list = hsm::group::shasta::utils::get_member_vec_from_hsm_group_name(
-            shasta_token,
-            shasta_base_url,
-            shasta_root_cert,
-            hsm_group_name,
-        )
-        .await;
+        let hsm_group_node_list =
+            hsm::group::shasta::utils::try_get_member_vec_from_hsm_group_name(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                hsm_group_name,
+            )
+            .await
+            .ok()?;
 
         let hsm_group_node_boot_param_vec = crate::bss::http_client::get_boot_params(
             shasta_token,
@@ -1,26 +1,153 @@
-pub trait Cluster {
-    /// Shuts down all nodes of a cluster
-    pub fn power_off() -> Result<(), dyn Err>;
-    /// Start all nodes of a cluster
-    pub fn power_on() -> Result<(), dyn Err>;
-    /// Restarts all nodes of a cluster
-    pub fn reset() -> Result<(), dyn Err>;
-    /// Get power state for all nodes in a cluster
-    pub fn get_power_state() -> Resutl<(), dyn Err>;
-    /// Get all CFS configuration related to each node of a cluster
-    pub fn get_boot_config() -> Option<Vec<(String, String)>>;
-    /// Get CFS configurations related to each node of a cluster
-    pub fn get_desired_config() -> Option<Vec<(String, String)>>;
-    /// Get overall cluster status (OFF, BOOTING, CONFIGURING, STANDBY)
-    pub fn get_status() -> Result<String, dyn Err>;
-    /// Get cluster details
-    pub fn get_details() -> Result<Value, dyn Err>;
-    /// Migrate cluster
-    pub fn migrate() -> Result<(), dyn Err>;
+use std::error::Error;
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::{
+    common::{
+        cluster_migration_ops::{self, MigrationConfig},
+        power_ops::{self, PowerAction, PowerConfig, PowerReport},
+    },
+    node::{
+        r#struct::{CsmClient, NodeStatus},
+        utils,
+    },
+};
+
+pub use crate::node::traits::Cluster;
+
+/// Cluster (HSM group) managed through CSM
+#[derive(Debug, Clone)]
+pub struct CsmCluster {
+    pub csm_client: CsmClient,
+    pub hsm_group_name: String,
+}
+
+impl CsmCluster {
+    pub fn new(csm_client: CsmClient, hsm_group_name: &str) -> Self {
+        Self {
+            csm_client,
+            hsm_group_name: hsm_group_name.to_string(),
+        }
+    }
+
+    /// Returns the nodes in the HSM group
+    pub async fn get_member_vec(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        crate::hsm::group::shasta::utils::try_get_member_vec_from_hsm_group_name(
+            &self.csm_client.shasta_token,
+            &self.csm_client.shasta_base_url,
+            &self.csm_client.shasta_root_cert,
+            &self.hsm_group_name,
+        )
+        .await
+    }
+
+    async fn power(&self, action: PowerAction, force: bool) -> Result<PowerReport, Box<dyn Error>> {
+        power_ops::power(
+            &self.csm_client.shasta_token,
+            &self.csm_client.shasta_base_url,
+            &self.csm_client.shasta_root_cert,
+            action,
+            &self.get_member_vec().await?,
+            &PowerConfig {
+                force,
+                ..Default::default()
+            },
+        )
+        .await
+    }
 }
 
-pub struct VCluster {
+#[async_trait(?Send)]
+impl Cluster for CsmCluster {
+    fn get_hsm_group_name(&self) -> &str {
+        &self.hsm_group_name
+    }
+
+    async fn power_off(&self, force: bool) -> Result<PowerReport, Box<dyn Error>> {
+        self.power(PowerAction::Off, force).await
+    }
+
+    async fn power_on(&self) -> Result<PowerReport, Box<dyn Error>> {
+        self.power(PowerAction::On, false).await
+    }
+
+    async fn reset(&self, force: bool) -> Result<PowerReport, Box<dyn Error>> {
+        self.power(PowerAction::Reset, force).await
+    }
+
+    async fn get_power_state(&self) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+        utils::get_power_state_vec(
+            &self.csm_client.shasta_token,
+            &self.csm_client.shasta_base_url,
+            &self.csm_client.shasta_root_cert,
+            &self.get_member_vec().await?,
+        )
+        .await
+    }
+
+    async fn get_boot_config(&self) -> Result<Vec<(String, Option<String>)>, Box<dyn Error>> {
+        utils::get_boot_config_vec(
+            &self.csm_client.shasta_token,
+            &self.csm_client.shasta_base_url,
+            &self.csm_client.shasta_root_cert,
+            &self.get_member_vec().await?,
+        )
+        .await
+    }
+
+    async fn get_desired_config(&self) -> Result<Vec<(String, Option<String>)>, Box<dyn Error>> {
+        utils::get_desired_config_vec(
+            &self.csm_client.shasta_token,
+            &self.csm_client.shasta_base_url,
+            &self.csm_client.shasta_root_cert,
+            &self.get_member_vec().await?,
+        )
+        .await
+    }
+
+    async fn get_status(&self) -> Result<NodeStatus, Box<dyn Error>> {
+        let node_status_vec: Vec<NodeStatus> = utils::get_status_vec(
+            &self.csm_client.shasta_token,
+            &self.csm_client.shasta_base_url,
+            &self.csm_client.shasta_root_cert,
+            &self.get_member_vec().await?,
+        )
+        .await?
+        .into_iter()
+        .map(|(_, node_status)| node_status)
+        .collect();
+
+        Ok(NodeStatus::aggregate(&node_status_vec))
+    }
+
+    async fn get_details(&self) -> Result<Value, Box<dyn Error>> {
+        let node_details_vec = utils::get_node_details(
+            &self.csm_client.shasta_token,
+            &self.csm_client.shasta_base_url,
+            &self.csm_client.shasta_root_cert,
+            self.get_member_vec().await?,
+        )
+        .await;
+
+        Ok(serde_json::json!({
+            "hsm_group_name": self.hsm_group_name,
+            "status": self.get_status().await?,
+            "nodes": node_details_vec,
+        }))
+    }
+
+    async fn migrate(&self, pool_hsm_group_name: &str) -> Result<(), Box<dyn Error>> {
+        cluster_migration_ops::migrate(
+            &self.csm_client.shasta_token,
+            &self.csm_client.shasta_base_url,
+            &self.csm_client.shasta_root_cert,
+            &self.hsm_group_name,
+            pool_hsm_group_name,
+            &MigrationConfig::default(),
+        )
+        .await?;
 
-    pub name: String,
-    pub description: String,
+        Ok(())
+    }
 }
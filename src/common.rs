@@ -1,8 +1,16 @@
 pub mod authentication;
+pub mod boot_image_ops;
+pub mod cluster_migration_ops;
 pub mod cluster_ops;
 pub mod gitea;
+pub mod image_gc_ops;
+pub mod image_lineage_ops;
+pub mod journal;
 pub mod jwt_ops;
 pub mod kubernetes;
 pub mod local_git_repo;
 pub mod log_ops;
+pub mod power_ops;
+pub mod rolling_reboot_ops;
+pub mod secret_provider;
 pub mod vault;
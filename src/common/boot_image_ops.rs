@@ -0,0 +1,299 @@
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bss::{self, utils::BootParametersChange},
+    common::power_ops::{self, PowerAction, PowerConfig},
+    hsm, ims, node,
+};
+
+/// Boot artifacts an image needs to be able to boot a node
+pub const BOOT_ARTIFACT_NAME_VEC: [&str; 3] = ["kernel", "initrd", "rootfs"];
+
+pub const BOOT_IMAGE_BUCKET: &str = "boot-images";
+
+/// Record of a boot image switch. Contains the boot parameters before and after the switch so
+/// the operation can be rolled back
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BootImageSwitch {
+    pub image_id: String,
+    pub created: String,
+    pub boot_parameters_change_vec: Vec<BootParametersChange>,
+}
+
+impl BootImageSwitch {
+    /// Returns a list of tuples like (xnames, image id) with the image used by the nodes before
+    /// the switch
+    pub fn get_previous_image_id_vec(&self) -> Vec<(Vec<String>, Option<String>)> {
+        self.boot_parameters_change_vec
+            .iter()
+            .map(|boot_parameters_change| {
+                (
+                    boot_parameters_change.current.hosts.clone(),
+                    boot_parameters_change.current.get_boot_image_id(),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the list of nodes affected by the switch
+    pub fn get_xname_vec(&self) -> Vec<String> {
+        self.boot_parameters_change_vec
+            .iter()
+            .flat_map(|boot_parameters_change| boot_parameters_change.new.hosts.clone())
+            .collect()
+    }
+}
+
+/// Checks the image exists in IMS and its kernel, initrd and rootfs are in S3.
+/// Returns the rootfs etag (needed by the 'root' kernel parameter)
+pub async fn validate_boot_image(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    image_id: &str,
+) -> Result<String, Box<dyn Error>> {
+    if ims::image::mesa::http_client::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        Some(image_id),
+    )
+    .await
+    .is_err()
+    {
+        return Err(format!("Image '{}' not found in IMS", image_id).into());
+    }
+
+    let sts_value = ims::s3::s3_auth(shasta_token, shasta_base_url, shasta_root_cert).await?;
+
+    let mut rootfs_etag = String::new();
+
+    for artifact_name in BOOT_ARTIFACT_NAME_VEC {
+        let object_path = format!("{}/{}", image_id, artifact_name);
+
+        let head_object_output =
+            ims::s3::s3_head_object(&sts_value, &object_path, BOOT_IMAGE_BUCKET).await?;
+
+        if head_object_output.content_length().unwrap_or_default() <= 0 {
+            return Err(format!(
+                "Artifact '{}' for image '{}' is empty",
+                artifact_name, image_id
+            )
+            .into());
+        }
+
+        if artifact_name == "rootfs" {
+            rootfs_etag = head_object_output
+                .e_tag()
+                .unwrap_or_default()
+                .trim_matches('"')
+                .to_string();
+        }
+    }
+
+    Ok(rootfs_etag)
+}
+
+/// Changes the image a list of nodes boot. This will:
+///  - validate the image (see `validate_boot_image`)
+///  - rewrite kernel, initrd and rootfs references in BSS
+///  - reboot the nodes in batches of `reboot_batch_size_opt` nodes, if provided
+///
+/// Fails without changing anything if some nodes do not boot an IMS image.
+/// If `dry_run` is true, nothing is changed in CSM and the returned value contains the changes
+/// that would be applied.
+/// The value returned can be stored and used later by `rollback_boot_image_switch`
+pub async fn switch_boot_image(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    image_id: &str,
+    xname_vec: &[String],
+    reboot_batch_size_opt: Option<usize>,
+    dry_run: bool,
+) -> Result<BootImageSwitch, Box<dyn Error>> {
+    // Accept NIDs and aliases too
+    let xname_vec = &node::resolver::resolve_xname_vec(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+    )
+    .await?;
+
+    let rootfs_etag =
+        validate_boot_image(shasta_token, shasta_base_url, shasta_root_cert, image_id).await?;
+
+    let mut not_ims_xname_vec: Vec<String> = Vec::new();
+
+    let boot_parameters_change_vec = bss::utils::plan_boot_parameters_change(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+        |boot_parameters| {
+            if boot_parameters.update_boot_image(image_id) {
+                boot_parameters.update_rootfs_etag(&rootfs_etag);
+            } else {
+                log::error!(
+                    "Nodes {:?} do not boot an IMS image (kernel '{}')",
+                    boot_parameters.hosts,
+                    boot_parameters.kernel
+                );
+                not_ims_xname_vec.extend(boot_parameters.hosts.iter().cloned());
+            }
+        },
+    )
+    .await?;
+
+    if !not_ims_xname_vec.is_empty() {
+        return Err(format!(
+            "Nodes {:?} do not boot an IMS image, can't switch their boot image",
+            not_ims_xname_vec
+        )
+        .into());
+    }
+
+    let boot_image_switch = BootImageSwitch {
+        image_id: image_id.to_string(),
+        created: chrono::Utc::now().to_rfc3339(),
+        boot_parameters_change_vec,
+    };
+
+    if dry_run {
+        return Ok(boot_image_switch);
+    }
+
+    bss::utils::apply_boot_parameters_change(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &boot_image_switch.boot_parameters_change_vec,
+    )
+    .await?;
+
+    if let Some(reboot_batch_size) = reboot_batch_size_opt {
+        reboot_in_batches(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &boot_image_switch.get_xname_vec(),
+            reboot_batch_size,
+            Some(format!("Boot image switched to {}", image_id)),
+        )
+        .await?;
+    }
+
+    Ok(boot_image_switch)
+}
+
+/// Same as `switch_boot_image` but for all members of an HSM group
+pub async fn switch_boot_image_hsm_group(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    image_id: &str,
+    hsm_group_name: &str,
+    reboot_batch_size_opt: Option<usize>,
+    dry_run: bool,
+) -> Result<BootImageSwitch, Box<dyn Error>> {
+    let xname_vec = hsm::group::shasta::utils::try_get_member_vec_from_hsm_group_name(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        hsm_group_name,
+    )
+    .await?;
+
+    switch_boot_image(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        image_id,
+        &xname_vec,
+        reboot_batch_size_opt,
+        dry_run,
+    )
+    .await
+}
+
+/// Restores the boot parameters the nodes had before a boot image switch
+pub async fn rollback_boot_image_switch(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    boot_image_switch: &BootImageSwitch,
+    reboot_batch_size_opt: Option<usize>,
+) -> Result<(), Box<dyn Error>> {
+    let boot_parameters_change_vec: Vec<BootParametersChange> = boot_image_switch
+        .boot_parameters_change_vec
+        .iter()
+        .map(|boot_parameters_change| boot_parameters_change.reverse())
+        .collect();
+
+    bss::utils::apply_boot_parameters_change(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &boot_parameters_change_vec,
+    )
+    .await?;
+
+    if let Some(reboot_batch_size) = reboot_batch_size_opt {
+        reboot_in_batches(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &boot_image_switch.get_xname_vec(),
+            reboot_batch_size,
+            Some(format!(
+                "Rollback boot image switch to {}",
+                boot_image_switch.image_id
+            )),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Reboots a list of nodes, `batch_size` nodes at a time. Each batch must be back ON before
+/// rebooting the next one, stops at the first batch with nodes which failed to power OFF or ON
+async fn reboot_in_batches(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+    batch_size: usize,
+    reason_opt: Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    for (i, xname_batch) in xname_vec.chunks(batch_size.max(1)).enumerate() {
+        log::info!("Rebooting batch {}: {:?}", i + 1, xname_batch);
+
+        let power_report = power_ops::power(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            PowerAction::Reset,
+            xname_batch,
+            &PowerConfig {
+                reason_opt: reason_opt.clone(),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+        if !power_report.is_success() {
+            return Err(format!(
+                "Reboot of batch {} failed: {:?}",
+                i + 1,
+                power_report.get_failed_vec()
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,679 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    bss::{self, r#struct::BootParameters},
+    cfs::{self, component::shasta::r#struct::Component},
+    common::{
+        power_ops::{self, PowerAction, PowerConfig, PowerReport},
+        rolling_reboot_ops::{self, RebootMethod, RollingRebootConfig},
+    },
+    hsm,
+};
+
+/// Migration steps in the order they are run
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, PartialOrd)]
+pub enum MigrationStep {
+    NodesSelected,
+    BootParametersCopied,
+    DesiredConfigCopied,
+    /// New nodes booted and configured
+    NodesBooted,
+    MembershipMoved,
+    OldNodesPoweredOff,
+    Completed,
+    RolledBack,
+}
+
+/// Node replaced during a migration. Keeps the original values of the new node so the
+/// migration can be rolled back
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NodeMigration {
+    pub old_xname: String,
+    pub new_xname: String,
+    pub old_boot_parameters_opt: Option<BootParameters>,
+    pub old_desired_config_opt: Option<String>,
+    pub new_boot_parameters_backup_opt: Option<BootParameters>,
+    /// CFS desired configuration of the new node as found before the migration, empty if it had
+    /// none. Not set if the node was not a CFS component
+    pub new_desired_config_backup_opt: Option<String>,
+    #[serde(default)]
+    pub new_enabled_backup_opt: Option<bool>,
+    /// Old node was already a member of the pool before the migration
+    #[serde(default)]
+    pub old_xname_in_pool: bool,
+}
+
+/// Checkpoint of a cluster migration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ClusterMigration {
+    pub hsm_group_name: String,
+    pub pool_hsm_group_name: String,
+    pub created: String,
+    /// Last step completed
+    pub step: MigrationStep,
+    pub node_migration_vec: Vec<NodeMigration>,
+}
+
+impl ClusterMigration {
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn to_file(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        // Write to a temporary file first so a crash does not leave a truncated checkpoint
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(tmp_path, path)?;
+
+        Ok(())
+    }
+
+    pub fn get_old_xname_vec(&self) -> Vec<String> {
+        self.node_migration_vec
+            .iter()
+            .map(|node_migration| node_migration.old_xname.clone())
+            .collect()
+    }
+
+    pub fn get_new_xname_vec(&self) -> Vec<String> {
+        self.node_migration_vec
+            .iter()
+            .map(|node_migration| node_migration.new_xname.clone())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MigrationConfig {
+    /// File where the migration is checkpointed after each step. If the file exists, the
+    /// migration resumes from it
+    pub checkpoint_file_opt: Option<PathBuf>,
+    pub power_config: PowerConfig,
+    /// Time for the new nodes to be HSM 'Ready' and CFS 'configured'
+    pub health_timeout: Duration,
+    pub poll_interval: Duration,
+}
+
+impl Default for MigrationConfig {
+    fn default() -> Self {
+        Self {
+            checkpoint_file_opt: None,
+            power_config: PowerConfig::default(),
+            health_timeout: Duration::from_secs(3600),
+            poll_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Moves a cluster (HSM group) to nodes taken from a pool HSM group. The new nodes get the boot
+/// image, kernel params and CFS desired configuration of the nodes they replace, are booted and
+/// configured, then HSM membership is swapped and the old nodes are powered off and returned to
+/// the pool.
+/// On error, the checkpoint is left at the last step completed so the migration can be resumed
+/// by calling this function again or undone with `rollback_migration`
+pub async fn migrate(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    hsm_group_name: &str,
+    pool_hsm_group_name: &str,
+    migration_config: &MigrationConfig,
+) -> Result<ClusterMigration, Box<dyn Error>> {
+    let checkpoint_file_opt = migration_config.checkpoint_file_opt.as_deref();
+
+    let mut cluster_migration = match checkpoint_file_opt {
+        Some(checkpoint_file) if checkpoint_file.exists() => {
+            let cluster_migration = ClusterMigration::from_file(checkpoint_file)?;
+
+            if cluster_migration.hsm_group_name != hsm_group_name
+                || cluster_migration.pool_hsm_group_name != pool_hsm_group_name
+            {
+                return Err(format!(
+                    "Checkpoint '{}' belongs to migration of '{}' to '{}'",
+                    checkpoint_file.display(),
+                    cluster_migration.hsm_group_name,
+                    cluster_migration.pool_hsm_group_name
+                )
+                .into());
+            }
+
+            if cluster_migration.step >= MigrationStep::Completed {
+                return Ok(cluster_migration);
+            }
+
+            log::info!(
+                "Resuming migration of '{}' after step {:?}",
+                hsm_group_name,
+                cluster_migration.step
+            );
+
+            cluster_migration
+        }
+        _ => {
+            let cluster_migration = plan_migration(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                hsm_group_name,
+                pool_hsm_group_name,
+            )
+            .await?;
+
+            save_checkpoint(&cluster_migration, checkpoint_file_opt)?;
+
+            cluster_migration
+        }
+    };
+
+    if cluster_migration.step < MigrationStep::BootParametersCopied {
+        for node_migration in &cluster_migration.node_migration_vec {
+            let old_boot_parameters =
+                node_migration
+                    .old_boot_parameters_opt
+                    .as_ref()
+                    .ok_or_else(|| {
+                        format!("Node '{}' has no boot parameters", node_migration.old_xname)
+                    })?;
+
+            bss::http_client::put(
+                shasta_base_url,
+                shasta_token,
+                shasta_root_cert,
+                &vec![node_migration.new_xname.clone()],
+                &old_boot_parameters.params,
+                &old_boot_parameters.kernel,
+                &old_boot_parameters.initrd,
+            )
+            .await?;
+        }
+
+        cluster_migration.step = MigrationStep::BootParametersCopied;
+        save_checkpoint(&cluster_migration, checkpoint_file_opt)?;
+    }
+
+    if cluster_migration.step < MigrationStep::DesiredConfigCopied {
+        let component_vec: Vec<Component> = cluster_migration
+            .node_migration_vec
+            .iter()
+            .filter_map(|node_migration| {
+                node_migration
+                    .old_desired_config_opt
+                    .as_ref()
+                    .map(|desired_config| Component {
+                        id: Some(node_migration.new_xname.clone()),
+                        desired_config: Some(desired_config.clone()),
+                        enabled: Some(true),
+                        ..Default::default()
+                    })
+            })
+            .collect();
+
+        if !component_vec.is_empty() {
+            cfs::component::shasta::http_client::patch_component_list(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                component_vec,
+            )
+            .await?;
+        }
+
+        cluster_migration.step = MigrationStep::DesiredConfigCopied;
+        save_checkpoint(&cluster_migration, checkpoint_file_opt)?;
+    }
+
+    if cluster_migration.step < MigrationStep::NodesBooted {
+        let new_xname_vec = cluster_migration.get_new_xname_vec();
+
+        check_power_report(
+            power_ops::power(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                PowerAction::Reset,
+                &new_xname_vec,
+                &migration_config.power_config,
+            )
+            .await?,
+        )?;
+
+        let node_failure_vec = rolling_reboot_ops::wait_for_healthy_nodes(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &new_xname_vec,
+            &RollingRebootConfig {
+                reboot_method: RebootMethod::PowerReset,
+                health_timeout: migration_config.health_timeout,
+                poll_interval: migration_config.poll_interval,
+                ..Default::default()
+            },
+        )
+        .await?;
+
+        if !node_failure_vec.is_empty() {
+            return Err(format!("New nodes not healthy: {:?}", node_failure_vec).into());
+        }
+
+        cluster_migration.step = MigrationStep::NodesBooted;
+        save_checkpoint(&cluster_migration, checkpoint_file_opt)?;
+    }
+
+    if cluster_migration.step < MigrationStep::MembershipMoved {
+        move_membership(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &cluster_migration,
+            false,
+        )
+        .await?;
+
+        cluster_migration.step = MigrationStep::MembershipMoved;
+        save_checkpoint(&cluster_migration, checkpoint_file_opt)?;
+    }
+
+    if cluster_migration.step < MigrationStep::OldNodesPoweredOff {
+        check_power_report(
+            power_ops::power(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                PowerAction::Off,
+                &cluster_migration.get_old_xname_vec(),
+                &migration_config.power_config,
+            )
+            .await?,
+        )?;
+
+        cluster_migration.step = MigrationStep::OldNodesPoweredOff;
+        save_checkpoint(&cluster_migration, checkpoint_file_opt)?;
+    }
+
+    cluster_migration.step = MigrationStep::Completed;
+    save_checkpoint(&cluster_migration, checkpoint_file_opt)?;
+
+    Ok(cluster_migration)
+}
+
+/// Undoes a migration: old nodes are powered on and moved back to the cluster, new nodes get
+/// their original boot parameters and desired configuration, are powered off and moved back to
+/// the pool
+pub async fn rollback_migration(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    cluster_migration: &mut ClusterMigration,
+    migration_config: &MigrationConfig,
+) -> Result<(), Box<dyn Error>> {
+    let checkpoint_file_opt = migration_config.checkpoint_file_opt.as_deref();
+
+    if cluster_migration.step == MigrationStep::RolledBack {
+        return Ok(());
+    }
+
+    // Old nodes may have been partially powered off even if the step was not completed
+    if cluster_migration.step >= MigrationStep::MembershipMoved {
+        check_power_report(
+            power_ops::power(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                PowerAction::On,
+                &cluster_migration.get_old_xname_vec(),
+                &migration_config.power_config,
+            )
+            .await?,
+        )?;
+    }
+
+    // Membership may have been partially moved even if the step was not completed
+    move_membership(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        cluster_migration,
+        true,
+    )
+    .await?;
+
+    // New nodes may have been booted even if the step was not completed
+    if cluster_migration.step >= MigrationStep::DesiredConfigCopied {
+        check_power_report(
+            power_ops::power(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                PowerAction::Off,
+                &cluster_migration.get_new_xname_vec(),
+                &migration_config.power_config,
+            )
+            .await?,
+        )?;
+    }
+
+    let component_vec: Vec<Component> = cluster_migration
+        .node_migration_vec
+        .iter()
+        .filter(|node_migration| {
+            node_migration.new_desired_config_backup_opt.is_some()
+                || node_migration.new_enabled_backup_opt.is_some()
+        })
+        .map(|node_migration| Component {
+            id: Some(node_migration.new_xname.clone()),
+            desired_config: node_migration.new_desired_config_backup_opt.clone(),
+            enabled: node_migration.new_enabled_backup_opt,
+            ..Default::default()
+        })
+        .collect();
+
+    if !component_vec.is_empty() {
+        cfs::component::shasta::http_client::patch_component_list(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            component_vec,
+        )
+        .await?;
+    }
+
+    for node_migration in &cluster_migration.node_migration_vec {
+        if node_migration.new_desired_config_backup_opt.is_none() {
+            log::warn!(
+                "Node '{}' was not a CFS component before the migration, keeping the desired configuration copied from '{}'",
+                node_migration.new_xname,
+                node_migration.old_xname
+            );
+        }
+
+        match &node_migration.new_boot_parameters_backup_opt {
+            Some(new_boot_parameters_backup) => {
+                bss::http_client::put(
+                    shasta_base_url,
+                    shasta_token,
+                    shasta_root_cert,
+                    &vec![node_migration.new_xname.clone()],
+                    &new_boot_parameters_backup.params,
+                    &new_boot_parameters_backup.kernel,
+                    &new_boot_parameters_backup.initrd,
+                )
+                .await?;
+            }
+            None => log::warn!(
+                "Node '{}' had no boot parameters before the migration, keeping the ones copied from '{}'",
+                node_migration.new_xname,
+                node_migration.old_xname
+            ),
+        }
+    }
+
+    cluster_migration.step = MigrationStep::RolledBack;
+    save_checkpoint(cluster_migration, checkpoint_file_opt)?;
+
+    Ok(())
+}
+
+/// Selects the replacement nodes and takes a snapshot of the old and new nodes
+async fn plan_migration(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    hsm_group_name: &str,
+    pool_hsm_group_name: &str,
+) -> Result<ClusterMigration, Box<dyn Error>> {
+    let member_vec = hsm::group::shasta::utils::try_get_member_vec_from_hsm_group_name(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        hsm_group_name,
+    )
+    .await?;
+
+    let pool_member_vec = hsm::group::shasta::utils::try_get_member_vec_from_hsm_group_name(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        pool_hsm_group_name,
+    )
+    .await?;
+
+    let replacement_vec = select_replacement_nodes(&member_vec, &pool_member_vec)?;
+
+    let xname_vec: Vec<String> = [member_vec.clone(), replacement_vec.clone()].concat();
+
+    let boot_parameters_vec = bss::http_client::get_boot_parameters(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &xname_vec,
+    )
+    .await?;
+
+    let cfs_component_map: HashMap<String, Value> = cfs::component::mesa::http_client::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &xname_vec,
+    )
+    .await?
+    .into_iter()
+    .filter_map(|cfs_component| Some((cfs_component["id"].as_str()?.to_string(), cfs_component)))
+    .collect();
+
+    let get_desired_config = |xname: &String| {
+        cfs_component_map
+            .get(xname)
+            .and_then(|cfs_component| cfs_component["desiredConfig"].as_str())
+            .map(str::to_string)
+    };
+
+    let get_boot_parameters = |xname: &String| {
+        boot_parameters_vec
+            .iter()
+            .find(|boot_parameters| boot_parameters.hosts.contains(xname))
+            .cloned()
+    };
+
+    let node_migration_vec = member_vec
+        .iter()
+        .zip(replacement_vec.iter())
+        .map(|(old_xname, new_xname)| NodeMigration {
+            old_xname: old_xname.clone(),
+            new_xname: new_xname.clone(),
+            old_boot_parameters_opt: get_boot_parameters(old_xname),
+            old_desired_config_opt: get_desired_config(old_xname)
+                .filter(|desired_config| !desired_config.is_empty()),
+            new_boot_parameters_backup_opt: get_boot_parameters(new_xname),
+            new_desired_config_backup_opt: get_desired_config(new_xname),
+            new_enabled_backup_opt: cfs_component_map
+                .get(new_xname)
+                .and_then(|cfs_component| cfs_component["enabled"].as_bool()),
+            old_xname_in_pool: pool_member_vec.contains(old_xname),
+        })
+        .collect();
+
+    Ok(ClusterMigration {
+        hsm_group_name: hsm_group_name.to_string(),
+        pool_hsm_group_name: pool_hsm_group_name.to_string(),
+        created: chrono::Utc::now().to_rfc3339(),
+        step: MigrationStep::NodesSelected,
+        node_migration_vec,
+    })
+}
+
+/// Moves new nodes from the pool to the cluster and old nodes from the cluster to the pool. If
+/// reverse, moves them the other way round. Nodes already in the right group are skipped so the
+/// operation can be retried
+async fn move_membership(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    cluster_migration: &ClusterMigration,
+    reverse: bool,
+) -> Result<(), Box<dyn Error>> {
+    let member_vec = hsm::group::shasta::utils::try_get_member_vec_from_hsm_group_name(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &cluster_migration.hsm_group_name,
+    )
+    .await?;
+
+    let pool_member_vec = hsm::group::shasta::utils::try_get_member_vec_from_hsm_group_name(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &cluster_migration.pool_hsm_group_name,
+    )
+    .await?;
+
+    for node_migration in &cluster_migration.node_migration_vec {
+        let (xname_to_cluster, xname_to_pool) = if reverse {
+            (&node_migration.old_xname, &node_migration.new_xname)
+        } else {
+            (&node_migration.new_xname, &node_migration.old_xname)
+        };
+
+        // Old nodes which were already in the pool before the migration stay there
+        let keep_in_pool = reverse && node_migration.old_xname_in_pool;
+
+        // Remove first in case groups are exclusive
+        for (hsm_group_name, group_member_vec, xname, keep) in [
+            (
+                &cluster_migration.pool_hsm_group_name,
+                &pool_member_vec,
+                xname_to_cluster,
+                keep_in_pool,
+            ),
+            (
+                &cluster_migration.hsm_group_name,
+                &member_vec,
+                xname_to_pool,
+                false,
+            ),
+        ] {
+            if !keep && group_member_vec.contains(xname) {
+                hsm::group::mesa::http_client::delete_member(
+                    shasta_token,
+                    shasta_base_url,
+                    shasta_root_cert,
+                    hsm_group_name,
+                    xname,
+                )
+                .await?;
+            }
+        }
+
+        for (hsm_group_name, group_member_vec, xname) in [
+            (
+                &cluster_migration.hsm_group_name,
+                &member_vec,
+                xname_to_cluster,
+            ),
+            (
+                &cluster_migration.pool_hsm_group_name,
+                &pool_member_vec,
+                xname_to_pool,
+            ),
+        ] {
+            if !group_member_vec.contains(xname) {
+                hsm::group::mesa::http_client::post_member(
+                    shasta_token,
+                    shasta_base_url,
+                    shasta_root_cert,
+                    hsm_group_name,
+                    xname,
+                )
+                .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Picks as many nodes from the pool as nodes in the cluster
+pub fn select_replacement_nodes(
+    member_vec: &[String],
+    pool_member_vec: &[String],
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut candidate_vec: Vec<String> = pool_member_vec
+        .iter()
+        .filter(|xname| !member_vec.contains(xname))
+        .cloned()
+        .collect();
+
+    if candidate_vec.len() < member_vec.len() {
+        return Err(format!(
+            "Not enough nodes in pool, {} needed but only {} available",
+            member_vec.len(),
+            candidate_vec.len()
+        )
+        .into());
+    }
+
+    candidate_vec.sort();
+    candidate_vec.truncate(member_vec.len());
+
+    Ok(candidate_vec)
+}
+
+fn check_power_report(power_report: PowerReport) -> Result<(), Box<dyn Error>> {
+    if power_report.is_success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Power {:?} failed: {:?}",
+            power_report.action,
+            power_report.get_failed_vec()
+        )
+        .into())
+    }
+}
+
+fn save_checkpoint(
+    cluster_migration: &ClusterMigration,
+    checkpoint_file_opt: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    log::info!(
+        "Migration of '{}' step {:?} completed",
+        cluster_migration.hsm_group_name,
+        cluster_migration.step
+    );
+
+    match checkpoint_file_opt {
+        Some(checkpoint_file) => cluster_migration.to_file(checkpoint_file),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::select_replacement_nodes;
+
+    #[test]
+    fn test_select_replacement_nodes() {
+        let member_vec = vec!["x1000c0s0b0n0".to_string(), "x1000c0s0b0n1".to_string()];
+        let pool_member_vec = vec![
+            "x1000c0s2b0n1".to_string(),
+            "x1000c0s0b0n1".to_string(),
+            "x1000c0s2b0n0".to_string(),
+            "x1000c0s3b0n0".to_string(),
+        ];
+
+        assert_eq!(
+            select_replacement_nodes(&member_vec, &pool_member_vec).unwrap(),
+            vec!["x1000c0s2b0n0".to_string(), "x1000c0s2b0n1".to_string()]
+        );
+
+        assert!(select_replacement_nodes(&member_vec, &pool_member_vec[..2]).is_err());
+    }
+}
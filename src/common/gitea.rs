@@ -129,4 +129,96 @@ pub mod http_client {
         get_last_commit_from_repo_name(gitea_api_base_url, repo_name, gitea_token, shasta_root_cert)
             .await
     }
+
+    /// Returns the details of a commit or None if the commit or the repo is not in VCS.
+    /// repo_name includes the organization, eg 'cray/uan-config-management'
+    pub async fn get_commit_details_from_repo_name(
+        gitea_base_url: &str,
+        repo_name: &str,
+        commitid: &str,
+        gitea_token: &str,
+        shasta_root_cert: &[u8],
+    ) -> core::result::Result<Option<Value>, Box<dyn std::error::Error>> {
+        let client_builder = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
+
+        // Build client
+        let client = if let Ok(socks5_env) = std::env::var("SOCKS5") {
+            // socks5 proxy
+            let socks5proxy = reqwest::Proxy::all(socks5_env)?;
+
+            client_builder.proxy(socks5proxy).build()?
+        } else {
+            client_builder.build()?
+        };
+
+        let api_url = format!(
+            "{}/api/v1/repos/{}/git/commits/{}",
+            gitea_base_url.trim_end_matches('/'),
+            repo_name,
+            commitid
+        );
+
+        log::info!("Request to {}", api_url);
+
+        let resp = client
+            .get(api_url)
+            .header("Authorization", format!("token {}", gitea_token))
+            .send()
+            .await?;
+
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            Ok(None)
+        } else {
+            Ok(Some(resp.error_for_status()?.json().await?))
+        }
+    }
+
+    /// Returns the details of a branch. repo_name includes the organization, eg
+    /// 'cray/uan-config-management'
+    pub async fn get_branch_details(
+        gitea_base_url: &str,
+        repo_name: &str,
+        branch: &str,
+        gitea_token: &str,
+        shasta_root_cert: &[u8],
+    ) -> core::result::Result<Value, Box<dyn std::error::Error>> {
+        let client_builder = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
+
+        // Build client
+        let client = if let Ok(socks5_env) = std::env::var("SOCKS5") {
+            // socks5 proxy
+            let socks5proxy = reqwest::Proxy::all(socks5_env)?;
+
+            client_builder.proxy(socks5proxy).build()?
+        } else {
+            client_builder.build()?
+        };
+
+        let api_url = format!(
+            "{}/api/v1/repos/{}/branches/{}",
+            gitea_base_url.trim_end_matches('/'),
+            repo_name,
+            branch
+        );
+
+        log::info!("Request to {}", api_url);
+
+        let resp = client
+            .get(api_url)
+            .header("Authorization", format!("token {}", gitea_token))
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            Ok(resp.json().await?)
+        } else {
+            Err(format!(
+                "Branch '{}' not found in repo '{}' in Shasta VCS",
+                branch, repo_name
+            )
+            .into())
+        }
+    }
 }
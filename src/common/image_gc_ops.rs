@@ -0,0 +1,445 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bos::template::mesa::r#struct::response_payload::BosSessionTemplate,
+    bss::r#struct::BootParameters,
+    cfs::session::mesa::r#struct::CfsSessionGetResponse,
+    common::boot_image_ops::BOOT_IMAGE_BUCKET,
+    ims::{self, image::r#struct::Image},
+};
+
+/// Reason an image can't be deleted
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum ImageReference {
+    /// Nodes booting the image according to BSS
+    BootParameters(Vec<String>),
+    /// BOS session template using the image
+    BosSessionTemplate(String),
+    /// CFS session which created the image and is younger than the policy max age
+    RecentCfsSession(String),
+    /// Image is one of the last N images of an HSM group
+    KeepLastN(String),
+}
+
+/// Rules to decide which images are deleted
+#[derive(Debug, Clone)]
+pub struct ImageGcPolicy {
+    /// Number of most recent images to keep per HSM group
+    pub keep_last_n_per_hsm_group: usize,
+    /// Images created by CFS sessions younger than this are kept
+    pub cfs_session_max_age: chrono::Duration,
+    /// If provided, only images related to these HSM groups are candidates for deletion
+    pub hsm_group_name_vec_opt: Option<Vec<String>>,
+}
+
+impl Default for ImageGcPolicy {
+    fn default() -> Self {
+        Self {
+            keep_last_n_per_hsm_group: 3,
+            cfs_session_max_age: chrono::Duration::days(7),
+            hsm_group_name_vec_opt: None,
+        }
+    }
+}
+
+/// Images to delete and images kept with the reasons why
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ImageGcPlan {
+    pub created: String,
+    pub image_to_delete_vec: Vec<Image>,
+    pub image_to_keep_vec: Vec<(Image, Vec<ImageReference>)>,
+}
+
+/// Returns the list of images which can be deleted according to a policy. An image is deleted
+/// if it is not:
+///  - booted by any node in BSS
+///  - referenced by any BOS session template
+///  - the result of a CFS session younger than `cfs_session_max_age`
+///  - one of the last `keep_last_n_per_hsm_group` images of any HSM group it is related to
+pub async fn plan_image_gc(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    image_gc_policy: &ImageGcPolicy,
+) -> Result<ImageGcPlan, Box<dyn Error>> {
+    let image_vec =
+        ims::image::mesa::http_client::get_all(shasta_token, shasta_base_url, shasta_root_cert)
+            .await?;
+
+    // An empty list of xnames returns the boot parameters of all nodes
+    let boot_parameters_vec = crate::bss::http_client::get_boot_parameters(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &[],
+    )
+    .await?;
+
+    let bos_sessiontemplate_vec = crate::bos::template::mesa::http_client::get_all(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+    )
+    .await?;
+
+    let cfs_session_vec = crate::cfs::session::mesa::http_client::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        None,
+        None,
+    )
+    .await?;
+
+    Ok(get_image_gc_plan(
+        image_vec,
+        &boot_parameters_vec,
+        &bos_sessiontemplate_vec,
+        &cfs_session_vec,
+        image_gc_policy,
+        chrono::Utc::now(),
+    ))
+}
+
+/// Deletes the images in a plan, both the S3 artifacts and the IMS record. S3 artifacts are
+/// deleted first so a failure leaves a visible IMS record which can be deleted again later.
+/// Returns the ids of the images deleted. If `dry_run` is true, nothing is deleted
+pub async fn delete_images(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    image_gc_plan: &ImageGcPlan,
+    dry_run: bool,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let image_id_vec: Vec<String> = image_gc_plan
+        .image_to_delete_vec
+        .iter()
+        .filter_map(|image| image.id.clone())
+        .collect();
+
+    if dry_run {
+        for image in &image_gc_plan.image_to_delete_vec {
+            log::info!(
+                "Dry run: image '{}' ({}) would be deleted",
+                image.name,
+                image.id.clone().unwrap_or_default()
+            );
+        }
+
+        return Ok(image_id_vec);
+    }
+
+    let sts_value = ims::s3::s3_auth(shasta_token, shasta_base_url, shasta_root_cert).await?;
+
+    for image in &image_gc_plan.image_to_delete_vec {
+        let image_id = match &image.id {
+            Some(image_id) => image_id,
+            None => continue,
+        };
+
+        // Artifacts listed in the manifest plus anything else under the image id. Artifacts
+        // outside the image id path may be shared with other images and are left untouched
+        let mut object_vec: Vec<(String, String)> =
+            match ims::image::manifest::get_manifest(&sts_value, image).await {
+                Ok(manifest) => manifest
+                    .artifacts
+                    .iter()
+                    .filter_map(|artifact| ims::s3::split_s3_url(&artifact.link.path))
+                    .filter(|(_, key)| key.starts_with(&format!("{}/", image_id)))
+                    .collect(),
+                Err(error) => {
+                    log::warn!("Could not read manifest of image '{}': {}", image_id, error);
+                    Vec::new()
+                }
+            };
+
+        for key in
+            ims::s3::s3_list_objects(&sts_value, BOOT_IMAGE_BUCKET, &format!("{}/", image_id))
+                .await?
+        {
+            object_vec.push((BOOT_IMAGE_BUCKET.to_string(), key));
+        }
+
+        object_vec.sort();
+        object_vec.dedup();
+
+        for (bucket, key) in object_vec {
+            log::info!("Deleting 's3://{}/{}'", bucket, key);
+            ims::s3::s3_remove_object(&sts_value, &key, &bucket).await?;
+        }
+
+        log::info!("Deleting IMS image '{}' ({})", image.name, image_id);
+
+        ims::image::shasta::http_client::delete(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            image_id,
+        )
+        .await?;
+    }
+
+    Ok(image_id_vec)
+}
+
+/// Builds the plan from the data already fetched from CSM
+pub fn get_image_gc_plan(
+    mut image_vec: Vec<Image>,
+    boot_parameters_vec: &[BootParameters],
+    bos_sessiontemplate_vec: &[BosSessionTemplate],
+    cfs_session_vec: &[CfsSessionGetResponse],
+    image_gc_policy: &ImageGcPolicy,
+    now: chrono::DateTime<chrono::Utc>,
+) -> ImageGcPlan {
+    // Most recent first
+    image_vec.sort_by(|a, b| b.created.cmp(&a.created));
+
+    let mut image_reference_map: HashMap<String, Vec<ImageReference>> = HashMap::new();
+    let mut image_hsm_group_map: HashMap<String, HashSet<String>> = HashMap::new();
+
+    for boot_parameters in boot_parameters_vec {
+        if let Some(image_id) = boot_parameters.get_boot_image_id() {
+            image_reference_map
+                .entry(image_id)
+                .or_default()
+                .push(ImageReference::BootParameters(boot_parameters.hosts.clone()));
+        }
+    }
+
+    for bos_sessiontemplate in bos_sessiontemplate_vec {
+        if bos_sessiontemplate.boot_sets.is_none() {
+            continue;
+        }
+
+        for path in bos_sessiontemplate.get_path() {
+            if let Some(image_id) = get_image_id_from_path(&path) {
+                image_reference_map
+                    .entry(image_id.clone())
+                    .or_default()
+                    .push(ImageReference::BosSessionTemplate(
+                        bos_sessiontemplate.name.clone().unwrap_or_default(),
+                    ));
+
+                image_hsm_group_map
+                    .entry(image_id)
+                    .or_default()
+                    .extend(bos_sessiontemplate.get_target_hsm());
+            }
+        }
+    }
+
+    for cfs_session in cfs_session_vec {
+        let image_id = match cfs_session.get_result_id() {
+            Some(image_id) if !image_id.is_empty() => image_id,
+            _ => continue,
+        };
+
+        image_hsm_group_map
+            .entry(image_id.clone())
+            .or_default()
+            .extend(cfs_session.get_target_hsm().unwrap_or_default());
+
+        let start_time_opt = cfs_session
+            .status
+            .as_ref()
+            .and_then(|status| status.session.as_ref())
+            .and_then(|session| session.start_time.as_ref())
+            .and_then(|start_time| parse_time(start_time));
+
+        // Sessions without start time are considered recent to be on the safe side
+        let is_recent = match start_time_opt {
+            Some(start_time) => {
+                now.signed_duration_since(start_time) < image_gc_policy.cfs_session_max_age
+            }
+            None => true,
+        };
+
+        if is_recent {
+            image_reference_map
+                .entry(image_id)
+                .or_default()
+                .push(ImageReference::RecentCfsSession(
+                    cfs_session.name.clone().unwrap_or_default(),
+                ));
+        }
+    }
+
+    // Keep last N images per HSM group (image_vec is sorted by creation time DESC)
+    let mut hsm_group_image_count_map: HashMap<String, usize> = HashMap::new();
+
+    for image in &image_vec {
+        let image_id = image.id.clone().unwrap_or_default();
+
+        let mut hsm_group_vec: Vec<String> = image_hsm_group_map
+            .get(&image_id)
+            .map(|hsm_group_set| hsm_group_set.iter().cloned().collect())
+            .unwrap_or_default();
+        hsm_group_vec.sort();
+
+        for hsm_group in hsm_group_vec {
+            let image_count = hsm_group_image_count_map.entry(hsm_group.clone()).or_default();
+
+            if *image_count < image_gc_policy.keep_last_n_per_hsm_group {
+                image_reference_map
+                    .entry(image_id.clone())
+                    .or_default()
+                    .push(ImageReference::KeepLastN(hsm_group));
+            }
+
+            *image_count += 1;
+        }
+    }
+
+    let mut image_gc_plan = ImageGcPlan {
+        created: now.to_rfc3339(),
+        ..Default::default()
+    };
+
+    for image in image_vec {
+        let image_id = image.id.clone().unwrap_or_default();
+
+        // Images out of scope are never deleted
+        let is_in_scope = match &image_gc_policy.hsm_group_name_vec_opt {
+            Some(hsm_group_name_vec) => {
+                image_hsm_group_map.get(&image_id).is_some_and(|hsm_group_set| {
+                    hsm_group_set
+                        .iter()
+                        .any(|hsm_group| hsm_group_name_vec.contains(hsm_group))
+                })
+            }
+            None => true,
+        };
+
+        match image_reference_map.remove(&image_id) {
+            Some(image_reference_vec) => image_gc_plan
+                .image_to_keep_vec
+                .push((image, image_reference_vec)),
+            None if is_in_scope && !image_id.is_empty() => {
+                image_gc_plan.image_to_delete_vec.push(image)
+            }
+            None => image_gc_plan.image_to_keep_vec.push((image, Vec::new())),
+        }
+    }
+
+    image_gc_plan
+}
+
+/// Returns the image id from an S3 path, eg 's3://boot-images/<image id>/manifest.json'
+fn get_image_id_from_path(path: &str) -> Option<String> {
+    path.strip_prefix(&format!("s3://{}/", BOOT_IMAGE_BUCKET))
+        .and_then(|path| path.split('/').next())
+        .filter(|image_id| !image_id.is_empty())
+        .map(|image_id| image_id.to_string())
+}
+
+/// CFS returns times without timezone (UTC), eg '2024-01-10T10:00:00'
+fn parse_time(time: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    chrono::DateTime::parse_from_rfc3339(time)
+        .map(|time| time.with_timezone(&chrono::Utc))
+        .ok()
+        .or_else(|| {
+            chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S")
+                .ok()
+                .map(|time| chrono::DateTime::from_naive_utc_and_offset(time, chrono::Utc))
+        })
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{
+        bss::r#struct::BootParameters, cfs::session::mesa::r#struct::CfsSessionGetResponse,
+        ims::image::r#struct::Image,
+    };
+
+    use super::{get_image_gc_plan, ImageGcPolicy, ImageReference};
+
+    fn new_image(id: &str, created: &str) -> Image {
+        Image {
+            id: Some(id.to_string()),
+            created: Some(created.to_string()),
+            name: id.to_string(),
+            link: None,
+            arch: None,
+        }
+    }
+
+    fn new_cfs_session(name: &str, result_id: &str, start_time: &str) -> CfsSessionGetResponse {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "target": { "definition": "image", "groups": [{ "name": "zinal", "members": [] }] },
+            "status": {
+                "artifacts": [{ "image_id": "base", "result_id": result_id, "type": "ims_customized_image" }],
+                "session": { "startTime": start_time, "succeeded": "true" }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_image_gc_plan() {
+        let image_vec = vec![
+            new_image("image-1", "2024-01-01T00:00:00"),
+            new_image("image-2", "2024-01-02T00:00:00"),
+            new_image("image-3", "2024-01-03T00:00:00"),
+            new_image("image-4", "2024-01-04T00:00:00"),
+            new_image("unrelated", "2024-01-05T00:00:00"),
+        ];
+
+        let boot_parameters_vec = vec![BootParameters {
+            hosts: vec!["x1003c1s7b0n0".to_string()],
+            kernel: "s3://boot-images/image-1/kernel".to_string(),
+            ..Default::default()
+        }];
+
+        let cfs_session_vec = vec![
+            new_cfs_session("session-1", "image-1", "2024-01-01T00:00:00"),
+            new_cfs_session("session-2", "image-2", "2024-01-02T00:00:00"),
+            new_cfs_session("session-3", "image-3", "2024-01-03T00:00:00"),
+            new_cfs_session("session-4", "image-4", "2024-01-04T00:00:00"),
+        ];
+
+        let image_gc_policy = ImageGcPolicy {
+            keep_last_n_per_hsm_group: 1,
+            cfs_session_max_age: chrono::Duration::days(1),
+            hsm_group_name_vec_opt: Some(vec!["zinal".to_string()]),
+        };
+
+        let now = chrono::DateTime::parse_from_rfc3339("2024-01-04T12:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+
+        let image_gc_plan = get_image_gc_plan(
+            image_vec,
+            &boot_parameters_vec,
+            &[],
+            &cfs_session_vec,
+            &image_gc_policy,
+            now,
+        );
+
+        let image_to_delete_id_vec: Vec<String> = image_gc_plan
+            .image_to_delete_vec
+            .iter()
+            .map(|image| image.id.clone().unwrap())
+            .collect();
+
+        // image-1 booted by a node, image-4 is the last image of 'zinal' and its CFS session is
+        // recent, 'unrelated' is out of scope
+        assert_eq!(image_to_delete_id_vec, vec!["image-3", "image-2"]);
+
+        let (_, image_4_reference_vec) = image_gc_plan
+            .image_to_keep_vec
+            .iter()
+            .find(|(image, _)| image.id.as_deref() == Some("image-4"))
+            .unwrap();
+
+        assert!(image_4_reference_vec.contains(&ImageReference::KeepLastN("zinal".to_string())));
+        assert!(image_4_reference_vec
+            .contains(&ImageReference::RecentCfsSession("session-4".to_string())));
+    }
+}
@@ -0,0 +1,418 @@
+use std::{collections::HashSet, error::Error};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bos::template::mesa::r#struct::response_payload::BosSessionTemplate,
+    bss::r#struct::BootParameters,
+    cfs::{
+        configuration::mesa::r#struct::cfs_configuration_response::CfsConfigurationResponse,
+        session::mesa::r#struct::CfsSessionGetResponse,
+    },
+    ims::image::r#struct::Image,
+};
+
+/// CFS configuration layer used to customize an image
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct LayerProvenance {
+    pub name: String,
+    pub clone_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    pub playbook: String,
+}
+
+/// Where an image comes from and where it is used
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ImageProvenance {
+    pub image_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    /// CFS session which created the image
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cfs_session_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cfs_configuration_name: Option<String>,
+    /// Layers of the CFS configuration, empty if the configuration does not exists anymore
+    pub layer_vec: Vec<LayerProvenance>,
+    /// Images the CFS session customized to create this image
+    pub base_image_id_vec: Vec<String>,
+    pub bos_sessiontemplate_name_vec: Vec<String>,
+    /// Nodes booting the image according to BSS
+    pub xname_vec: Vec<String>,
+}
+
+/// History of an image. The first element is the image requested followed by its base images,
+/// the base images of those, etc
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ImageLineage {
+    pub image_id: String,
+    pub image_provenance_vec: Vec<ImageProvenance>,
+}
+
+impl ImageLineage {
+    pub fn get_image_provenance(&self, image_id: &str) -> Option<&ImageProvenance> {
+        self.image_provenance_vec
+            .iter()
+            .find(|image_provenance| image_provenance.image_id == image_id)
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Returns the lineage as a Graphviz DOT graph
+    /// eg `dot -Tsvg lineage.dot > lineage.svg`
+    pub fn to_dot(&self) -> String {
+        let mut line_vec = vec![
+            "digraph image_lineage {".to_string(),
+            "  rankdir=LR;".to_string(),
+        ];
+
+        for image_provenance in &self.image_provenance_vec {
+            let image_node = format!("image_{}", image_provenance.image_id);
+
+            line_vec.push(format!(
+                "  {} [shape=box, label={}];",
+                quote(&image_node),
+                quote(&format!(
+                    "{}\n{}",
+                    image_provenance.image_name.clone().unwrap_or_default(),
+                    image_provenance.image_id
+                ))
+            ));
+
+            for base_image_id in &image_provenance.base_image_id_vec {
+                line_vec.push(format!(
+                    "  {} -> {} [label={}];",
+                    quote(&format!("image_{}", base_image_id)),
+                    quote(&image_node),
+                    quote(
+                        &image_provenance
+                            .cfs_session_name
+                            .clone()
+                            .unwrap_or_default()
+                    )
+                ));
+            }
+
+            if let Some(cfs_configuration_name) = &image_provenance.cfs_configuration_name {
+                let configuration_node = format!("configuration_{}", cfs_configuration_name);
+
+                line_vec.push(format!(
+                    "  {} [shape=ellipse, label={}];",
+                    quote(&configuration_node),
+                    quote(cfs_configuration_name)
+                ));
+                line_vec.push(format!(
+                    "  {} -> {} [label=\"configures\"];",
+                    quote(&configuration_node),
+                    quote(&image_node)
+                ));
+
+                for layer in &image_provenance.layer_vec {
+                    let layer_node = format!(
+                        "layer_{}_{}",
+                        layer.clone_url,
+                        layer.commit.clone().unwrap_or_default()
+                    );
+
+                    line_vec.push(format!(
+                        "  {} [shape=note, label={}];",
+                        quote(&layer_node),
+                        quote(&format!(
+                            "{}\n{}",
+                            layer.name,
+                            layer
+                                .commit
+                                .clone()
+                                .or(layer.branch.clone())
+                                .unwrap_or_default()
+                        ))
+                    ));
+                    line_vec.push(format!(
+                        "  {} -> {};",
+                        quote(&layer_node),
+                        quote(&configuration_node)
+                    ));
+                }
+            }
+
+            for bos_sessiontemplate_name in &image_provenance.bos_sessiontemplate_name_vec {
+                let bos_sessiontemplate_node = format!("bos_{}", bos_sessiontemplate_name);
+
+                line_vec.push(format!(
+                    "  {} [shape=component, label={}];",
+                    quote(&bos_sessiontemplate_node),
+                    quote(bos_sessiontemplate_name)
+                ));
+                line_vec.push(format!(
+                    "  {} -> {} [label=\"references\"];",
+                    quote(&bos_sessiontemplate_node),
+                    quote(&image_node)
+                ));
+            }
+
+            for xname in &image_provenance.xname_vec {
+                line_vec.push(format!(
+                    "  {} [shape=circle, label={}];",
+                    quote(&format!("node_{}", xname)),
+                    quote(xname)
+                ));
+                line_vec.push(format!(
+                    "  {} -> {} [label=\"boots\"];",
+                    quote(&image_node),
+                    quote(&format!("node_{}", xname))
+                ));
+            }
+        }
+
+        line_vec.push("}".to_string());
+
+        // Nodes and edges can be repeated if shared between images
+        let mut seen = HashSet::new();
+        line_vec.retain(|line| seen.insert(line.clone()));
+
+        line_vec.join("\n")
+    }
+}
+
+/// Returns the history of an image: the images it was built from, the CFS configuration and
+/// layer commits used to customize it, the BOS session templates referencing it and the nodes
+/// booting it
+pub async fn get_image_lineage(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    image_id: &str,
+) -> Result<ImageLineage, Box<dyn Error>> {
+    let image_vec = crate::ims::image::mesa::http_client::get_all(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+    )
+    .await?;
+
+    let cfs_session_vec = crate::cfs::session::mesa::http_client::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        None,
+        None,
+    )
+    .await?;
+
+    let bos_sessiontemplate_vec = crate::bos::template::mesa::http_client::get_all(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+    )
+    .await?;
+
+    // An empty list of xnames returns the boot parameters of all nodes
+    let boot_parameters_vec = crate::bss::http_client::get_boot_parameters(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &[],
+    )
+    .await?;
+
+    let mut image_lineage = ImageLineage {
+        image_id: image_id.to_string(),
+        image_provenance_vec: Vec::new(),
+    };
+
+    let mut pending_image_id_vec = vec![image_id.to_string()];
+    let mut visited_image_id_set = HashSet::new();
+
+    while let Some(image_id) = pending_image_id_vec.pop() {
+        if !visited_image_id_set.insert(image_id.clone()) {
+            continue;
+        }
+
+        let cfs_session_opt =
+            crate::cfs::session::mesa::utils::find_cfs_session_related_to_image_id(
+                &cfs_session_vec,
+                &image_id,
+            );
+
+        let cfs_configuration_opt = match cfs_session_opt
+            .as_ref()
+            .and_then(|cfs_session| cfs_session.get_configuration_name())
+        {
+            Some(cfs_configuration_name) => crate::cfs::configuration::mesa::http_client::get(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                Some(&cfs_configuration_name),
+            )
+            .await
+            .ok()
+            .and_then(|cfs_configuration_vec| cfs_configuration_vec.first().cloned()),
+            None => None,
+        };
+
+        let image_provenance = get_image_provenance(
+            &image_id,
+            &image_vec,
+            cfs_session_opt.as_ref(),
+            cfs_configuration_opt.as_ref(),
+            &bos_sessiontemplate_vec,
+            &boot_parameters_vec,
+        );
+
+        pending_image_id_vec.extend(image_provenance.base_image_id_vec.clone());
+
+        image_lineage.image_provenance_vec.push(image_provenance);
+    }
+
+    Ok(image_lineage)
+}
+
+/// Builds the provenance of a single image from the data already fetched from CSM
+pub fn get_image_provenance(
+    image_id: &str,
+    image_vec: &[Image],
+    cfs_session_opt: Option<&CfsSessionGetResponse>,
+    cfs_configuration_opt: Option<&CfsConfigurationResponse>,
+    bos_sessiontemplate_vec: &[BosSessionTemplate],
+    boot_parameters_vec: &[BootParameters],
+) -> ImageProvenance {
+    let image_opt = image_vec
+        .iter()
+        .find(|image| image.id.as_deref() == Some(image_id));
+
+    // For CFS sessions building images, the target groups members are the base images
+    let base_image_id_vec: Vec<String> = cfs_session_opt
+        .and_then(|cfs_session| cfs_session.target.as_ref())
+        .and_then(|target| target.groups.as_ref())
+        .map(|group_vec| {
+            group_vec
+                .iter()
+                .flat_map(|group| group.members.clone())
+                .filter(|member| member != image_id)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let layer_vec = cfs_configuration_opt
+        .map(|cfs_configuration| {
+            cfs_configuration
+                .layers
+                .iter()
+                .map(|layer| LayerProvenance {
+                    name: layer.name.clone(),
+                    clone_url: layer.clone_url.clone(),
+                    commit: layer.commit.clone(),
+                    branch: layer.branch.clone(),
+                    playbook: layer.playbook.clone(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let bos_sessiontemplate_name_vec = bos_sessiontemplate_vec
+        .iter()
+        .filter(|bos_sessiontemplate| {
+            bos_sessiontemplate
+                .boot_sets
+                .as_ref()
+                .is_some_and(|boot_sets| !boot_sets.is_empty())
+        })
+        .filter(|bos_sessiontemplate| {
+            crate::bos::template::mesa::utils::find_bos_sessiontemplate_related_to_image_id(
+                std::slice::from_ref(*bos_sessiontemplate),
+                image_id,
+            )
+            .is_some()
+        })
+        .filter_map(|bos_sessiontemplate| bos_sessiontemplate.name.clone())
+        .collect();
+
+    let xname_vec = boot_parameters_vec
+        .iter()
+        .filter(|boot_parameters| {
+            boot_parameters
+                .get_boot_image_id()
+                .is_some_and(|boot_image_id| boot_image_id == image_id)
+        })
+        .flat_map(|boot_parameters| boot_parameters.hosts.clone())
+        .collect();
+
+    ImageProvenance {
+        image_id: image_id.to_string(),
+        image_name: image_opt.map(|image| image.name.clone()),
+        created: image_opt.and_then(|image| image.created.clone()),
+        cfs_session_name: cfs_session_opt.and_then(|cfs_session| cfs_session.name.clone()),
+        cfs_configuration_name: cfs_session_opt
+            .and_then(|cfs_session| cfs_session.get_configuration_name()),
+        layer_vec,
+        base_image_id_vec,
+        bos_sessiontemplate_name_vec,
+        xname_vec,
+    }
+}
+
+/// Quotes a DOT id
+fn quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{ImageLineage, ImageProvenance, LayerProvenance};
+
+    #[test]
+    fn test_image_lineage_to_dot() {
+        let image_lineage = ImageLineage {
+            image_id: "image-2".to_string(),
+            image_provenance_vec: vec![
+                ImageProvenance {
+                    image_id: "image-2".to_string(),
+                    image_name: Some("zinal-cos".to_string()),
+                    cfs_session_name: Some("batcave-session".to_string()),
+                    cfs_configuration_name: Some("zinal-config".to_string()),
+                    layer_vec: vec![LayerProvenance {
+                        name: "cos".to_string(),
+                        clone_url:
+                            "https://api-gw-service-nmn.local/vcs/cray/cos-config-management.git"
+                                .to_string(),
+                        commit: Some("1a2b3c".to_string()),
+                        branch: None,
+                        playbook: "site.yml".to_string(),
+                    }],
+                    base_image_id_vec: vec!["image-1".to_string()],
+                    bos_sessiontemplate_name_vec: vec!["zinal-template".to_string()],
+                    xname_vec: vec!["x1003c1s7b0n0".to_string()],
+                    ..Default::default()
+                },
+                ImageProvenance {
+                    image_id: "image-1".to_string(),
+                    image_name: Some("cos-base".to_string()),
+                    ..Default::default()
+                },
+            ],
+        };
+
+        let dot = image_lineage.to_dot();
+
+        assert!(dot.starts_with("digraph image_lineage {"));
+        assert!(dot.contains("\"image_image-1\" -> \"image_image-2\" [label=\"batcave-session\"];"));
+        assert!(dot.contains("\"configuration_zinal-config\" -> \"image_image-2\""));
+        assert!(dot.contains("\"image_image-2\" -> \"node_x1003c1s7b0n0\" [label=\"boots\"];"));
+        assert!(dot.ends_with('}'));
+
+        let image_lineage_json: serde_json::Value =
+            serde_json::from_str(&image_lineage.to_json().unwrap()).unwrap();
+
+        assert_eq!(
+            image_lineage_json["image_provenance_vec"][0]["base_image_id_vec"][0],
+            "image-1"
+        );
+    }
+}
@@ -0,0 +1,374 @@
+use std::{
+    error::Error,
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bss::{self, r#struct::BootParameters},
+    cfs::component::shasta::r#struct::Component,
+};
+
+/// Name of the environment variable with the path to the journal file. Journaling is disabled
+/// if not set
+pub const JOURNAL_FILE_ENV_VAR_NAME: &str = "MESA_JOURNAL_FILE";
+
+/// CFS component values we need to restore a node configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CfsComponentSnapshot {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desired_config: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum JournalSnapshot {
+    BootParameters(Vec<BootParameters>),
+    CfsComponent(Vec<CfsComponentSnapshot>),
+}
+
+/// State of a list of nodes before a mutating operation
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JournalEntry {
+    pub id: String,
+    pub created: String,
+    pub operation: String,
+    pub xname_vec: Vec<String>,
+    pub snapshot: JournalSnapshot,
+}
+
+/// Journal of the state of the nodes before mutating BSS boot parameters or CFS components.
+/// Entries are stored in a JSON lines file (one JSON document per line) so the file can be
+/// appended safely and inspected with standard tools.
+/// Journaling is opt-in, mutating operations in BSS and CFS components will only create
+/// entries if the environment variable MESA_JOURNAL_FILE is set (see `Journal::from_env`)
+pub struct Journal {
+    pub path: PathBuf,
+}
+
+impl Journal {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Returns the journal configured through env var MESA_JOURNAL_FILE (if any)
+    pub fn from_env() -> Option<Self> {
+        std::env::var(JOURNAL_FILE_ENV_VAR_NAME)
+            .ok()
+            .filter(|path| !path.is_empty())
+            .map(|path| Self::new(Path::new(&path)))
+    }
+
+    pub fn append(&self, journal_entry: &JournalEntry) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+
+        writeln!(file, "{}", serde_json::to_string(journal_entry)?)?;
+
+        log::debug!(
+            "Journal entry '{}' for operation '{}' stored in '{}'",
+            journal_entry.id,
+            journal_entry.operation,
+            self.path.display()
+        );
+
+        Ok(())
+    }
+
+    /// Returns all journal entries sorted by creation time ASC
+    pub fn get_entry_vec(&self) -> Result<Vec<JournalEntry>, Box<dyn Error>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let file = std::fs::File::open(&self.path)?;
+
+        let mut journal_entry_vec = Vec::new();
+
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            journal_entry_vec.push(serde_json::from_str::<JournalEntry>(&line)?);
+        }
+
+        Ok(journal_entry_vec)
+    }
+
+    pub fn get_entry(&self, journal_id: &str) -> Result<Option<JournalEntry>, Box<dyn Error>> {
+        Ok(self
+            .get_entry_vec()?
+            .into_iter()
+            .find(|journal_entry| journal_entry.id == journal_id))
+    }
+
+    /// Stores the current boot parameters of a list of nodes
+    pub async fn snapshot_boot_parameters(
+        &self,
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+        xname_vec: &[String],
+        operation: &str,
+    ) -> Result<JournalEntry, Box<dyn Error>> {
+        let mut boot_parameters_vec = bss::http_client::get_boot_parameters(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            xname_vec,
+        )
+        .await?;
+
+        // BSS groups nodes with same boot params, we only want to store the ones requested
+        for boot_parameters in boot_parameters_vec.iter_mut() {
+            boot_parameters
+                .hosts
+                .retain(|host| xname_vec.contains(host));
+        }
+
+        boot_parameters_vec.retain(|boot_parameters| !boot_parameters.hosts.is_empty());
+
+        let journal_entry = JournalEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            created: chrono::Utc::now().to_rfc3339(),
+            operation: operation.to_string(),
+            xname_vec: xname_vec.to_vec(),
+            snapshot: JournalSnapshot::BootParameters(boot_parameters_vec),
+        };
+
+        self.append(&journal_entry)?;
+
+        Ok(journal_entry)
+    }
+
+    /// Stores the current CFS desired configuration and enabled state of a list of nodes
+    pub async fn snapshot_cfs_components(
+        &self,
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+        xname_vec: &[String],
+        operation: &str,
+    ) -> Result<JournalEntry, Box<dyn Error>> {
+        let cfs_component_value_vec = crate::cfs::component::mesa::http_client::get(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            xname_vec,
+        )
+        .await?;
+
+        let cfs_component_snapshot_vec = cfs_component_value_vec
+            .iter()
+            .filter_map(|cfs_component_value| {
+                cfs_component_value["id"]
+                    .as_str()
+                    .map(|id| CfsComponentSnapshot {
+                        id: id.to_string(),
+                        desired_config: cfs_component_value["desiredConfig"]
+                            .as_str()
+                            .map(|desired_config| desired_config.to_string()),
+                        enabled: cfs_component_value["enabled"].as_bool(),
+                    })
+            })
+            .collect();
+
+        let journal_entry = JournalEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            created: chrono::Utc::now().to_rfc3339(),
+            operation: operation.to_string(),
+            xname_vec: xname_vec.to_vec(),
+            snapshot: JournalSnapshot::CfsComponent(cfs_component_snapshot_vec),
+        };
+
+        self.append(&journal_entry)?;
+
+        Ok(journal_entry)
+    }
+
+    /// Restores the state of the nodes stored in a journal entry
+    pub async fn rollback(
+        &self,
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+        journal_id: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let journal_entry = self
+            .get_entry(journal_id)?
+            .ok_or_else(|| format!("Journal entry '{}' not found", journal_id))?;
+
+        log::info!(
+            "Rolling back operation '{}' ({}) on nodes {:?}",
+            journal_entry.operation,
+            journal_entry.created,
+            journal_entry.xname_vec
+        );
+
+        match journal_entry.snapshot {
+            JournalSnapshot::BootParameters(boot_parameters_vec) => {
+                for boot_parameters in boot_parameters_vec {
+                    bss::http_client::patch_boot_parameters(
+                        shasta_base_url,
+                        shasta_token,
+                        shasta_root_cert,
+                        &boot_parameters,
+                    )
+                    .await?;
+                }
+            }
+            JournalSnapshot::CfsComponent(cfs_component_snapshot_vec) => {
+                let component_vec: Vec<Component> = cfs_component_snapshot_vec
+                    .into_iter()
+                    .map(|cfs_component_snapshot| Component {
+                        id: Some(cfs_component_snapshot.id),
+                        state: None,
+                        state_append: None,
+                        desired_config: cfs_component_snapshot.desired_config,
+                        error_count: None,
+                        retry_policy: None,
+                        enabled: cfs_component_snapshot.enabled,
+                        tags: None,
+                    })
+                    .collect();
+
+                crate::cfs::component::shasta::http_client::patch_component_list(
+                    shasta_token,
+                    shasta_base_url,
+                    shasta_root_cert,
+                    component_vec,
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Stores the boot parameters of a list of nodes in the journal if journaling is enabled.
+/// Fails if the snapshot can't be stored so the caller does not change the nodes without a
+/// rollback record
+pub async fn record_boot_parameters(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+    operation: &str,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(journal) = Journal::from_env() {
+        journal
+            .snapshot_boot_parameters(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                xname_vec,
+                operation,
+            )
+            .await
+            .map_err(|error| {
+                format!(
+                    "Could not store boot parameters in journal '{}', aborting: {}",
+                    journal.path.display(),
+                    error
+                )
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Stores the CFS components of a list of nodes in the journal if journaling is enabled.
+/// Fails if the snapshot can't be stored so the caller does not change the nodes without a
+/// rollback record
+pub async fn record_cfs_components(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+    operation: &str,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(journal) = Journal::from_env() {
+        journal
+            .snapshot_cfs_components(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                xname_vec,
+                operation,
+            )
+            .await
+            .map_err(|error| {
+                format!(
+                    "Could not store CFS components in journal '{}', aborting: {}",
+                    journal.path.display(),
+                    error
+                )
+            })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{CfsComponentSnapshot, Journal, JournalEntry, JournalSnapshot};
+
+    #[test]
+    fn test_journal_append_and_read() {
+        let journal_path =
+            std::env::temp_dir().join(format!("mesa-journal-{}.jsonl", uuid::Uuid::new_v4()));
+
+        let journal = Journal::new(&journal_path);
+
+        assert!(journal.get_entry_vec().unwrap().is_empty());
+
+        for id in ["first", "second"] {
+            journal
+                .append(&JournalEntry {
+                    id: id.to_string(),
+                    created: chrono::Utc::now().to_rfc3339(),
+                    operation: "test".to_string(),
+                    xname_vec: vec!["x1003c1s7b0n0".to_string()],
+                    snapshot: JournalSnapshot::CfsComponent(vec![CfsComponentSnapshot {
+                        id: "x1003c1s7b0n0".to_string(),
+                        desired_config: Some(format!("{}-config", id)),
+                        enabled: Some(true),
+                    }]),
+                })
+                .unwrap();
+        }
+
+        assert_eq!(journal.get_entry_vec().unwrap().len(), 2);
+
+        let journal_entry = journal.get_entry("second").unwrap().unwrap();
+
+        match journal_entry.snapshot {
+            JournalSnapshot::CfsComponent(cfs_component_snapshot_vec) => assert_eq!(
+                cfs_component_snapshot_vec[0].desired_config.as_deref(),
+                Some("second-config")
+            ),
+            _ => panic!("unexpected snapshot type"),
+        }
+
+        assert!(journal.get_entry("third").unwrap().is_none());
+
+        std::fs::remove_file(journal_path).unwrap();
+    }
+}
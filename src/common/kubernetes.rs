@@ -1,12 +1,14 @@
-use core::time;
-use std::{error::Error, str::FromStr};
+use std::{error::Error, path::PathBuf, str::FromStr, sync::Arc, time::Duration};
 
 use futures::TryStreamExt;
 
 use futures::{io::Lines, AsyncBufReadExt};
 use hyper::Uri;
 use hyper_socks2::SocksConnector;
-use k8s_openapi::api::core::v1::{Container, Pod};
+use k8s_openapi::{
+    api::core::v1::{Container, ContainerState, Pod},
+    apimachinery::pkg::apis::meta::v1::Status,
+};
 use kube::{
     api::{AttachParams, AttachedProcess},
     client::ConfigExt,
@@ -14,6 +16,7 @@ use kube::{
         AuthInfo, Cluster, Context, KubeConfigOptions, Kubeconfig, NamedAuthInfo, NamedCluster,
         NamedContext,
     },
+    runtime::{watcher, WatchStreamExt},
     Api,
 };
 
@@ -21,24 +24,132 @@ use futures::StreamExt;
 
 use secrecy::SecretString;
 use serde_json::Value;
-use termion::color;
+use tokio::io::{AsyncRead, AsyncReadExt};
 
-pub async fn get_k8s_client_programmatically(
+use crate::common::secret_provider::SecretProvider;
+
+/// Maximum time to wait for CFS and IMS pods to start
+pub const POD_WAIT_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Name in the certificate of the CSM k8s API server (Subject: CN). Taken from the output of
+/// echo | openssl s_client -showcerts -servername 10.252.1.12 -connect 10.252.1.12:6442 2>/dev/null | openssl x509 -inform pem -noout -text
+pub const CSM_K8S_API_TLS_SERVER_NAME: &str = "kube-apiserver";
+
+/// Where to get the configuration to connect to the k8s API from
+#[derive(Debug, Clone)]
+pub enum K8sClientSource {
+    /// Cluster certificates fetched from a secret provider, eg Vault
+    SecretProvider {
+        k8s_api_url: String,
+        secret_provider: Arc<dyn SecretProvider>,
+    },
+    /// Kubeconfig file. Uses $KUBECONFIG or ~/.kube/config if no path is provided and the
+    /// current context if no context is provided
+    Kubeconfig {
+        path_opt: Option<PathBuf>,
+        context_opt: Option<String>,
+    },
+    /// Service account of the pod running this code
+    InCluster,
+    /// Base64 encoded PEM certificates, same format as in a kubeconfig file
+    CertData {
+        k8s_api_url: String,
+        certificate_authority_data: String,
+        client_certificate_data: String,
+        client_key_data: String,
+        /// Name to validate the API server certificate against, if it is not the host in
+        /// k8s_api_url
+        tls_server_name_opt: Option<String>,
+    },
+}
+
+impl K8sClientSource {
+    /// Cluster certificates as returned by `SecretProvider::get_k8s_secrets`
+    pub fn from_k8s_secrets(
+        k8s_api_url: &str,
+        shasta_k8s_secrets: &Value,
+    ) -> Result<Self, Box<dyn Error>> {
+        let get_secret = |key: &str| {
+            shasta_k8s_secrets[key]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| format!("'{}' not found in k8s secrets", key))
+        };
+
+        Ok(K8sClientSource::CertData {
+            k8s_api_url: k8s_api_url.to_string(),
+            certificate_authority_data: get_secret("certificate-authority-data")?,
+            client_certificate_data: get_secret("client-certificate-data")?,
+            client_key_data: get_secret("client-key-data")?,
+            tls_server_name_opt: Some(CSM_K8S_API_TLS_SERVER_NAME.to_string()),
+        })
+    }
+
+    pub async fn get_config(&self) -> Result<kube::Config, Box<dyn Error>> {
+        match self {
+            K8sClientSource::SecretProvider {
+                k8s_api_url,
+                secret_provider,
+            } => {
+                let shasta_k8s_secrets = secret_provider.get_k8s_secrets().await?;
+
+                Box::pin(
+                    K8sClientSource::from_k8s_secrets(k8s_api_url, &shasta_k8s_secrets)?
+                        .get_config(),
+                )
+                .await
+            }
+            K8sClientSource::Kubeconfig {
+                path_opt,
+                context_opt,
+            } => {
+                let kube_config = match path_opt {
+                    Some(path) => Kubeconfig::read_from(path)?,
+                    None => Kubeconfig::read()?,
+                };
+
+                let kube_config_options = KubeConfigOptions {
+                    context: context_opt.clone(),
+                    cluster: None,
+                    user: None,
+                };
+
+                Ok(kube::Config::from_custom_kubeconfig(kube_config, &kube_config_options).await?)
+            }
+            K8sClientSource::InCluster => Ok(kube::Config::incluster()?),
+            K8sClientSource::CertData {
+                k8s_api_url,
+                certificate_authority_data,
+                client_certificate_data,
+                client_key_data,
+                tls_server_name_opt,
+            } => {
+                get_cert_data_config(
+                    k8s_api_url,
+                    certificate_authority_data,
+                    client_certificate_data,
+                    client_key_data,
+                    tls_server_name_opt.as_deref(),
+                )
+                .await
+            }
+        }
+    }
+}
+
+async fn get_cert_data_config(
     k8s_api_url: &str,
-    shasta_k8s_secrets: Value,
-) -> Result<kube::Client, Box<dyn Error>> {
+    certificate_authority_data: &str,
+    client_certificate_data: &str,
+    client_key_data: &str,
+    tls_server_name_opt: Option<&str>,
+) -> Result<kube::Config, Box<dyn Error>> {
     let shasta_cluster = Cluster {
         server: Some(k8s_api_url.to_string()),
-        tls_server_name: Some("kube-apiserver".to_string()), // The value "kube-apiserver" has been taken from the
-        // Subject: CN value in the Shasta certificate running
-        // this command echo | openssl s_client -showcerts -servername 10.252.1.12 -connect 10.252.1.12:6442 2>/dev/null | openssl x509 -inform pem -noout -text
-        insecure_skip_tls_verify: Some(true),
+        tls_server_name: tls_server_name_opt.map(str::to_string),
+        insecure_skip_tls_verify: None,
         certificate_authority: None,
-        certificate_authority_data: Some(String::from(
-            shasta_k8s_secrets["certificate-authority-data"]
-                .as_str()
-                .unwrap(),
-        )),
+        certificate_authority_data: Some(certificate_authority_data.to_string()),
         proxy_url: None,
         extensions: None,
     };
@@ -54,16 +165,9 @@ pub async fn get_k8s_client_programmatically(
         token: None,
         token_file: None,
         client_certificate: None,
-        client_certificate_data: Some(String::from(
-            shasta_k8s_secrets["client-certificate-data"]
-                .as_str()
-                .unwrap(),
-        )),
+        client_certificate_data: Some(client_certificate_data.to_string()),
         client_key: None,
-        client_key_data: Some(
-            SecretString::from_str(shasta_k8s_secrets["client-key-data"].as_str().unwrap())
-                .unwrap(),
-        ),
+        client_key_data: Some(SecretString::from_str(client_key_data)?),
         impersonate: None,
         impersonate_groups: None,
         auth_provider: None,
@@ -104,164 +208,68 @@ pub async fn get_k8s_client_programmatically(
         user: Some(String::from("kubernetes-admin")),
     };
 
-    let config = kube::Config::from_custom_kubeconfig(kube_config, &kube_config_options).await?;
+    Ok(kube::Config::from_custom_kubeconfig(kube_config, &kube_config_options).await?)
+}
 
-    // OPTION 1 --> Native TLS - WORKING
-    /* let client = if std::env::var("SOCKS5").is_ok() {
-        log::debug!("SOCKS5 enabled");
-        let connector = {
-            let mut http = hyper::client::HttpConnector::new();
-            http.enforce_http(false);
-            let proxy = hyper_socks2::SocksConnector {
-                proxy_addr: std::env::var("SOCKS5").unwrap().parse::<Uri>().unwrap(),
+/// Creates a k8s client. If env var 'SOCKS5' is set, connections go through that SOCKS5 proxy
+pub async fn get_k8s_client(
+    k8s_client_source: &K8sClientSource,
+) -> Result<kube::Client, Box<dyn Error>> {
+    let config = k8s_client_source.get_config().await?;
+
+    let client = match std::env::var("SOCKS5") {
+        Ok(socks5_proxy) => {
+            log::debug!("SOCKS5 enabled");
+            let mut http_connector = hyper::client::HttpConnector::new();
+            http_connector.enforce_http(false);
+            let socks_http_connector = SocksConnector {
+                proxy_addr: socks5_proxy.parse::<Uri>()?, // scheme is required by HttpConnector
                 auth: None,
-                connector: http,
+                connector: http_connector,
             };
-            let mut native_tls_builder = native_tls::TlsConnector::builder();
-            native_tls_builder.danger_accept_invalid_certs(true);
-            native_tls_builder.danger_accept_invalid_hostnames(true);
-            native_tls_builder.use_sni(false);
-
-            let tls = tokio_native_tls::TlsConnector::from(config.native_tls_connector()?);
-            hyper_tls::HttpsConnector::from((proxy, tls))
-        };
-
-        let service = tower::ServiceBuilder::new()
-            .layer(config.base_uri_layer())
-            .option_layer(config.auth_layer()?)
-            .service(hyper::Client::builder().build(connector));
-
-        kube::Client::new(service, config.default_namespace)
-    } else {
-        let https = config.openssl_https_connector()?;
-        let service = tower::ServiceBuilder::new()
-            .layer(config.base_uri_layer())
-            .service(hyper::Client::builder().build(https));
-        kube::Client::new(service, config.default_namespace)
-    }; */
-
-    // OPTION 2 --> rustls - Not working. Probably failing hyper client
-    /* let client = if std::env::var("SOCKS5").is_ok() {
-        log::debug!("SOCKS5 enabled");
-        // let https = config.rustls_https_connector()?;
-        let rustls_config = std::sync::Arc::new(config.rustls_client_config()?);
-        println!("rustls_config:\n{:#?}", config.rustls_client_config());
-        let mut http_connector = hyper::client::HttpConnector::new();
-        http_connector.enforce_http(false);
-        let socks_http_connector = SocksConnector {
-            proxy_addr: std::env::var("SOCKS5").unwrap().parse::<Uri>().unwrap(), // scheme is required by HttpConnector
-            auth: None,
-            connector: http_connector.clone(),
-        };
-        // let socks = socks_http_connector.clone().with_tls()?;
-        let https_socks_http_connector = hyper_rustls::HttpsConnector::from((
-            socks_http_connector.clone(),
-            rustls_config.clone(),
-        ));
-
-        println!(
-            "https_socks_http_connector:\n{:#?}",
-            https_socks_http_connector
-        );
-        // let https_http_connector = hyper_rustls::HttpsConnector::from((http_connector, rustls_config));
-        let service = tower::ServiceBuilder::new()
-            .layer(config.base_uri_layer())
-            .service(hyper::Client::builder().build(https_socks_http_connector));
-        kube::Client::new(service, config.default_namespace)
-    } else {
-        let https = config.openssl_https_connector()?;
-        let service = tower::ServiceBuilder::new()
-            .layer(config.base_uri_layer())
-            .service(hyper::Client::builder().build(https));
-        kube::Client::new(service, config.default_namespace)
-    }; */
-
-    let client = if std::env::var("SOCKS5").is_ok() {
-        log::debug!("SOCKS5 enabled");
-        let mut http_connector = hyper::client::HttpConnector::new();
-        http_connector.enforce_http(false);
-        let socks_http_connector = SocksConnector {
-            proxy_addr: std::env::var("SOCKS5").unwrap().parse::<Uri>().unwrap(), // scheme is required by HttpConnector
-            auth: None,
-            connector: http_connector.clone(),
-        };
-
-        // HttpsConnector following https://github.com/rustls/hyper-rustls/blob/main/examples/client.rs
-        // Get CA root cert
-        let mut ca_root_cert_pem_decoded: &[u8] = &base64::decode(
-            shasta_k8s_secrets["certificate-authority-data"]
-                .as_str()
-                .unwrap(),
-        )?;
-
-        let ca_root_cert = rustls_pemfile::certs(&mut ca_root_cert_pem_decoded)?;
-
-        // Import CA cert into rustls ROOT certificate store
-        let mut root_cert_store = tokio_rustls::rustls::RootCertStore::empty();
-
-        root_cert_store.add_parsable_certificates(&ca_root_cert);
 
-        // Prepare client authentication https://github.com/rustls/rustls/blob/0018e7586c2dc689eb9e1ba8e0283c0f24b9fe8c/examples/src/bin/tlsclient-mio.rs#L414-L426
-        // Get client cert
-        let mut client_cert_pem_decoded: &[u8] = &base64::decode(
-            shasta_k8s_secrets["client-certificate-data"]
-                .as_str()
-                .unwrap(),
-        )?;
-
-        let client_certs = rustls_pemfile::certs(&mut client_cert_pem_decoded)
-            .unwrap()
-            .iter()
-            .map(|cert| tokio_rustls::rustls::Certificate(cert.clone()))
-            .collect();
-
-        // Get client key
-        let mut client_key_decoded: &[u8] =
-            &base64::decode(shasta_k8s_secrets["client-key-data"].as_str().unwrap())?;
-
-        let client_key = match rustls_pemfile::read_one(&mut client_key_decoded)
-            .expect("cannot parse private key .pem file")
-        {
-            Some(rustls_pemfile::Item::RSAKey(key)) => tokio_rustls::rustls::PrivateKey(key),
-            Some(rustls_pemfile::Item::PKCS8Key(key)) => tokio_rustls::rustls::PrivateKey(key),
-            Some(rustls_pemfile::Item::ECKey(key)) => tokio_rustls::rustls::PrivateKey(key),
-            _ => tokio_rustls::rustls::PrivateKey(Vec::new()),
-        };
-
-        // Create HTTPS connector
-        let rustls_config = tokio_rustls::rustls::ClientConfig::builder()
-            .with_safe_defaults()
-            .with_root_certificates(root_cert_store)
-            // .with_no_client_auth();
-            .with_client_auth_cert(client_certs, client_key)?;
-
-        let rustls_config = std::sync::Arc::new(rustls_config);
-
-        let args = (socks_http_connector, rustls_config);
-        let https_socks_http_connector = hyper_rustls::HttpsConnector::from(args);
-
-        /* let https_socks_http_connector = socks_http_connector
-        .with_rustls_root_cert_store(root_cert_store); */
+            // TLS config (CA, client certs and whether to verify the server) comes from the
+            // kube config
+            let https_connector_builder = hyper_rustls::HttpsConnectorBuilder::new()
+                .with_tls_config(config.rustls_client_config()?)
+                .https_or_http();
+
+            let https_connector_builder = match &config.tls_server_name {
+                Some(tls_server_name) => {
+                    https_connector_builder.with_server_name(tls_server_name.clone())
+                }
+                None => https_connector_builder,
+            };
 
-        // Create HTTPS client
-        let hyper_client = hyper::Client::builder().build(https_socks_http_connector);
+            let https_socks_http_connector = https_connector_builder
+                .enable_http1()
+                .wrap_connector(socks_http_connector);
 
-        let service = tower::ServiceBuilder::new()
-            .layer(config.base_uri_layer())
-            .service(hyper_client);
+            let service = tower::ServiceBuilder::new()
+                .layer(config.base_uri_layer())
+                .option_layer(config.auth_layer()?)
+                .service(hyper::Client::builder().build(https_socks_http_connector));
 
-        kube::Client::new(service, config.default_namespace)
-    } else {
-        let https = config.openssl_https_connector()?;
-        let service = tower::ServiceBuilder::new()
-            .layer(config.base_uri_layer())
-            .service(hyper::Client::builder().build(https));
-        kube::Client::new(service, config.default_namespace)
+            kube::Client::new(service, config.default_namespace)
+        }
+        Err(_) => kube::Client::try_from(config)?,
     };
 
     Ok(client)
 }
 
+/// Creates a k8s client from the cluster certificates stored in Vault
+pub async fn get_k8s_client_programmatically(
+    k8s_api_url: &str,
+    shasta_k8s_secrets: Value,
+) -> Result<kube::Client, Box<dyn Error>> {
+    get_k8s_client(&K8sClientSource::from_k8s_secrets(
+        k8s_api_url,
+        &shasta_k8s_secrets,
+    )?)
+    .await
+}
+
 pub async fn get_init_container_logs_stream(
     cfs_session_layer_container: &Container,
     cfs_session_pod: &Pod,
@@ -272,18 +280,21 @@ pub async fn get_init_container_logs_stream(
         cfs_session_layer_container.name
     );
 
-    println!(
-        "\n{}####{} Init container {}'{}'{} logs\n",
-        color::Fg(color::Green),
-        color::Fg(color::Reset),
-        color::Fg(color::Blue),
+    let cfs_session_pod_name = cfs_session_pod
+        .metadata
+        .name
+        .as_ref()
+        .ok_or("Pod for cfs session has no name")?;
+
+    log::info!(
+        "Init container '{}' logs of pod '{}'",
         cfs_session_layer_container.name,
-        color::Fg(color::Reset),
+        cfs_session_pod_name
     );
 
     let container_log_stream = pods_api
         .log_stream(
-            cfs_session_pod.metadata.name.as_ref().unwrap(),
+            cfs_session_pod_name,
             &kube::api::LogParams {
                 follow: true,
                 container: Some(cfs_session_layer_container.name.clone()),
@@ -309,18 +320,21 @@ pub async fn get_container_logs_stream(
         cfs_session_layer_container.name
     );
 
-    println!(
-        "\n{}####{} Container {}'{}'{} logs\n",
-        color::Fg(color::Green),
-        color::Fg(color::Reset),
-        color::Fg(color::Blue),
+    let cfs_session_pod_name = cfs_session_pod
+        .metadata
+        .name
+        .as_ref()
+        .ok_or("Pod for cfs session has no name")?;
+
+    log::info!(
+        "Container '{}' logs of pod '{}'",
         cfs_session_layer_container.name,
-        color::Fg(color::Reset),
+        cfs_session_pod_name
     );
 
     let container_log_stream = pods_api
         .log_stream(
-            cfs_session_pod.metadata.name.as_ref().unwrap(),
+            cfs_session_pod_name,
             &kube::api::LogParams {
                 follow: true,
                 container: Some(cfs_session_layer_container.name.clone()),
@@ -336,26 +350,31 @@ pub async fn get_container_logs_stream(
     Ok(container_log_stream)
 }
 
-pub async fn print_cfs_session_logs(client: kube::Client, cfs_session_name: &str) {
+/// Prints the git-clone and ansible container logs of a CFS session to stdout
+pub async fn print_cfs_session_logs(
+    client: kube::Client,
+    cfs_session_name: &str,
+) -> Result<(), Box<dyn Error + Sync + Send>> {
     let logs_stream_rslt =
         get_cfs_session_container_git_clone_logs_stream(client.clone(), cfs_session_name).await;
 
     match logs_stream_rslt {
         Ok(mut logs_stream) => {
-            while let Some(line) = logs_stream.try_next().await.unwrap() {
+            while let Some(line) = logs_stream.try_next().await? {
                 println!("{}", line);
             }
         }
         Err(error_msg) => log::error!("{}", error_msg),
     }
 
-    let mut logs_stream = get_cfs_session_container_ansible_logs_stream(client, cfs_session_name)
-        .await
-        .unwrap();
+    let mut logs_stream =
+        get_cfs_session_container_ansible_logs_stream(client, cfs_session_name).await?;
 
-    while let Some(line) = logs_stream.try_next().await.unwrap() {
+    while let Some(line) = logs_stream.try_next().await? {
         println!("{}", line);
     }
+
+    Ok(())
 }
 
 pub async fn get_cfs_session_container_git_clone_logs_stream(
@@ -366,197 +385,34 @@ pub async fn get_cfs_session_container_git_clone_logs_stream(
 
     let pods_api: kube::Api<Pod> = kube::Api::namespaced(client, "services");
 
-    let params = kube::api::ListParams::default()
-        .limit(1)
-        .labels(format!("cfsession={}", cfs_session_name).as_str());
-
-    let mut pods = pods_api.list(&params).await?;
-
-    let mut i = 0;
-    let max = 300;
-    let delay_secs = 2;
-
-    // Waiting for pod to start
-    while pods.items.is_empty() && i <= max {
-        println!(
-            "Pod for cfs session '{}' not ready. Trying again in {} secs. Attempt {} of {}",
-            cfs_session_name,
-            delay_secs,
-            i + 1,
-            max
-        );
-        i += 1;
-        tokio::time::sleep(time::Duration::from_secs(delay_secs)).await;
-        pods = pods_api.list(&params).await?;
-    }
-
-    if pods.items.is_empty() {
-        return Err(format!(
-            "Pod for cfs session {}; not ready. Aborting operation",
-            cfs_session_name
-        )
-        .into());
-    }
-
-    let params = kube::api::ListParams::default()
-        .limit(1)
-        .labels(format!("cfsession={}", cfs_session_name).as_str());
-
-    let mut pods = pods_api.list(&params).await?;
-
-    let mut i = 0;
-    let max = 300;
-    let delay_secs = 2;
-
-    // Waiting for pod to start
-    while pods.items.is_empty() && i <= max {
-        println!(
-            "Pod for cfs session '{}' not ready. Trying again in {} secs. Attempt {} of {}",
-            cfs_session_name,
-            delay_secs,
-            i + 1,
-            max
-        );
-        i += 1;
-        tokio::time::sleep(time::Duration::from_secs(delay_secs)).await;
-        pods = pods_api.list(&params).await?;
-    }
-
-    if pods.items.is_empty() {
-        return Err(format!(
-            "Pod for cfs session {}; not ready. Aborting operation",
-            cfs_session_name
-        )
-        .into());
-    }
-
-    let cfs_session_pod = &pods.items[0].clone();
+    let cfs_session_pod = wait_for_container(
+        &pods_api,
+        &format!("cfsession={}", cfs_session_name),
+        init_container_name,
+        POD_WAIT_TIMEOUT,
+    )
+    .await
+    .map_err(|error| error.to_string())?;
 
-    let cfs_session_pod_name = cfs_session_pod.metadata.name.clone().unwrap();
-    log::info!("Pod name: {}", cfs_session_pod_name);
+    log::info!("Pod name: {:?}", cfs_session_pod.metadata.name);
 
-    let mut init_container_vec = cfs_session_pod
+    let git_clone_container: &Container = cfs_session_pod
         .spec
         .as_ref()
-        .unwrap()
-        .init_containers
-        .clone()
-        .unwrap();
-
-    log::debug!(
-        "Init containers found in pod {}: {:#?}",
-        cfs_session_pod_name,
-        init_container_vec
-    );
-
-    let mut git_clone_container: &Container = init_container_vec
-        .iter()
-        .find(|container| container.name.eq(init_container_name))
-        .unwrap();
-
-    log::info!(
-        "Fetching logs for contaianer {} in namespace/pod {}/{}",
-        init_container_name,
-        cfs_session_pod.clone().metadata.namespace.unwrap(),
-        cfs_session_pod.clone().metadata.name.unwrap(),
-    );
-
-    let init_container_status = cfs_session_pod
-        .status
-        .clone()
-        .unwrap()
-        .init_container_statuses
-        .unwrap()
-        .into_iter()
-        .find(|init_container| init_container.name.eq(&git_clone_container.name));
-
-    log::debug!("Container status:\n{:#?}", init_container_status);
-
-    let mut i = 0;
-    let max = 30;
-
-    /* // Check init container is terminated
-    if init_container_status
-            .clone()
-            .unwrap()
-            .state
-            .unwrap()
-            .terminated
-            .is_some() {
-
-        return Err(format!("Init container {} terminated", init_container_name)
-        .into());
-    } */
-
-    // Waiting for init container to start
-    while (init_container_status.is_none()
-        || init_container_status
-            .clone()
-            .unwrap()
-            .state
-            .unwrap()
-            .waiting
-            .is_some()/* || !init_container_status
-    .clone()
-    .unwrap()
-    .state
-    .unwrap()
-    .terminated
-    .is_some() */)
-        && i <= max
-    {
-        log::info!(
-            "Container {} state {:?}",
-            git_clone_container.name,
-            init_container_status.clone().unwrap().state.unwrap()
-        );
-        println!(
-            "Waiting for container '{}' to be ready. Checking again in 2 secs. Attempt {} of {}",
-            git_clone_container.name,
-            i + 1,
-            max
-        );
-
-        i += 1;
-        tokio::time::sleep(time::Duration::from_secs(2)).await;
-
-        let cfs_session_pod = pods_api.list(&params).await?.items[0].clone();
-
-        init_container_vec = cfs_session_pod
-            .spec
-            .as_ref()
-            .unwrap()
-            .init_containers
-            .clone()
-            .unwrap();
-
-        git_clone_container = init_container_vec
-            .iter()
-            .find(|container| container.name.eq("git-clone"))
-            .unwrap();
-
-        log::debug!(
-            "Container status:\n{:#?}",
-            init_container_status.as_ref().unwrap()
-        );
-    }
-
-    if init_container_status.is_none()
-        || init_container_status
-            .unwrap()
-            .state
-            .unwrap()
-            .waiting
-            .is_some()
-    {
-        return Err(format!(
-            "Container '{}' not ready. Aborting operation",
-            init_container_name
-        )
-        .into());
-    }
-
-    get_init_container_logs_stream(git_clone_container, cfs_session_pod, &pods_api).await
+        .and_then(|pod_spec| pod_spec.init_containers.as_ref())
+        .and_then(|init_container_vec| {
+            init_container_vec
+                .iter()
+                .find(|container| container.name.eq(init_container_name))
+        })
+        .ok_or_else(|| {
+            format!(
+                "Init container '{}' not found in pod for cfs session '{}'",
+                init_container_name, cfs_session_name
+            )
+        })?;
+
+    get_init_container_logs_stream(git_clone_container, &cfs_session_pod, &pods_api).await
 }
 
 pub async fn get_cfs_session_container_ansible_logs_stream(
@@ -567,235 +423,267 @@ pub async fn get_cfs_session_container_ansible_logs_stream(
 
     let pods_api: kube::Api<Pod> = kube::Api::namespaced(client, "services");
 
-    let params = kube::api::ListParams::default()
-        .limit(1)
-        .labels(format!("cfsession={}", cfs_session_name).as_str());
-
-    let mut pods = pods_api.list(&params).await?;
-
-    let mut i = 0;
-    let max = 300;
-    let delay_secs = 2;
-
-    // Waiting for pod to start
-    while pods.items.is_empty() && i <= max {
-        println!(
-            "Pod for cfs session {} not ready. Trying again in {} secs. Attempt {} of {}",
-            cfs_session_name,
-            delay_secs,
-            i + 1,
-            max
-        );
-        i += 1;
-        tokio::time::sleep(time::Duration::from_secs(delay_secs)).await;
-        pods = pods_api.list(&params).await?;
-    }
-
-    if pods.items.is_empty() {
-        return Err(format!(
-            "Pod for cfs session {} not ready. Aborting operation",
-            cfs_session_name
-        )
-        .into());
-    }
+    let cfs_session_pod = wait_for_container(
+        &pods_api,
+        &format!("cfsession={}", cfs_session_name),
+        container_name,
+        POD_WAIT_TIMEOUT,
+    )
+    .await
+    .map_err(|error| error.to_string())?;
 
-    let cfs_session_pod = &pods.items[0].clone();
+    log::info!("Pod name: {:?}", cfs_session_pod.metadata.name);
 
-    let cfs_session_pod_name = cfs_session_pod.metadata.name.clone().unwrap();
-    log::info!("Pod name: {}", cfs_session_pod_name);
-
-    let mut containers = cfs_session_pod.spec.as_ref().unwrap().containers.iter();
-
-    log::debug!(
-        "Containers found in pod {}: {:#?}",
-        cfs_session_pod_name,
-        containers
-    );
-
-    let ansible_container: &Container = containers
-        .find(|container| container.name.eq(container_name))
-        .unwrap();
+    let ansible_container: &Container = cfs_session_pod
+        .spec
+        .as_ref()
+        .and_then(|pod_spec| {
+            pod_spec
+                .containers
+                .iter()
+                .find(|container| container.name.eq(container_name))
+        })
+        .ok_or_else(|| {
+            format!(
+                "Container '{}' not found in pod for cfs session '{}'",
+                container_name, cfs_session_name
+            )
+        })?;
+
+    get_container_logs_stream(ansible_container, &cfs_session_pod, &pods_api).await
+}
 
-    let mut container_status = get_container_status(cfs_session_pod, &ansible_container.name);
+/// Returns the state of a container or init container in a pod
+pub fn get_container_state(pod: &Pod, container_name: &str) -> Option<ContainerState> {
+    let pod_status = pod.status.as_ref()?;
 
-    let mut i = 0;
-    let max = 300;
+    pod_status
+        .container_statuses
+        .iter()
+        .chain(pod_status.init_container_statuses.iter())
+        .flatten()
+        .find(|container_status| container_status.name.eq(container_name))
+        .and_then(|container_status| container_status.state.clone())
+}
 
-    // Waiting for container ansible-x to start
-    while container_status.as_ref().is_none()
-        || container_status.as_ref().unwrap().waiting.is_some() && i <= max
-    {
-        println!(
-            "Waiting for container '{}' to be ready. Checking again in 2 secs. Attempt {} of {}",
-            ansible_container.name,
-            i + 1,
-            max
-        );
-        i += 1;
-        tokio::time::sleep(time::Duration::from_secs(2)).await;
-        let pods = pods_api.list(&params).await?;
-        container_status = get_container_status(&pods.items[0], &ansible_container.name);
-        log::debug!(
-            "Container status:\n{:#?}",
-            container_status.as_ref().unwrap()
-        );
-    }
+/// Waits for a pod matching a label selector to exist and for the condition to be true. Returns
+/// the pod once the condition is met or an error if the timeout expires
+pub async fn wait_for_pod<F>(
+    pods_api: &Api<Pod>,
+    label_selector: &str,
+    timeout: Duration,
+    condition: F,
+) -> Result<Pod, Box<dyn Error>>
+where
+    F: Fn(&Pod) -> bool,
+{
+    log::info!("Waiting for pod with labels '{}'", label_selector);
+
+    let mut pod_stream = watcher(
+        pods_api.clone(),
+        watcher::Config::default().labels(label_selector),
+    )
+    .applied_objects()
+    .boxed();
+
+    let wait_for_condition = async {
+        while let Some(pod) = pod_stream.try_next().await? {
+            log::debug!(
+                "Pod '{:?}' phase {:?}",
+                pod.metadata.name,
+                pod.status
+                    .as_ref()
+                    .and_then(|pod_status| pod_status.phase.as_ref())
+            );
+
+            if condition(&pod) {
+                return Ok(pod);
+            }
+        }
 
-    if container_status.as_ref().unwrap().waiting.is_some() {
-        return Err(format!(
-            "Container '{}' not ready. Aborting operation",
-            ansible_container.name
+        Err::<Pod, Box<dyn Error>>(
+            format!("Watch on pods with labels '{}' closed", label_selector).into(),
         )
-        .into());
-    }
+    };
 
-    get_container_logs_stream(ansible_container, cfs_session_pod, &pods_api).await
+    tokio::time::timeout(timeout, wait_for_condition)
+        .await
+        .map_err(|_| {
+            format!(
+                "Pod with labels '{}' not ready after {} secs. Aborting operation",
+                label_selector,
+                timeout.as_secs()
+            )
+        })?
 }
 
-fn get_container_status(
-    pod: &k8s_openapi::api::core::v1::Pod,
-    container_name: &String,
-) -> Option<k8s_openapi::api::core::v1::ContainerState> {
-    let container_status = pod
-        .status
-        .as_ref()
-        .unwrap()
-        .container_statuses
-        .as_ref()
-        .and_then(|status_vec| {
-            status_vec
-                .iter()
-                .find(|container_status| container_status.name.eq(container_name))
-        });
-
-    match container_status {
-        Some(container_status_aux) => container_status_aux.state.clone(),
-        None => None,
-    }
+/// Waits for a container (or init container) in the pod matching a label selector to be running
+/// or terminated. Returns the pod
+pub async fn wait_for_container(
+    pods_api: &Api<Pod>,
+    label_selector: &str,
+    container_name: &str,
+    timeout: Duration,
+) -> Result<Pod, Box<dyn Error>> {
+    wait_for_pod(pods_api, label_selector, timeout, |pod| {
+        get_container_state(pod, container_name).is_some_and(|container_state| {
+            container_state.running.is_some() || container_state.terminated.is_some()
+        })
+    })
+    .await
 }
 
-pub async fn attach_cfs_session_container_target_k8s_service_name(
-    client: kube::Client,
-    cfs_session_name: &str,
-) -> AttachedProcess {
-    let pods_fabric: Api<Pod> = Api::namespaced(client.clone(), "services");
-
-    let params = kube::api::ListParams::default()
-        .limit(1)
-        .labels(format!("cfsession={}", cfs_session_name).as_str());
-
-    let mut pods = pods_fabric.list(&params).await.unwrap();
-
-    let mut i = 0;
-    let max = 300;
-
-    // Waiting for pod to start
-    while pods.items.is_empty() && i <= max {
-        println!(
-            "Pod for cfs session {} not ready. Trying again in 2 secs. Attempt {} of {}",
-            cfs_session_name,
-            i + 1,
-            max
-        );
-        i += 1;
-        tokio::time::sleep(time::Duration::from_secs(2)).await;
-        pods = pods_fabric.list(&params).await.unwrap();
-    }
+/// Output of a command executed in a container
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecOutput {
+    pub stdout: String,
+    pub stderr: String,
+    /// Status reported by k8s once the command finished
+    pub status_opt: Option<Status>,
+}
 
-    if pods.items.is_empty() {
-        eprintln!(
-            "Pod for cfs session {} not ready. Aborting operation",
-            cfs_session_name
-        );
-        std::process::exit(1);
+impl ExecOutput {
+    pub fn is_success(&self) -> bool {
+        self.status_opt
+            .as_ref()
+            .and_then(|status| status.status.as_deref())
+            == Some("Success")
     }
+}
 
-    let console_operator_pod = &pods.items[0].clone();
-
-    let console_operator_pod_name = console_operator_pod.metadata.name.clone().unwrap();
+/// Runs a command in a container and waits for it to finish
+pub async fn exec(
+    pods_api: &Api<Pod>,
+    pod_name: &str,
+    container_name: &str,
+    command: Vec<&str>,
+) -> Result<ExecOutput, Box<dyn Error>> {
+    log::debug!(
+        "Running command {:?} in container '{}' pod '{}'",
+        command,
+        container_name,
+        pod_name
+    );
 
-    let attached = pods_fabric
+    let mut attached = pods_api
         .exec(
-            &console_operator_pod_name,
-            vec![
-                "sh",
-                "-c",
-                "cat /inventory/hosts/01-cfs-generated.yaml | grep cray-ims- | head -n 1",
-            ],
+            pod_name,
+            command,
             &AttachParams::default()
-                .container("cray-console-operator")
-                .stderr(false),
+                .container(container_name)
+                .stdin(false)
+                .stdout(true)
+                .stderr(true),
         )
-        .await
-        .unwrap();
-
-    let mut output = get_output(attached).await;
-    log::info!("{output}");
+        .await?;
 
-    output = output.trim().to_string();
-
-    println!("{output}");
-
-    output
-        .strip_prefix("ansible_host: ")
-        .unwrap()
-        .strip_suffix("-service.ims.svc.cluster.local")
-        .unwrap();
+    let stdout_opt = attached.stdout();
+    let stderr_opt = attached.stderr();
+    let status_future_opt = attached.take_status();
 
-    println!("{output}");
+    // stdout and stderr are read concurrently, otherwise the command may block writing to one
+    // of them
+    let (stdout, stderr) =
+        tokio::try_join!(read_to_string(stdout_opt), read_to_string(stderr_opt))?;
 
-    let ansible_target_container_label = output + "-customize";
-
-    println!("{ansible_target_container_label}");
+    let status_opt = match status_future_opt {
+        Some(status_future) => status_future.await,
+        None => None,
+    };
 
-    // Find ansible target container
+    attached.join().await?;
 
-    let pods_fabric: Api<Pod> = Api::namespaced(client, "ims");
+    Ok(ExecOutput {
+        stdout,
+        stderr,
+        status_opt,
+    })
+}
 
-    let params = kube::api::ListParams::default()
-        .limit(1)
-        .labels(format!("job-name={}", ansible_target_container_label).as_str());
+async fn read_to_string<R: AsyncRead + Unpin>(
+    reader_opt: Option<R>,
+) -> Result<String, std::io::Error> {
+    let mut buffer = Vec::new();
 
-    let mut pods = pods_fabric.list(&params).await.unwrap();
+    if let Some(mut reader) = reader_opt {
+        reader.read_to_end(&mut buffer).await?;
+    }
 
-    let mut i = 0;
-    let max = 300;
+    Ok(String::from_utf8_lossy(&buffer).to_string())
+}
 
-    // Waiting for pod to start
-    while pods.items.is_empty() && i <= max {
-        println!(
-            "Pod for cfs session {} not ready. Trying again in 2 secs. Attempt {} of {}",
-            cfs_session_name,
-            i + 1,
-            max
-        );
-        i += 1;
-        tokio::time::sleep(time::Duration::from_secs(2)).await;
-        pods = pods_fabric.list(&params).await.unwrap();
-    }
+/// Returns the name of the IMS job from the ansible inventory line of an image customization CFS
+/// session, eg 'ansible_host: cray-ims-<id>-service.ims.svc.cluster.local' --> 'cray-ims-<id>'
+pub fn get_ims_job_name(inventory_line: &str) -> Option<String> {
+    inventory_line
+        .trim()
+        .strip_prefix("ansible_host: ")?
+        .strip_suffix("-service.ims.svc.cluster.local")
+        .map(str::to_string)
+}
 
-    if pods.items.is_empty() {
-        eprintln!(
-            "Pod for cfs session {} not ready. Aborting operation",
+/// Opens an interactive shell in the IMS pod an image customization CFS session is configuring
+pub async fn attach_cfs_session_container_target_k8s_service_name(
+    client: kube::Client,
+    cfs_session_name: &str,
+) -> Result<AttachedProcess, Box<dyn Error>> {
+    let services_pods_api: Api<Pod> = Api::namespaced(client.clone(), "services");
+
+    let cfs_session_pod = wait_for_container(
+        &services_pods_api,
+        &format!("cfsession={}", cfs_session_name),
+        "ansible",
+        POD_WAIT_TIMEOUT,
+    )
+    .await?;
+
+    let cfs_session_pod_name = cfs_session_pod
+        .metadata
+        .name
+        .ok_or("Pod for cfs session has no name")?;
+
+    log::info!("Ansible pod name: {}", cfs_session_pod_name);
+
+    let exec_output = exec(
+        &services_pods_api,
+        &cfs_session_pod_name,
+        "ansible",
+        vec![
+            "sh",
+            "-c",
+            "cat /inventory/hosts/01-cfs-generated.yaml | grep cray-ims- | head -n 1",
+        ],
+    )
+    .await?;
+
+    log::info!("{}", exec_output.stdout);
+
+    let ims_job_name = get_ims_job_name(&exec_output.stdout).ok_or_else(|| {
+        format!(
+            "IMS target not found in ansible inventory of cfs session '{}'",
             cfs_session_name
-        );
-        std::process::exit(1);
-    }
+        )
+    })?;
 
-    let console_operator_pod = &pods.items[0].clone();
+    // Find ansible target container
+    let ims_pods_api: Api<Pod> = Api::namespaced(client, "ims");
 
-    log::info!("Connecting to console ansible target container");
+    let ims_pod = wait_for_container(
+        &ims_pods_api,
+        &format!("job-name={}-customize", ims_job_name),
+        "sshd",
+        POD_WAIT_TIMEOUT,
+    )
+    .await?;
 
-    let console_operator_pod_name = console_operator_pod.metadata.name.clone().unwrap();
+    let ims_pod_name = ims_pod.metadata.name.ok_or("IMS pod has no name")?;
 
-    let command = vec!["bash"]; // Enter the container and open conman to access node's console
-                                // let command = vec!["bash"]; // Enter the container and open bash to start an interactive
-                                // terminal session
+    log::info!("Connecting to console ansible target container");
 
-    let attachment_rslt = pods_fabric
+    let attachment = ims_pods_api
         .exec(
-            &console_operator_pod_name,
-            command,
+            &ims_pod_name,
+            vec!["bash"],
             &AttachParams::default()
                 .container("sshd")
                 .stdin(true)
@@ -803,28 +691,23 @@ pub async fn attach_cfs_session_container_target_k8s_service_name(
                 .stderr(false) // Note to self: tty and stderr cannot both be true
                 .tty(true),
         )
-        .await;
-
-    if let Ok(attachment) = attachment_rslt {
-        attachment
-    } else {
-        eprintln!(
-            "Error attaching to container 'sshd' in pod {}. Exit",
-            console_operator_pod_name
-        );
-        std::process::exit(1);
-    }
+        .await?;
+
+    Ok(attachment)
 }
 
-pub async fn get_output(mut attached: AttachedProcess) -> String {
-    let stdout = tokio_util::io::ReaderStream::new(attached.stdout().unwrap());
+pub async fn get_output(mut attached: AttachedProcess) -> Result<String, Box<dyn Error>> {
+    let stdout = tokio_util::io::ReaderStream::new(
+        attached.stdout().ok_or("Attached process has no stdout")?,
+    );
     let out = stdout
         .filter_map(|r| async { r.ok().and_then(|v| String::from_utf8(v.to_vec()).ok()) })
         .collect::<Vec<_>>()
         .await
         .join("");
-    attached.join().await.unwrap();
-    out
+    attached.join().await?;
+
+    Ok(out)
 }
 
 #[cfg(test)]
@@ -838,7 +721,18 @@ mod test {
 
     use crate::common::vault::http_client::fetch_shasta_k8s_secrets;
 
-    use super::get_k8s_client_programmatically;
+    use super::{get_ims_job_name, get_k8s_client_programmatically};
+
+    #[test]
+    fn test_get_ims_job_name() {
+        assert_eq!(
+            get_ims_job_name(
+                "    ansible_host: cray-ims-0b8e7a2c-5a5e-4c3b-9d4c-7d0e6b4f2a11-service.ims.svc.cluster.local\n"
+            ),
+            Some("cray-ims-0b8e7a2c-5a5e-4c3b-9d4c-7d0e6b4f2a11".to_string())
+        );
+        assert_eq!(get_ims_job_name("x3000c0s1b0n0"), None);
+    }
 
     #[tokio::test]
     async fn test_connection_to_k8s() {
@@ -850,7 +744,9 @@ mod test {
         let pod_name = "cfs-7e54c14a-89fb-4564-886e-d11d69866212-d25rn";
 
         let shasta_k8s_secrets =
-            fetch_shasta_k8s_secrets(vault_base_url, vault_secret_path, vault_role_id).await;
+            fetch_shasta_k8s_secrets(vault_base_url, vault_secret_path, vault_role_id)
+                .await
+                .unwrap();
 
         let client = get_k8s_client_programmatically(k8s_api_url, shasta_k8s_secrets)
             .await
@@ -870,7 +766,9 @@ mod test {
             .await
             .unwrap();
 
-        let output = crate::common::kubernetes::get_output(attached).await;
+        let output = crate::common::kubernetes::get_output(attached)
+            .await
+            .unwrap();
 
         println!("Current CFS configurarion layer is {}", output);
     }
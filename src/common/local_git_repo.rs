@@ -1,12 +1,14 @@
 #![allow(dead_code, unused_imports)] // TODO: to avoid compiler from complaining about unused methods
 
+use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
 // Code below inspired on https://github.com/rust-lang/git2-rs/issues/561
-use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 
-use dialoguer::{Input, Password};
-use git2::{Commit, ObjectType, PushOptions, Remote, Repository};
+use git2::{
+    Commit, Cred, ObjectType, Oid, PushOptions, Remote, RemoteCallbacks, Repository, Signature,
+};
 
 pub fn get_repo(repo_path: &str) -> Result<Repository, git2::Error> {
     let repo_root = PathBuf::from(repo_path);
@@ -116,150 +118,183 @@ pub fn add_all(repo: &Repository) {
     index.write().unwrap();
 }
 
-pub fn commit(repo: &Repository) {
-    let mut index = repo.index().unwrap();
-    let oid = index.write_tree().unwrap();
-    let signature = repo.signature().unwrap();
-    let parent_commit = repo.head().unwrap().peel_to_commit().unwrap();
-    let tree = repo.find_tree(oid).unwrap();
+/// Commits the index. Author and committer are the user in git config if no author is provided.
+/// Returns the id of the new commit
+pub fn commit(
+    repo: &Repository,
+    message: &str,
+    author_opt: Option<&Signature>,
+) -> Result<Oid, git2::Error> {
+    let mut index = repo.index()?;
+    let oid = index.write_tree()?;
+    let tree = repo.find_tree(oid)?;
+
+    let signature = match author_opt {
+        Some(author) => author.to_owned(),
+        None => repo.signature()?,
+    };
+
+    // First commit in the repo has no parent
+    let parent_commit_vec = match repo.head() {
+        Ok(head) => vec![head.peel_to_commit()?],
+        Err(_) => Vec::new(),
+    };
+
     repo.commit(
         Some("HEAD"),
         &signature,
         &signature,
-        "testing git2-rs... commit created programatically...",
+        message,
         &tree,
-        &[&parent_commit],
+        &parent_commit_vec.iter().collect::<Vec<&Commit>>(),
     )
-    .unwrap();
 }
 
-pub fn push(mut remote: Remote) -> Result<(), git2::Error> {
-    // Configure callbacks for push operation
-    let mut callbacks = git2::RemoteCallbacks::new();
+/// How to authenticate against a git remote
+#[derive(Clone)]
+pub enum GitCredentials {
+    /// Keys loaded in ssh-agent
+    SshAgent,
+    SshKey {
+        private_key_path: PathBuf,
+        passphrase_opt: Option<String>,
+    },
+    /// Username and token (eg Gitea token) over HTTPS
+    Token { username: String, token: String },
+    /// Credential helper configured in git config
+    CredentialHelper,
+}
 
-    // TODO: CLEAN THIS!!!
-    callbacks.credentials(|_url, username_from_url, _allowed_types| {
-        log::debug!("url is: {}", _url);
-        log::debug!(
-            "username from url is: {}",
-            username_from_url.unwrap_or("Not defined")
-        ); // IMPORTANT: username from url is None because .git/config has https address 'url = https://git.cscs.ch/msopena/manta.git'
-        log::debug!("allowed types are: {:#?}", _allowed_types);
-
-        if username_from_url.unwrap().eq("git") {
-            // ssh authentication
-            git2::Cred::ssh_key(
-                username_from_url.unwrap(),
-                None,
-                std::path::Path::new(&format!(
-                    "{}/.ssh/gitlab_vcef",
-                    std::env::var("HOME").expect("key file ~/.ssh/gitlab_vcef not found")
-                )),
-                None,
-            )
-        } else {
-            // plain username and password authentication
-            let username: String = Input::new()
-                .with_prompt("Username: ")
-                .interact_text()
-                .unwrap();
-
-            let password = Password::new()
-                .with_prompt("New Password")
-                .with_confirmation("Confirm password", "Passwords mismatching")
-                .interact()
-                .unwrap();
-
-            git2::Cred::userpass_plaintext(&username, &password) // IMPORTANT: this with combination of .git/config having an https address 'url = https://git.cscs.ch/msopena/manta.git' makes library to switch to CredentialType::USER_PASS_PLAINTEXT
+impl fmt::Debug for GitCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GitCredentials::SshAgent => f.write_str("SshAgent"),
+            GitCredentials::SshKey {
+                private_key_path,
+                passphrase_opt,
+            } => f
+                .debug_struct("SshKey")
+                .field("private_key_path", private_key_path)
+                .field(
+                    "passphrase_opt",
+                    &passphrase_opt.as_ref().map(|_| "<redacted>"),
+                )
+                .finish(),
+            GitCredentials::Token { username, .. } => f
+                .debug_struct("Token")
+                .field("username", username)
+                .field("token", &"<redacted>")
+                .finish(),
+            GitCredentials::CredentialHelper => f.write_str("CredentialHelper"),
         }
-    });
+    }
+}
 
-    callbacks.push_update_reference(|_reference_name, callback_status| {
-        log::debug!("reference name: {}", _reference_name);
+impl GitCredentials {
+    pub fn get_cred(
+        &self,
+        url: &str,
+        username_from_url: Option<&str>,
+        allowed_types: git2::CredentialType,
+    ) -> Result<Cred, git2::Error> {
         log::debug!(
-            "callback status: {}",
-            callback_status.unwrap_or("Not defined")
+            "Credentials for url '{}' username from url {:?} allowed types {:?}",
+            url,
+            username_from_url,
+            allowed_types
         );
 
-        Ok(())
+        // libgit2 asks for the username first on SSH URLs without one (eg ssh://host/repo)
+        if allowed_types == git2::CredentialType::USERNAME {
+            return Cred::username(username_from_url.unwrap_or("git"));
+        }
+
+        match self {
+            GitCredentials::SshAgent => {
+                Cred::ssh_key_from_agent(username_from_url.unwrap_or("git"))
+            }
+            GitCredentials::SshKey {
+                private_key_path,
+                passphrase_opt,
+            } => Cred::ssh_key(
+                username_from_url.unwrap_or("git"),
+                None,
+                private_key_path,
+                passphrase_opt.as_deref(),
+            ),
+            GitCredentials::Token { username, token } => Cred::userpass_plaintext(username, token),
+            GitCredentials::CredentialHelper => {
+                Cred::credential_helper(&git2::Config::open_default()?, url, username_from_url)
+            }
+        }
+    }
+
+    /// Callbacks providing these credentials to git2. Credentials are only offered once per
+    /// credential type, otherwise git2 keeps asking for them when they are wrong
+    pub fn get_remote_callbacks(&self) -> RemoteCallbacks<'_> {
+        let mut callbacks = RemoteCallbacks::new();
+        let mut attempt_map: HashMap<u32, usize> = HashMap::new();
+
+        callbacks.credentials(move |url, username_from_url, allowed_types| {
+            let attempt = attempt_map.entry(allowed_types.bits()).or_default();
+            *attempt += 1;
+
+            if *attempt > 1 {
+                return Err(git2::Error::from_str(&format!(
+                    "Authentication failed for '{}'",
+                    url
+                )));
+            }
+
+            self.get_cred(url, username_from_url, allowed_types)
+        });
+
+        callbacks
+    }
+}
+
+/// Pushes refspecs to a remote. Fails if the remote rejects any reference
+pub fn push(
+    remote: &mut Remote,
+    refspec_vec: &[&str],
+    git_credentials: &GitCredentials,
+) -> Result<(), git2::Error> {
+    let mut callbacks = git_credentials.get_remote_callbacks();
+
+    callbacks.push_update_reference(|reference_name, status_opt| match status_opt {
+        Some(status) => Err(git2::Error::from_str(&format!(
+            "Remote rejected reference '{}': {}",
+            reference_name, status
+        ))),
+        None => {
+            log::debug!("Reference '{}' pushed", reference_name);
+            Ok(())
+        }
     });
 
-    // Configure push options
-    let po = &mut PushOptions::default();
-    po.remote_callbacks(callbacks);
-
-    // Push
-    remote.push(
-        &[
-            "+refs/heads/main",
-            "+refs/heads/apply-dynamic-target-session",
-        ],
-        Some(po),
-    )
+    let mut push_options = PushOptions::default();
+    push_options.remote_callbacks(callbacks);
+
+    remote.push(refspec_vec, Some(&mut push_options))
 }
 
 pub fn fetch<'a>(
     repo: &'a git2::Repository,
     refs: &[&str],
     remote: &'a mut git2::Remote,
+    git_credentials: &GitCredentials,
 ) -> Result<git2::AnnotatedCommit<'a>, Box<dyn Error>> {
-    let mut cb = git2::RemoteCallbacks::new();
+    let mut cb = git_credentials.get_remote_callbacks();
 
-    // TODO: CLEAN THIS!!!
-    cb.credentials(|_url, username_from_url, _allowed_types| {
-        log::debug!("url is: {}", _url);
-        log::debug!(
-            "username from url is: {}",
-            username_from_url.unwrap_or("Not defined")
-        ); // IMPORTANT: username from url is None because .git/config has https address 'url = https://git.cscs.ch/msopena/manta.git'
-        log::debug!("allowed types are: {:#?}", _allowed_types);
-
-        if username_from_url.unwrap().eq("git") {
-            // ssh authentication
-            git2::Cred::ssh_key(
-                username_from_url.unwrap(),
-                None,
-                std::path::Path::new(&format!(
-                    "{}/.ssh/gitlab_vcef",
-                    std::env::var("HOME").expect("key file ~/.ssh/gitlab_vcef not found")
-                )),
-                None,
-            )
-        } else {
-            // plain username and password authentication
-            let username: String = Input::new()
-                .with_prompt("Username: ")
-                .interact_text()
-                .unwrap();
-
-            let password = Password::new()
-                .with_prompt("New Password")
-                .with_confirmation("Confirm password", "Passwords mismatching")
-                .interact()
-                .unwrap();
-
-            git2::Cred::userpass_plaintext(&username, &password) // IMPORTANT: this with combination of .git/config having an https address 'url = https://git.cscs.ch/msopena/manta.git' makes library to switch to CredentialType::USER_PASS_PLAINTEXT
-        }
-    });
-
-    // Print out our transfer progress.
     cb.transfer_progress(|stats| {
-        if stats.received_objects() == stats.total_objects() {
-            print!(
-                "Resolving deltas {}/{}\r",
-                stats.indexed_deltas(),
-                stats.total_deltas()
-            );
-        } else if stats.total_objects() > 0 {
-            print!(
-                "Received {}/{} objects ({}) in {} bytes\r",
-                stats.received_objects(),
-                stats.total_objects(),
-                stats.indexed_objects(),
-                stats.received_bytes()
-            );
-        }
-        io::stdout().flush().unwrap();
+        log::debug!(
+            "Received {}/{} objects in {} bytes. Resolved deltas {}/{}",
+            stats.received_objects(),
+            stats.total_objects(),
+            stats.received_bytes(),
+            stats.indexed_deltas(),
+            stats.total_deltas()
+        );
         true
     });
 
@@ -268,30 +303,9 @@ pub fn fetch<'a>(
     // Always fetch all tags.
     // Perform a download and also update tips
     fo.download_tags(git2::AutotagOption::All);
-    println!("Fetching {} for repo", remote.name().unwrap());
+    log::info!("Fetching {:?} from remote {:?}", refs, remote.name());
     remote.fetch(refs, Some(&mut fo), None)?;
 
-    // If there are local objects (we got a thin pack), then tell the user
-    // how many objects we saved from having to cross the network.
-    let stats = remote.stats();
-    if stats.local_objects() > 0 {
-        println!(
-            "\rReceived {}/{} objects in {} bytes (used {} local \
-                 objects)",
-            stats.indexed_objects(),
-            stats.total_objects(),
-            stats.received_bytes(),
-            stats.local_objects()
-        );
-    } else {
-        println!(
-            "\rReceived {}/{} objects in {} bytes",
-            stats.indexed_objects(),
-            stats.total_objects(),
-            stats.received_bytes()
-        );
-    }
-
     let fetch_head = repo.find_reference("FETCH_HEAD")?;
     Ok(repo.reference_to_annotated_commit(&fetch_head)?)
 }
@@ -317,12 +331,241 @@ pub fn has_conflicts(
     Ok(())
 }
 
-pub fn fetch_and_check_conflicts(repo: &Repository) -> core::result::Result<(), Box<dyn Error>> {
-    let head_commit = repo.reference_to_annotated_commit(&repo.head()?)?;
-    let mut remote_aux = repo.find_remote("origin")?;
-    let remote_branch = "apply-dynamic-target-session";
-    let fetch_commit = fetch(repo, &[remote_branch], &mut remote_aux)?;
+/// Fetches the current branch from a remote and checks it can be merged with the local one
+pub fn fetch_and_check_conflicts(
+    repo: &Repository,
+    remote_name: &str,
+    git_credentials: &GitCredentials,
+) -> core::result::Result<(), Box<dyn Error>> {
+    let head = repo.head()?;
+    let branch_name = head.shorthand().ok_or("Current branch name is not valid")?;
+    let head_commit = repo.reference_to_annotated_commit(&head)?;
+    let mut remote = repo.find_remote(remote_name)?;
+    let fetch_commit = fetch(repo, &[branch_name], &mut remote, git_credentials)?;
     has_conflicts(repo, &head_commit, &fetch_commit)?;
 
     Ok(())
 }
+
+/// Name of the remote pointing to CSM VCS added to local layer repos
+pub const CSM_VCS_REMOTE_NAME: &str = "csm-vcs";
+
+/// Returns the name of a repo from its URL, eg 'https://git.cscs.ch/msopena/manta.git' or
+/// 'git@github.com:eth-cscs/manta.git' --> 'manta'
+pub fn get_repo_name_from_url(url: &str) -> Option<&str> {
+    let repo_name = url.trim_end_matches('/').rsplit(['/', ':']).next()?;
+    let repo_name = repo_name.strip_suffix(".git").unwrap_or(repo_name);
+
+    if repo_name.is_empty() {
+        None
+    } else {
+        Some(repo_name)
+    }
+}
+
+/// Returns the name of a local repo, taken from the 'origin' remote or, if missing, from the
+/// repo folder
+pub fn get_repo_name(repo: &Repository) -> Option<String> {
+    let origin_url_opt = repo
+        .find_remote("origin")
+        .ok()
+        .and_then(|remote| remote.url().map(str::to_string));
+
+    match &origin_url_opt {
+        Some(origin_url) => get_repo_name_from_url(origin_url).map(str::to_string),
+        None => repo
+            .workdir()
+            .and_then(|workdir| workdir.file_name())
+            .map(|file_name| file_name.to_string_lossy().to_string()),
+    }
+}
+
+/// Pushes the current branch of a local layer repo to CSM VCS (Gitea 'cray' organization) so
+/// it can be used in a CFS configuration layer. Returns the SHA of the commit pushed
+pub fn push_to_csm_vcs(
+    repo: &Repository,
+    gitea_base_url: &str,
+    git_credentials: &GitCredentials,
+) -> Result<String, Box<dyn Error>> {
+    push_to_vcs(repo, gitea_base_url, "cray", git_credentials)
+}
+
+/// Pushes the current branch of a local repo to a Gitea organization. The VCS is added as remote
+/// 'csm-vcs' to the repo, fails if the repo already has a 'csm-vcs' remote pointing somewhere
+/// else. Returns the SHA of the commit pushed
+pub fn push_to_vcs(
+    repo: &Repository,
+    gitea_base_url: &str,
+    vcs_org: &str,
+    git_credentials: &GitCredentials,
+) -> Result<String, Box<dyn Error>> {
+    let repo_name = get_repo_name(repo).ok_or("Could not get the repo name")?;
+
+    let vcs_url = format!(
+        "{}/{}/{}.git",
+        gitea_base_url.trim_end_matches('/'),
+        vcs_org,
+        repo_name
+    );
+
+    match repo.find_remote(CSM_VCS_REMOTE_NAME) {
+        Ok(remote) if remote.url() == Some(vcs_url.as_str()) => {}
+        Ok(remote) => {
+            return Err(format!(
+                "Remote '{}' points to '{}' instead of '{}', please fix or remove it",
+                CSM_VCS_REMOTE_NAME,
+                remote.url().unwrap_or_default(),
+                vcs_url
+            )
+            .into())
+        }
+        Err(_) => {
+            repo.remote(CSM_VCS_REMOTE_NAME, &vcs_url)?;
+        }
+    }
+
+    let head = repo.head()?;
+
+    if !head.is_branch() {
+        return Err("HEAD is detached, please checkout a branch before pushing".into());
+    }
+
+    let branch_reference_name = head.name().ok_or("Current branch name is not valid")?;
+    let commit_sha = head.peel_to_commit()?.id().to_string();
+
+    log::info!(
+        "Pushing '{}' ({}) to {}",
+        branch_reference_name,
+        commit_sha,
+        vcs_url
+    );
+
+    push(
+        &mut repo.find_remote(CSM_VCS_REMOTE_NAME)?,
+        &[&format!("{0}:{0}", branch_reference_name)],
+        git_credentials,
+    )?;
+
+    Ok(commit_sha)
+}
+
+#[cfg(test)]
+pub mod test {
+    use git2::{Repository, Signature};
+
+    use super::{
+        add_all, commit, get_repo_name_from_url, push_to_vcs, GitCredentials, CSM_VCS_REMOTE_NAME,
+    };
+
+    /// Creates a local repo 'layer-repo' with a file ready to be committed
+    fn init_local_repo(dir: &std::path::Path) -> Repository {
+        let repo = Repository::init(dir.join("layer-repo")).unwrap();
+        std::fs::write(dir.join("layer-repo").join("site.yml"), "---\n").unwrap();
+        add_all(&repo);
+
+        repo
+    }
+
+    #[test]
+    fn test_commit() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = init_local_repo(dir.path());
+        let author = Signature::now("Jane Doe", "jane.doe@example.com").unwrap();
+
+        let first_oid = commit(&repo, "Add site.yml", Some(&author)).unwrap();
+
+        let first_commit = repo.find_commit(first_oid).unwrap();
+        assert_eq!(first_commit.message(), Some("Add site.yml"));
+        assert_eq!(first_commit.author().name(), Some("Jane Doe"));
+        assert_eq!(first_commit.parent_count(), 0);
+
+        std::fs::write(
+            dir.path().join("layer-repo").join("site.yml"),
+            "---\n- hosts: all\n",
+        )
+        .unwrap();
+        add_all(&repo);
+
+        let second_oid = commit(&repo, "Target all hosts", Some(&author)).unwrap();
+
+        let second_commit = repo.find_commit(second_oid).unwrap();
+        assert_eq!(second_commit.parent_id(0).unwrap(), first_oid);
+        assert_eq!(repo.head().unwrap().target(), Some(second_oid));
+    }
+
+    #[test]
+    fn test_push_to_vcs() {
+        let dir = tempfile::tempdir().unwrap();
+        let vcs_dir = dir.path().join("vcs");
+        let vcs_repo = Repository::init_bare(vcs_dir.join("cray").join("layer-repo.git")).unwrap();
+
+        let repo = init_local_repo(dir.path());
+        let author = Signature::now("Jane Doe", "jane.doe@example.com").unwrap();
+        let oid = commit(&repo, "Add site.yml", Some(&author)).unwrap();
+
+        let commit_sha = push_to_vcs(
+            &repo,
+            &vcs_dir.to_string_lossy(),
+            "cray",
+            &GitCredentials::CredentialHelper,
+        )
+        .unwrap();
+
+        assert_eq!(commit_sha, oid.to_string());
+
+        let branch_reference_name = repo.head().unwrap().name().unwrap().to_string();
+        assert_eq!(
+            vcs_repo
+                .find_reference(&branch_reference_name)
+                .unwrap()
+                .target(),
+            Some(oid)
+        );
+
+        // Pushing again reuses the remote
+        assert!(push_to_vcs(
+            &repo,
+            &vcs_dir.to_string_lossy(),
+            "cray",
+            &GitCredentials::CredentialHelper,
+        )
+        .is_ok());
+
+        // Existing remote pointing somewhere else is not overwritten
+        repo.remote_set_url(
+            CSM_VCS_REMOTE_NAME,
+            "https://git.example.com/layer-repo.git",
+        )
+        .unwrap();
+
+        assert!(push_to_vcs(
+            &repo,
+            &vcs_dir.to_string_lossy(),
+            "cray",
+            &GitCredentials::CredentialHelper,
+        )
+        .is_err());
+        assert_eq!(
+            repo.find_remote(CSM_VCS_REMOTE_NAME).unwrap().url(),
+            Some("https://git.example.com/layer-repo.git")
+        );
+    }
+
+    #[test]
+    fn test_get_repo_name_from_url() {
+        assert_eq!(
+            get_repo_name_from_url("https://git.cscs.ch/msopena/manta.git"),
+            Some("manta")
+        );
+        assert_eq!(
+            get_repo_name_from_url("git@github.com:eth-cscs/mesa.git"),
+            Some("mesa")
+        );
+        assert_eq!(
+            get_repo_name_from_url(
+                "https://api-gw-service-nmn.local/vcs/cray/uan-config-management"
+            ),
+            Some("uan-config-management")
+        );
+    }
+}
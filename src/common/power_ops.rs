@@ -0,0 +1,426 @@
+use std::{collections::HashMap, error::Error, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::time::Instant;
+
+use crate::{capmc, node};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum PowerAction {
+    On,
+    Off,
+    /// Power OFF followed by power ON
+    Reset,
+}
+
+/// Result of a power operation on a single node
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum PowerOutcome {
+    Succeeded,
+    /// Node was already in the requested power state, no action was sent
+    AlreadyInState,
+    Failed(String),
+    /// Deadline reached before the node reached the requested power state
+    TimedOut {
+        last_state: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NodePowerResult {
+    pub xname: String,
+    pub outcome: PowerOutcome,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PowerReport {
+    pub action: PowerAction,
+    pub node_power_result_vec: Vec<NodePowerResult>,
+}
+
+impl PowerReport {
+    /// Returns true if all nodes are in the requested power state
+    pub fn is_success(&self) -> bool {
+        self.node_power_result_vec.iter().all(|node_power_result| {
+            matches!(
+                node_power_result.outcome,
+                PowerOutcome::Succeeded | PowerOutcome::AlreadyInState
+            )
+        })
+    }
+
+    /// Returns the nodes which failed or timed out
+    pub fn get_failed_vec(&self) -> Vec<&NodePowerResult> {
+        self.node_power_result_vec
+            .iter()
+            .filter(|node_power_result| {
+                matches!(
+                    node_power_result.outcome,
+                    PowerOutcome::Failed(_) | PowerOutcome::TimedOut { .. }
+                )
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PowerConfig {
+    pub reason_opt: Option<String>,
+    pub force: bool,
+    /// Overall time to wait for all nodes to reach the requested power state
+    pub deadline: Duration,
+    /// Time between power status checks
+    pub poll_interval: Duration,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            reason_opt: None,
+            force: false,
+            deadline: Duration::from_secs(180),
+            poll_interval: Duration::from_secs(3),
+        }
+    }
+}
+
+/// Powers ON, OFF or resets a list of nodes and waits for them to reach the requested power
+/// state. Final state is verified against CAPMC power status (sourced from HSM). Returns the
+/// outcome for each node, errors are only returned if the power status can't be fetched
+pub async fn power(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    action: PowerAction,
+    xname_vec: &[String],
+    power_config: &PowerConfig,
+) -> Result<PowerReport, Box<dyn Error>> {
+    // Accept NIDs and aliases too
+    let xname_vec = &node::resolver::resolve_xname_vec(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+    )
+    .await?;
+
+    let deadline = Instant::now() + power_config.deadline;
+
+    let node_power_result_vec = match action {
+        PowerAction::On | PowerAction::Off => {
+            power_transition(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                action,
+                xname_vec,
+                power_config,
+                deadline,
+            )
+            .await?
+        }
+        PowerAction::Reset => {
+            let power_off_result_vec = power_transition(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                PowerAction::Off,
+                xname_vec,
+                power_config,
+                deadline,
+            )
+            .await?;
+
+            // Only nodes which are OFF are powered ON
+            let xname_off_vec: Vec<String> = power_off_result_vec
+                .iter()
+                .filter(|node_power_result| {
+                    matches!(
+                        node_power_result.outcome,
+                        PowerOutcome::Succeeded | PowerOutcome::AlreadyInState
+                    )
+                })
+                .map(|node_power_result| node_power_result.xname.clone())
+                .collect();
+
+            let mut node_power_result_vec = power_transition(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                PowerAction::On,
+                &xname_off_vec,
+                power_config,
+                deadline,
+            )
+            .await?;
+
+            // A node reset is never "already in state"
+            for node_power_result in node_power_result_vec.iter_mut() {
+                if node_power_result.outcome == PowerOutcome::AlreadyInState {
+                    node_power_result.outcome = PowerOutcome::Succeeded;
+                }
+            }
+
+            node_power_result_vec.extend(
+                power_off_result_vec
+                    .into_iter()
+                    .filter(|node_power_result| !xname_off_vec.contains(&node_power_result.xname)),
+            );
+
+            node_power_result_vec
+        }
+    };
+
+    let report = PowerReport {
+        action,
+        node_power_result_vec,
+    };
+
+    for node_power_result in report.get_failed_vec() {
+        log::warn!(
+            "Power {:?} node '{}': {:?}",
+            action,
+            node_power_result.xname,
+            node_power_result.outcome
+        );
+    }
+
+    Ok(report)
+}
+
+/// Sends a power ON or OFF to the nodes not already in the target state and waits until they
+/// reach it or the deadline expires
+async fn power_transition(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    action: PowerAction,
+    xname_vec: &[String],
+    power_config: &PowerConfig,
+    deadline: Instant,
+) -> Result<Vec<NodePowerResult>, Box<dyn Error>> {
+    if xname_vec.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let target_state = match action {
+        PowerAction::Off => "off",
+        _ => "on",
+    };
+
+    let mut power_state_map = get_power_state_map(
+        &capmc::http_client::node_power_status::post(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &xname_vec.to_vec(),
+        )
+        .await?,
+    );
+
+    let mut outcome_map: HashMap<String, PowerOutcome> = HashMap::new();
+
+    let mut pending_xname_vec = Vec::new();
+    for xname in xname_vec {
+        if power_state_map.get(xname).map(String::as_str) == Some(target_state) {
+            outcome_map.insert(xname.clone(), PowerOutcome::AlreadyInState);
+        } else {
+            pending_xname_vec.push(xname.clone());
+        }
+    }
+
+    if !pending_xname_vec.is_empty() {
+        let power_rslt = match action {
+            PowerAction::Off => {
+                capmc::http_client::node_power_off::post_raw(
+                    shasta_token,
+                    shasta_base_url,
+                    shasta_root_cert,
+                    pending_xname_vec.clone(),
+                    power_config.reason_opt.clone(),
+                    power_config.force,
+                )
+                .await
+            }
+            _ => {
+                capmc::http_client::node_power_on::post_raw(
+                    shasta_token,
+                    shasta_base_url,
+                    shasta_root_cert,
+                    pending_xname_vec.clone(),
+                    power_config.reason_opt.clone(),
+                )
+                .await
+            }
+        };
+
+        match power_rslt {
+            Ok(response) => {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                let power_value: Value = serde_json::from_str(&body).unwrap_or_default();
+
+                let failed_xname_vec = get_failed_xname_vec(&power_value);
+
+                if status.is_success() || !failed_xname_vec.is_empty() {
+                    for (xname, error_message) in failed_xname_vec {
+                        outcome_map.insert(xname, PowerOutcome::Failed(error_message));
+                    }
+                } else {
+                    // No per node result, the whole request failed
+                    for xname in &pending_xname_vec {
+                        outcome_map.insert(
+                            xname.clone(),
+                            PowerOutcome::Failed(format!("CAPMC returned {}: {}", status, body)),
+                        );
+                    }
+                }
+            }
+            Err(error) => {
+                for xname in &pending_xname_vec {
+                    outcome_map.insert(xname.clone(), PowerOutcome::Failed(error.to_string()));
+                }
+            }
+        }
+
+        pending_xname_vec.retain(|xname| !outcome_map.contains_key(xname));
+    }
+
+    while !pending_xname_vec.is_empty() && Instant::now() < deadline {
+        tokio::time::sleep(power_config.poll_interval).await;
+
+        match capmc::http_client::node_power_status::post(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &pending_xname_vec,
+        )
+        .await
+        {
+            Ok(power_status_value) => {
+                power_state_map = get_power_state_map(&power_status_value);
+            }
+            Err(error) => {
+                log::warn!("Could not get nodes power status: {}", error);
+                continue;
+            }
+        }
+
+        pending_xname_vec.retain(|xname| {
+            if power_state_map.get(xname).map(String::as_str) == Some(target_state) {
+                outcome_map.insert(xname.clone(), PowerOutcome::Succeeded);
+                false
+            } else {
+                true
+            }
+        });
+
+        log::info!(
+            "Waiting {} node(s) to be {}: {:?}",
+            pending_xname_vec.len(),
+            target_state.to_uppercase(),
+            pending_xname_vec
+        );
+    }
+
+    for xname in pending_xname_vec {
+        let last_state = power_state_map.get(&xname).cloned();
+        outcome_map.insert(xname, PowerOutcome::TimedOut { last_state });
+    }
+
+    Ok(xname_vec
+        .iter()
+        .map(|xname| NodePowerResult {
+            xname: xname.clone(),
+            outcome: outcome_map
+                .remove(xname)
+                .unwrap_or(PowerOutcome::Failed("no outcome".to_string())),
+        })
+        .collect())
+}
+
+/// Returns a map with the power state of each node from a CAPMC power status response
+/// eg {"e": 0, "err_msg": "", "on": ["x1000c0s0b0n0"], "off": ["x1000c0s0b0n1"]}
+pub fn get_power_state_map(power_status_value: &Value) -> HashMap<String, String> {
+    let mut power_state_map = HashMap::new();
+
+    if let Some(power_status_map) = power_status_value.as_object() {
+        for (power_state, xname_value) in power_status_map {
+            if power_state == "e" || power_state == "err_msg" {
+                continue;
+            }
+
+            for xname in xname_value
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+            {
+                power_state_map.insert(xname.to_string(), power_state.clone());
+            }
+        }
+    }
+
+    power_state_map
+}
+
+/// Returns the nodes which failed in a CAPMC power ON/OFF response and the reason
+/// eg {"e": -1, "err_msg": "Errors encountered with 1/2 Xnames issued On", "xnames": [{"xname": "x1000c0s0b0n0", "e": -1, "err_msg": "NodeBMC unreachable"}]}
+pub fn get_failed_xname_vec(power_value: &Value) -> Vec<(String, String)> {
+    power_value["xnames"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|xname_value| xname_value["e"].as_i64().unwrap_or(0) != 0)
+        .filter_map(|xname_value| {
+            xname_value["xname"].as_str().map(|xname| {
+                (
+                    xname.to_string(),
+                    xname_value["err_msg"]
+                        .as_str()
+                        .unwrap_or("unknown error")
+                        .to_string(),
+                )
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod test {
+    use serde_json::json;
+
+    use super::{get_failed_xname_vec, get_power_state_map};
+
+    #[test]
+    fn test_get_power_state_map_and_failed_xnames() {
+        let power_state_map = get_power_state_map(&json!({
+            "e": 0,
+            "err_msg": "",
+            "on": ["x1000c0s0b0n0"],
+            "off": ["x1000c0s0b0n1", "x1000c0s0b1n0"]
+        }));
+
+        assert_eq!(power_state_map.len(), 3);
+        assert_eq!(power_state_map["x1000c0s0b0n0"], "on");
+        assert_eq!(power_state_map["x1000c0s0b1n0"], "off");
+
+        let failed_xname_vec = get_failed_xname_vec(&json!({
+            "e": -1,
+            "err_msg": "Errors encountered with 1/2 Xnames issued On",
+            "xnames": [
+                {"xname": "x1000c0s0b0n0", "e": -1, "err_msg": "NodeBMC unreachable"},
+                {"xname": "x1000c0s0b0n1", "e": 0, "err_msg": ""}
+            ]
+        }));
+
+        assert_eq!(
+            failed_xname_vec,
+            vec![(
+                "x1000c0s0b0n0".to_string(),
+                "NodeBMC unreachable".to_string()
+            )]
+        );
+    }
+}
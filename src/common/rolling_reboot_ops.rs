@@ -0,0 +1,473 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::common::power_ops::{self, PowerAction, PowerConfig};
+
+/// Number of nodes rebooted at the same time
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum BatchSize {
+    Count(usize),
+    /// Percentage of the nodes in the HSM group (1-100)
+    Percentage(u8),
+}
+
+/// How nodes are rebooted
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum RebootMethod {
+    /// Power reset through CAPMC, nodes boot using the boot parameters already in BSS
+    PowerReset,
+    /// BOS session with operation 'reboot' limited to the nodes in the batch
+    BosSessionTemplate(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct RollingRebootConfig {
+    pub batch_size: BatchSize,
+    pub reboot_method: RebootMethod,
+    /// Rolling reboot stops if more than this number of nodes fail
+    pub failure_threshold: usize,
+    /// Power settings used by `RebootMethod::PowerReset`
+    pub power_config: PowerConfig,
+    /// Time for a batch to be HSM 'Ready' and CFS 'configured' after the reboot
+    pub health_timeout: Duration,
+    pub poll_interval: Duration,
+    /// File where progress is persisted so a rolling reboot can be paused and resumed
+    pub progress_file_opt: Option<PathBuf>,
+}
+
+impl Default for RollingRebootConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: BatchSize::Percentage(10),
+            reboot_method: RebootMethod::PowerReset,
+            failure_threshold: 0,
+            power_config: PowerConfig::default(),
+            health_timeout: Duration::from_secs(3600),
+            poll_interval: Duration::from_secs(30),
+            progress_file_opt: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum RollingRebootState {
+    Running,
+    /// Stopped before the next batch, call `rolling_reboot` again to resume
+    Paused,
+    /// Stopped because the failure threshold was crossed
+    Aborted,
+    Completed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NodeRebootFailure {
+    pub xname: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RollingRebootProgress {
+    pub hsm_group_name: String,
+    pub state: RollingRebootState,
+    pub batch_vec: Vec<Vec<String>>,
+    /// Index in `batch_vec` of the next batch to reboot
+    pub next_batch: usize,
+    pub node_failure_vec: Vec<NodeRebootFailure>,
+}
+
+impl RollingRebootProgress {
+    pub fn new(hsm_group_name: &str, batch_vec: Vec<Vec<String>>) -> Self {
+        Self {
+            hsm_group_name: hsm_group_name.to_string(),
+            state: RollingRebootState::Running,
+            batch_vec,
+            next_batch: 0,
+            node_failure_vec: Vec::new(),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn to_file(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        // Write to a temporary file first so a crash does not leave a truncated progress file
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(tmp_path, path)?;
+
+        Ok(())
+    }
+
+    /// Asks a running rolling reboot to stop before its next batch
+    pub fn pause(path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut progress = Self::from_file(path)?;
+
+        if progress.state == RollingRebootState::Running {
+            progress.state = RollingRebootState::Paused;
+            progress.to_file(path)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reboots the nodes of an HSM group in batches. Each batch has to reach HSM 'Ready' and CFS
+/// 'configured' before the next one starts. Progress is persisted after every batch if a
+/// progress file is configured, if the file already exists the rolling reboot resumes from it
+pub async fn rolling_reboot(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    hsm_group_name: &str,
+    rolling_reboot_config: &RollingRebootConfig,
+) -> Result<RollingRebootProgress, Box<dyn Error>> {
+    let progress_file_opt = rolling_reboot_config.progress_file_opt.as_deref();
+
+    let mut progress = match progress_file_opt {
+        Some(progress_file) if progress_file.exists() => {
+            let progress = RollingRebootProgress::from_file(progress_file)?;
+
+            if progress.hsm_group_name != hsm_group_name {
+                return Err(format!(
+                    "Progress file '{}' belongs to HSM group '{}'",
+                    progress_file.display(),
+                    progress.hsm_group_name
+                )
+                .into());
+            }
+
+            log::info!(
+                "Resuming rolling reboot of HSM group '{}' from batch {} of {}",
+                hsm_group_name,
+                progress.next_batch + 1,
+                progress.batch_vec.len()
+            );
+
+            progress
+        }
+        _ => {
+            let mut xname_vec =
+                crate::hsm::group::shasta::utils::try_get_member_vec_from_hsm_group_name(
+                    shasta_token,
+                    shasta_base_url,
+                    shasta_root_cert,
+                    hsm_group_name,
+                )
+                .await?;
+
+            xname_vec.sort();
+
+            RollingRebootProgress::new(
+                hsm_group_name,
+                get_batch_vec(&xname_vec, &rolling_reboot_config.batch_size),
+            )
+        }
+    };
+
+    progress.state = RollingRebootState::Running;
+    if let Some(progress_file) = progress_file_opt {
+        progress.to_file(progress_file)?;
+    }
+
+    while progress.next_batch < progress.batch_vec.len() {
+        // Another process may have paused the rolling reboot through the progress file
+        if let Some(progress_file) = progress_file_opt {
+            if RollingRebootProgress::from_file(progress_file)?.state == RollingRebootState::Paused
+            {
+                log::info!("Rolling reboot of HSM group '{}' paused", hsm_group_name);
+                progress.state = RollingRebootState::Paused;
+                return Ok(progress);
+            }
+        }
+
+        let batch = progress.batch_vec[progress.next_batch].clone();
+
+        log::info!(
+            "Rebooting batch {} of {}: {:?}",
+            progress.next_batch + 1,
+            progress.batch_vec.len(),
+            batch
+        );
+
+        let mut node_failure_vec = reboot_batch(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &batch,
+            rolling_reboot_config,
+        )
+        .await;
+
+        let xname_failed_vec: Vec<String> = node_failure_vec
+            .iter()
+            .map(|node_failure| node_failure.xname.clone())
+            .collect();
+
+        let xname_rebooted_vec: Vec<String> = batch
+            .iter()
+            .filter(|xname| !xname_failed_vec.contains(xname))
+            .cloned()
+            .collect();
+
+        node_failure_vec.extend(
+            wait_for_healthy_nodes(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                &xname_rebooted_vec,
+                rolling_reboot_config,
+            )
+            .await?,
+        );
+
+        progress.node_failure_vec.extend(node_failure_vec);
+        progress.next_batch += 1;
+
+        if progress.node_failure_vec.len() > rolling_reboot_config.failure_threshold {
+            log::error!(
+                "Rolling reboot of HSM group '{}' aborted, {} node(s) failed: {:?}",
+                hsm_group_name,
+                progress.node_failure_vec.len(),
+                progress.node_failure_vec
+            );
+            progress.state = RollingRebootState::Aborted;
+        } else if progress.next_batch == progress.batch_vec.len() {
+            progress.state = RollingRebootState::Completed;
+        }
+
+        if let Some(progress_file) = progress_file_opt {
+            // Keep a pause requested while the batch was rebooting
+            if progress.state == RollingRebootState::Running
+                && RollingRebootProgress::from_file(progress_file)?.state
+                    == RollingRebootState::Paused
+            {
+                progress.state = RollingRebootState::Paused;
+            }
+
+            progress.to_file(progress_file)?;
+        }
+
+        if progress.state != RollingRebootState::Running {
+            return Ok(progress);
+        }
+    }
+
+    progress.state = RollingRebootState::Completed;
+    if let Some(progress_file) = progress_file_opt {
+        progress.to_file(progress_file)?;
+    }
+
+    Ok(progress)
+}
+
+/// Reboots a batch of nodes. Returns the nodes which could not be rebooted
+async fn reboot_batch(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+    rolling_reboot_config: &RollingRebootConfig,
+) -> Vec<NodeRebootFailure> {
+    match &rolling_reboot_config.reboot_method {
+        RebootMethod::PowerReset => {
+            match power_ops::power(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                PowerAction::Reset,
+                xname_vec,
+                &rolling_reboot_config.power_config,
+            )
+            .await
+            {
+                Ok(power_report) => power_report
+                    .get_failed_vec()
+                    .into_iter()
+                    .map(|node_power_result| NodeRebootFailure {
+                        xname: node_power_result.xname.clone(),
+                        reason: format!("power reset: {:?}", node_power_result.outcome),
+                    })
+                    .collect(),
+                Err(error) => get_node_failure_vec(xname_vec, &format!("power reset: {}", error)),
+            }
+        }
+        RebootMethod::BosSessionTemplate(bos_sessiontemplate_name) => {
+            match crate::bos::session::shasta::http_client::post(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                bos_sessiontemplate_name,
+                "reboot",
+                Some(&xname_vec.join(",")),
+            )
+            .await
+            {
+                Ok(_) => Vec::new(),
+                Err(error) => get_node_failure_vec(xname_vec, &format!("BOS session: {}", error)),
+            }
+        }
+    }
+}
+
+/// Waits for nodes to be HSM 'Ready' and CFS 'configured'. Returns the nodes which did not get
+/// there before the timeout or whose CFS configuration failed
+pub async fn wait_for_healthy_nodes(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+    rolling_reboot_config: &RollingRebootConfig,
+) -> Result<Vec<NodeRebootFailure>, Box<dyn Error>> {
+    // Accept NIDs and aliases too
+    let xname_vec = &crate::node::resolver::resolve_xname_vec(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+    )
+    .await?;
+
+    let deadline = Instant::now() + rolling_reboot_config.health_timeout;
+
+    let mut pending_xname_vec = xname_vec.to_vec();
+    let mut node_failure_vec = Vec::new();
+
+    // Nodes can still be HSM 'Ready' and CFS 'configured' from before the reboot (BOS reboots
+    // asynchronously and HSM may not have caught up with a power reset yet). A node only passes
+    // the health gate once it has been seen leaving 'Ready' or with its configuration pending
+    let mut xname_seen_down_set: HashSet<String> = HashSet::new();
+
+    let mut hsm_state_map = HashMap::new();
+    let mut cfs_configuration_status_map = HashMap::new();
+
+    while !pending_xname_vec.is_empty() && Instant::now() < deadline {
+        tokio::time::sleep(rolling_reboot_config.poll_interval).await;
+
+        hsm_state_map = crate::hsm::component_status::shasta::utils::get_hsm_state_map(
+            &crate::hsm::component_status::shasta::http_client::get(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                &pending_xname_vec,
+            )
+            .await?,
+        );
+
+        cfs_configuration_status_map =
+            crate::cfs::component::mesa::utils::get_cfs_configuration_status_map(
+                &crate::cfs::component::mesa::http_client::get(
+                    shasta_token,
+                    shasta_base_url,
+                    shasta_root_cert,
+                    &pending_xname_vec,
+                )
+                .await?,
+            );
+
+        pending_xname_vec.retain(|xname| {
+            let hsm_state_opt = hsm_state_map.get(xname).map(String::as_str);
+            let cfs_configuration_status_opt =
+                cfs_configuration_status_map.get(xname).map(String::as_str);
+
+            if hsm_state_opt != Some("Ready") || cfs_configuration_status_opt == Some("pending") {
+                xname_seen_down_set.insert(xname.clone());
+            }
+
+            if !xname_seen_down_set.contains(xname) {
+                return true;
+            }
+
+            match (hsm_state_opt, cfs_configuration_status_opt) {
+                (_, Some("failed")) => {
+                    node_failure_vec.push(NodeRebootFailure {
+                        xname: xname.clone(),
+                        reason: "CFS configuration failed".to_string(),
+                    });
+                    false
+                }
+                (Some("Ready"), Some("configured")) => false,
+                _ => true,
+            }
+        });
+
+        log::info!(
+            "Waiting {} node(s) to be 'Ready' and 'configured': {:?}",
+            pending_xname_vec.len(),
+            pending_xname_vec
+        );
+    }
+
+    node_failure_vec.extend(pending_xname_vec.into_iter().map(|xname| {
+        let reason = format!(
+            "timed out, HSM state '{}', CFS configuration status '{}'",
+            hsm_state_map.get(&xname).cloned().unwrap_or_default(),
+            cfs_configuration_status_map
+                .get(&xname)
+                .cloned()
+                .unwrap_or_default()
+        );
+
+        NodeRebootFailure { xname, reason }
+    }));
+
+    Ok(node_failure_vec)
+}
+
+/// Splits a list of nodes in batches
+pub fn get_batch_vec(xname_vec: &[String], batch_size: &BatchSize) -> Vec<Vec<String>> {
+    let size = match batch_size {
+        BatchSize::Count(count) => *count,
+        BatchSize::Percentage(percentage) => {
+            (xname_vec.len() * usize::from((*percentage).min(100))).div_ceil(100)
+        }
+    }
+    .max(1);
+
+    xname_vec.chunks(size).map(|chunk| chunk.to_vec()).collect()
+}
+
+fn get_node_failure_vec(xname_vec: &[String], reason: &str) -> Vec<NodeRebootFailure> {
+    xname_vec
+        .iter()
+        .map(|xname| NodeRebootFailure {
+            xname: xname.clone(),
+            reason: reason.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{get_batch_vec, BatchSize};
+
+    #[test]
+    fn test_get_batch_vec() {
+        let xname_vec: Vec<String> = (0..10).map(|i| format!("x1000c0s{}b0n0", i)).collect();
+
+        let batch_vec = get_batch_vec(&xname_vec, &BatchSize::Count(4));
+        assert_eq!(
+            batch_vec.iter().map(Vec::len).collect::<Vec<usize>>(),
+            vec![4, 4, 2]
+        );
+
+        // Percentage rounds up so small groups still reboot at least one node per batch
+        let batch_vec = get_batch_vec(&xname_vec, &BatchSize::Percentage(25));
+        assert_eq!(
+            batch_vec.iter().map(Vec::len).collect::<Vec<usize>>(),
+            vec![3, 3, 3, 1]
+        );
+
+        let batch_vec = get_batch_vec(&xname_vec[..1], &BatchSize::Percentage(10));
+        assert_eq!(batch_vec, vec![vec!["x1000c0s0b0n0".to_string()]]);
+
+        assert!(get_batch_vec(&[], &BatchSize::Count(4)).is_empty());
+    }
+}
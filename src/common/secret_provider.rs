@@ -0,0 +1,412 @@
+use std::{
+    error::Error,
+    fmt::{self, Debug},
+    io::Write,
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use async_trait::async_trait;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+
+use super::vault::http_client;
+
+/// Name of the secret with the Gitea token
+pub const VCS_SECRET_NAME: &str = "vcs";
+/// Name of the secret with the k8s cluster certificates
+pub const K8S_SECRET_NAME: &str = "k8s";
+
+/// Location of the service account token in a pod
+pub const K8S_SERVICE_ACCOUNT_TOKEN_PATH: &str =
+    "/var/run/secrets/kubernetes.io/serviceaccount/token";
+
+const PBKDF2_ROUNDS: u32 = 600_000;
+/// AES-GCM nonce length in bytes
+const NONCE_LEN: usize = 12;
+
+/// Source of the secrets needed to operate CSM, eg the Gitea token or the k8s certificates
+#[async_trait(?Send)]
+pub trait SecretProvider: Debug {
+    /// Returns the secret stored under a name, eg 'vcs' or 'k8s'
+    async fn get_secret(&self, secret_name: &str) -> Result<Value, Box<dyn Error>>;
+
+    /// Returns the Gitea token
+    async fn get_vcs_token(&self) -> Result<String, Box<dyn Error>> {
+        get_vcs_token_from_secret(&self.get_secret(VCS_SECRET_NAME).await?)
+    }
+
+    /// Returns the k8s cluster certificates ('certificate-authority-data',
+    /// 'client-certificate-data' and 'client-key-data')
+    async fn get_k8s_secrets(&self) -> Result<Value, Box<dyn Error>> {
+        get_k8s_secrets_from_secret(&self.get_secret(K8S_SECRET_NAME).await?)
+    }
+}
+
+/// Gitea token is either the secret itself or its 'token' field
+pub fn get_vcs_token_from_secret(secret: &Value) -> Result<String, Box<dyn Error>> {
+    secret
+        .as_str()
+        .or_else(|| secret["token"].as_str())
+        .map(str::to_string)
+        .ok_or_else(|| "Gitea token not found in secret".into())
+}
+
+/// k8s certificates are either stored as a JSON string in field 'value' (CSM Vault layout) or
+/// as an object
+pub fn get_k8s_secrets_from_secret(secret: &Value) -> Result<Value, Box<dyn Error>> {
+    let k8s_secrets = match secret["value"].as_str() {
+        Some(value) => serde_json::from_str(value)?,
+        None => secret.clone(),
+    };
+
+    if k8s_secrets["certificate-authority-data"].is_string() {
+        Ok(k8s_secrets)
+    } else {
+        Err("k8s certificates not found in secret".into())
+    }
+}
+
+/// How to login to Vault
+#[derive(Clone)]
+pub enum VaultAuth {
+    AppRole {
+        role_id: String,
+    },
+    /// Vault token obtained by other means, no login needed
+    Token {
+        token: String,
+    },
+    /// Service account token of the pod running this code
+    Kubernetes {
+        auth_mount: String,
+        role: String,
+        jwt_path: PathBuf,
+    },
+}
+
+impl Debug for VaultAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VaultAuth::AppRole { role_id } => {
+                f.debug_struct("AppRole").field("role_id", role_id).finish()
+            }
+            VaultAuth::Token { .. } => f
+                .debug_struct("Token")
+                .field("token", &"<redacted>")
+                .finish(),
+            VaultAuth::Kubernetes {
+                auth_mount,
+                role,
+                jwt_path,
+            } => f
+                .debug_struct("Kubernetes")
+                .field("auth_mount", auth_mount)
+                .field("role", role)
+                .field("jwt_path", jwt_path)
+                .finish(),
+        }
+    }
+}
+
+impl VaultAuth {
+    /// Kubernetes auth method with the default mount and service account token location
+    pub fn kubernetes(role: &str) -> Self {
+        VaultAuth::Kubernetes {
+            auth_mount: "kubernetes".to_string(),
+            role: role.to_string(),
+            jwt_path: PathBuf::from(K8S_SERVICE_ACCOUNT_TOKEN_PATH),
+        }
+    }
+}
+
+/// Version of the Vault key value secret engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KvVersion {
+    V1,
+    /// Versioned secrets, data lives under '<mount>/data/<path>'
+    V2,
+}
+
+#[derive(Debug, Clone)]
+pub struct VaultSecretProvider {
+    pub vault_base_url: String,
+    pub vault_auth: VaultAuth,
+    /// Path to the secrets including the secret engine mount, eg 'shasta' or 'secret/shasta'
+    pub secret_path: String,
+    pub kv_version: KvVersion,
+}
+
+impl VaultSecretProvider {
+    pub fn new(
+        vault_base_url: &str,
+        vault_auth: VaultAuth,
+        secret_path: &str,
+        kv_version: KvVersion,
+    ) -> Self {
+        Self {
+            vault_base_url: vault_base_url.to_string(),
+            vault_auth,
+            secret_path: secret_path.trim_matches('/').to_string(),
+            kv_version,
+        }
+    }
+
+    /// Returns a Vault token
+    pub async fn login(&self) -> Result<String, Box<dyn Error>> {
+        match &self.vault_auth {
+            VaultAuth::AppRole { role_id } => http_client::auth(&self.vault_base_url, role_id)
+                .await
+                .map_err(|error| error as Box<dyn Error>),
+            VaultAuth::Token { token } => Ok(token.clone()),
+            VaultAuth::Kubernetes {
+                auth_mount,
+                role,
+                jwt_path,
+            } => {
+                let jwt = std::fs::read_to_string(jwt_path)?;
+
+                http_client::auth_kubernetes(&self.vault_base_url, auth_mount, role, jwt.trim())
+                    .await
+                    .map_err(|error| error as Box<dyn Error>)
+            }
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl SecretProvider for VaultSecretProvider {
+    async fn get_secret(&self, secret_name: &str) -> Result<Value, Box<dyn Error>> {
+        let vault_token = self.login().await?;
+
+        let secret_data = http_client::fetch_secret(
+            &vault_token,
+            &self.vault_base_url,
+            &get_vault_secret_api_path(&self.secret_path, secret_name, self.kv_version),
+        )
+        .await?;
+
+        match self.kv_version {
+            KvVersion::V1 => Ok(secret_data),
+            KvVersion::V2 => Ok(secret_data["data"].clone()),
+        }
+    }
+}
+
+/// Returns the Vault API path of a secret, eg ('secret/shasta', 'vcs', V2) -->
+/// '/v1/secret/data/shasta/vcs'
+pub fn get_vault_secret_api_path(
+    secret_path: &str,
+    secret_name: &str,
+    kv_version: KvVersion,
+) -> String {
+    let secret_path = secret_path.trim_matches('/');
+
+    match kv_version {
+        KvVersion::V1 => format!("/v1/{}/{}", secret_path, secret_name),
+        KvVersion::V2 => match secret_path.split_once('/') {
+            Some((mount, path)) => format!("/v1/{}/data/{}/{}", mount, path, secret_name),
+            None => format!("/v1/{}/data/{}", secret_path, secret_name),
+        },
+    }
+}
+
+/// Secrets in environment variables named '<prefix><SECRET NAME>', eg 'MESA_VCS' or
+/// 'MESA_K8S'. Values can be JSON or plain strings
+#[derive(Debug, Clone)]
+pub struct EnvSecretProvider {
+    pub prefix: String,
+}
+
+impl EnvSecretProvider {
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl SecretProvider for EnvSecretProvider {
+    async fn get_secret(&self, secret_name: &str) -> Result<Value, Box<dyn Error>> {
+        let env_var_name = format!("{}{}", self.prefix, secret_name.to_uppercase());
+
+        let value = std::env::var(&env_var_name)
+            .map_err(|_| format!("Env var '{}' not set", env_var_name))?;
+
+        Ok(serde_json::from_str(&value).unwrap_or(Value::String(value)))
+    }
+}
+
+/// Content of an encrypted secret file. All fields base64 encoded
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct EncryptedSecretFile {
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Secrets stored in a local file encrypted with a passphrase (AES-256-GCM, key derived with
+/// PBKDF2-SHA256). The decrypted content is a JSON object with a field per secret name
+#[derive(Clone)]
+pub struct EncryptedFileSecretProvider {
+    pub path: PathBuf,
+    passphrase: String,
+}
+
+impl Debug for EncryptedFileSecretProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptedFileSecretProvider")
+            .field("path", &self.path)
+            .field("passphrase", &"<redacted>")
+            .finish()
+    }
+}
+
+impl EncryptedFileSecretProvider {
+    pub fn new(path: &Path, passphrase: &str) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            passphrase: passphrase.to_string(),
+        }
+    }
+
+    /// Returns all secrets in the file
+    pub fn read(&self) -> Result<Value, Box<dyn Error>> {
+        let encrypted_secret_file: EncryptedSecretFile =
+            serde_json::from_str(&std::fs::read_to_string(&self.path)?)?;
+
+        let salt = base64::decode(encrypted_secret_file.salt)?;
+        let nonce = base64::decode(encrypted_secret_file.nonce)?;
+        let ciphertext = base64::decode(encrypted_secret_file.ciphertext)?;
+
+        let nonce: [u8; NONCE_LEN] = nonce.try_into().map_err(|_| {
+            format!(
+                "Invalid nonce in secret file '{}', file corrupted?",
+                self.path.display()
+            )
+        })?;
+
+        let plaintext = get_cipher(&self.passphrase, &salt)
+            .decrypt(&Nonce::from(nonce), ciphertext.as_ref())
+            .map_err(|_| {
+                format!(
+                    "Could not decrypt secret file '{}'. Wrong passphrase?",
+                    self.path.display()
+                )
+            })?;
+
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+
+    /// Encrypts and writes all secrets to the file, replacing its content
+    pub fn write(&self, secrets: &Value) -> Result<(), Box<dyn Error>> {
+        let mut salt = [0u8; 16];
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let ciphertext = get_cipher(&self.passphrase, &salt)
+            .encrypt(&Nonce::from(nonce), serde_json::to_vec(secrets)?.as_ref())
+            .map_err(|_| "Could not encrypt secrets")?;
+
+        let encrypted_secret_file = EncryptedSecretFile {
+            salt: base64::encode(salt),
+            nonce: base64::encode(nonce),
+            ciphertext: base64::encode(ciphertext),
+        };
+
+        // Only the owner can read the secrets file
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&self.path)?;
+        // mode is only applied to new files
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+
+        file.write_all(serde_json::to_string_pretty(&encrypted_secret_file)?.as_bytes())?;
+
+        Ok(())
+    }
+}
+
+fn get_cipher(passphrase: &str, salt: &[u8]) -> Aes256Gcm {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+
+    Aes256Gcm::new(&key.into())
+}
+
+#[async_trait(?Send)]
+impl SecretProvider for EncryptedFileSecretProvider {
+    async fn get_secret(&self, secret_name: &str) -> Result<Value, Box<dyn Error>> {
+        let secrets = self.read()?;
+
+        secrets.get(secret_name).cloned().ok_or_else(|| {
+            format!(
+                "Secret '{}' not found in '{}'",
+                secret_name,
+                self.path.display()
+            )
+            .into()
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::os::unix::fs::PermissionsExt;
+
+    use serde_json::json;
+
+    use super::{
+        get_vault_secret_api_path, EncryptedFileSecretProvider, KvVersion, SecretProvider,
+    };
+
+    #[test]
+    fn test_get_vault_secret_api_path() {
+        assert_eq!(
+            get_vault_secret_api_path("shasta", "vcs", KvVersion::V1),
+            "/v1/shasta/vcs"
+        );
+        assert_eq!(
+            get_vault_secret_api_path("/secret/shasta/", "k8s", KvVersion::V2),
+            "/v1/secret/data/shasta/k8s"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_file_secret_provider() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secrets.json");
+
+        let secret_provider = EncryptedFileSecretProvider::new(&path, "passphrase");
+        secret_provider
+            .write(&json!({ "vcs": { "token": "gitea-token" } }))
+            .unwrap();
+
+        assert!(!std::fs::read_to_string(&path)
+            .unwrap()
+            .contains("gitea-token"));
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert_eq!(
+            secret_provider.get_vcs_token().await.unwrap(),
+            "gitea-token"
+        );
+        assert!(EncryptedFileSecretProvider::new(&path, "wrong")
+            .read()
+            .is_err());
+        assert!(!format!("{:?}", secret_provider).contains("\"passphrase\""));
+    }
+}
@@ -4,6 +4,10 @@ pub mod http_client {
 
     use serde_json::{json, Value};
 
+    use crate::common::secret_provider::{
+        KvVersion, SecretProvider, VaultAuth, VaultSecretProvider,
+    };
+
     pub async fn auth(
         vault_base_url: &str,
         vault_role_id: &str,
@@ -24,16 +28,54 @@ pub mod http_client {
 
         if resp.status().is_success() {
             log::debug!("Login to {} successful", api_url);
-            let resp_text: Value = serde_json::from_str(&resp.text().await?)?;
-            Ok(String::from(
-                resp_text["auth"]["client_token"].as_str().unwrap(),
-            ))
+            let resp_json: Value = resp.json().await?;
+            resp_json["auth"]["client_token"]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| "Vault login response without client token".into())
         } else {
-            eprintln!("{:?}", resp);
-            Err(resp.json::<Value>().await?["errors"][0]
+            log::debug!("Login to {} failed: {:?}", api_url, resp);
+            let resp_json: Value = resp.json().await.unwrap_or_default();
+            Err(resp_json["errors"][0]
                 .as_str()
-                .unwrap()
-                .into()) // Black magic conversion from Err(Box::new("my error msg")) which does not
+                .unwrap_or("Vault login failed")
+                .into())
+        }
+    }
+
+    /// Login using the Vault Kubernetes auth method with the service account token (jwt) of the
+    /// pod running this code
+    pub async fn auth_kubernetes(
+        vault_base_url: &str,
+        vault_auth_mount: &str,
+        vault_role: &str,
+        jwt: &str,
+    ) -> Result<String, Box<dyn Error + Sync + Send>> {
+        let client = reqwest::Client::builder().build()?;
+
+        let api_url = format!("{}/v1/auth/{}/login", vault_base_url, vault_auth_mount);
+
+        log::debug!("Accessing/login to {}", api_url);
+
+        let resp = client
+            .post(api_url.clone())
+            .json(&json!({ "role": vault_role, "jwt": jwt }))
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            log::debug!("Login to {} successful", api_url);
+            let resp_json: Value = resp.json().await?;
+            resp_json["auth"]["client_token"]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| "Vault login response without client token".into())
+        } else {
+            let resp_json: Value = resp.json().await?;
+            Err(resp_json["errors"][0]
+                .as_str()
+                .unwrap_or("Vault login failed")
+                .into())
         }
     }
 
@@ -70,49 +112,35 @@ pub mod http_client {
         vault_secrets_path: &str,
         vault_role_id: &str,
     ) -> Result<String, Box<dyn Error>> {
-        let vault_token_resp = auth(vault_base_url, vault_role_id).await;
-
-        match vault_token_resp {
-            Ok(vault_token) => {
-                let vault_secret = fetch_secret(
-                    &vault_token,
-                    vault_base_url,
-                    &format!("/v1/{}/vcs", vault_secrets_path),
-                )
-                .await?; // this works for hashicorp-vault for fulen may need /v1/secret/data/shasta/vcs
-                Ok(String::from(vault_secret["token"].as_str().unwrap())) // this works for vault v1.12.0 for older versions may need vault_secret["data"]["token"]
-            }
-            Err(e) => {
-                eprintln!("{}", e);
-                std::process::exit(1);
-            }
-        }
+        get_approle_secret_provider(vault_base_url, vault_secrets_path, vault_role_id)
+            .get_vcs_token()
+            .await
     }
 
     pub async fn fetch_shasta_k8s_secrets(
         vault_base_url: &str,
         vault_secret_path: &str,
         vault_role_id: &str,
-    ) -> Value {
-        let vault_token_resp = auth(vault_base_url, vault_role_id).await;
-
-        match vault_token_resp {
-            Ok(vault_token) => {
-                let vault_secret = fetch_secret(
-                    &vault_token,
-                    vault_base_url,
-                    &format!("/v1/{}/k8s", vault_secret_path),
-                )
-                .await
-                .unwrap(); // this works for hashicorp-vault for fulen may need /v1/secret/data/shasta/k8s
-
-                serde_json::from_str::<Value>(vault_secret["value"].as_str().unwrap()).unwrap()
-                // this works for vault v1.12.0 for older versions may need vault_secret["data"]["value"]
-            }
-            Err(e) => {
-                eprintln!("{}", e);
-                std::process::exit(1);
-            }
-        }
+    ) -> Result<Value, Box<dyn Error>> {
+        get_approle_secret_provider(vault_base_url, vault_secret_path, vault_role_id)
+            .get_k8s_secrets()
+            .await
+    }
+
+    /// CSM Vault layout, AppRole login and KV v1 secrets under '<secret path>/vcs' and
+    /// '<secret path>/k8s'
+    fn get_approle_secret_provider(
+        vault_base_url: &str,
+        vault_secret_path: &str,
+        vault_role_id: &str,
+    ) -> VaultSecretProvider {
+        VaultSecretProvider::new(
+            vault_base_url,
+            VaultAuth::AppRole {
+                role_id: vault_role_id.to_string(),
+            },
+            vault_secret_path,
+            KvVersion::V1,
+        )
     }
 }
@@ -269,7 +269,10 @@ pub mod group {
 
         pub mod utils {
 
-            use std::collections::{HashMap, HashSet};
+            use std::{
+                collections::{HashMap, HashSet},
+                error::Error,
+            };
 
             use serde_json::Value;
 
@@ -365,6 +368,29 @@ pub mod group {
                     .collect()
             }
 
+            /// Returns the members of an HSM group or an error if the group does not exist
+            pub async fn try_get_member_vec_from_hsm_group_name(
+                shasta_token: &str,
+                shasta_base_url: &str,
+                shasta_root_cert: &[u8],
+                hsm_group: &str,
+            ) -> Result<Vec<String>, Box<dyn Error>> {
+                let hsm_group_value_vec = http_client::get(
+                    shasta_token,
+                    shasta_base_url,
+                    shasta_root_cert,
+                    Some(&hsm_group.to_string()),
+                )
+                .await
+                .map_err(|error| format!("Could not get HSM group '{}': {}", hsm_group, error))?;
+
+                let hsm_group_value = hsm_group_value_vec
+                    .first()
+                    .ok_or_else(|| format!("HSM group '{}' not found", hsm_group))?;
+
+                Ok(get_member_vec_from_hsm_group_value(hsm_group_value))
+            }
+
             pub async fn get_hsm_group_from_xname(
                 shasta_token: &str,
                 shasta_base_url: &str,
@@ -676,6 +702,93 @@ pub mod group {
                     Err(resp.text().await?.into())
                 }
             }
+
+            /// Adds a node to an HSM group
+            /// https://github.com/Cray-HPE/docs-csm/blob/release/1.5/api/smd.md#post-groupslabelmembers
+            pub async fn post_member(
+                shasta_token: &str,
+                shasta_base_url: &str,
+                shasta_root_cert: &[u8],
+                hsm_group_name: &str,
+                xname: &str,
+            ) -> Result<Value, Box<dyn Error>> {
+                let client_builder = reqwest::Client::builder()
+                    .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
+
+                // Build client
+                let client = if let Ok(socks5_env) = std::env::var("SOCKS5") {
+                    // socks5 proxy
+                    log::debug!("SOCKS5 enabled");
+                    let socks5proxy = reqwest::Proxy::all(socks5_env)?;
+
+                    // rest client to authenticate
+                    client_builder.proxy(socks5proxy).build()?
+                } else {
+                    client_builder.build()?
+                };
+
+                let url_api = format!(
+                    "{}/smd/hsm/v2/groups/{}/members",
+                    shasta_base_url, hsm_group_name
+                );
+
+                let resp = client
+                    .post(url_api)
+                    .bearer_auth(shasta_token)
+                    .json(&serde_json::json!({ "id": xname }))
+                    .send()
+                    .await?;
+
+                if resp.status().is_success() {
+                    Ok(resp.json::<Value>().await?)
+                } else {
+                    log::debug!("post member return code: {}", resp.status());
+                    Err(resp.text().await?.into())
+                }
+            }
+
+            /// Removes a node from an HSM group
+            /// https://github.com/Cray-HPE/docs-csm/blob/release/1.5/api/smd.md#delete-groupslabelmembersxname_id
+            pub async fn delete_member(
+                shasta_token: &str,
+                shasta_base_url: &str,
+                shasta_root_cert: &[u8],
+                hsm_group_name: &str,
+                xname: &str,
+            ) -> Result<Value, Box<dyn Error>> {
+                let client_builder = reqwest::Client::builder()
+                    .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
+
+                // Build client
+                let client = if let Ok(socks5_env) = std::env::var("SOCKS5") {
+                    // socks5 proxy
+                    log::debug!("SOCKS5 enabled");
+                    let socks5proxy = reqwest::Proxy::all(socks5_env)?;
+
+                    // rest client to authenticate
+                    client_builder.proxy(socks5proxy).build()?
+                } else {
+                    client_builder.build()?
+                };
+
+                let url_api = format!(
+                    "{}/smd/hsm/v2/groups/{}/members/{}",
+                    shasta_base_url, hsm_group_name, xname
+                );
+
+                let resp = client
+                    .delete(url_api)
+                    .bearer_auth(shasta_token)
+                    .send()
+                    .await?;
+
+                if resp.status().is_success() {
+                    Ok(resp.json::<Value>().await?)
+                } else {
+                    log::debug!("delete member return code: {}", resp.status());
+                    Err(resp.text().await?.into())
+                }
+            }
         }
 
         pub mod utils {
@@ -816,6 +929,30 @@ pub mod component_status {
                 Ok(cfs_components_value_vec)
             }
         }
+
+        pub mod utils {
+            use std::collections::HashMap;
+
+            use serde_json::Value;
+
+            /// Returns a map with the HSM state of each node from an HSM State/Components
+            /// response
+            pub fn get_hsm_state_map(
+                hsm_component_status_value: &Value,
+            ) -> HashMap<String, String> {
+                hsm_component_status_value["Components"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|component| {
+                        Some((
+                            component["ID"].as_str()?.to_string(),
+                            component["State"].as_str()?.to_string(),
+                        ))
+                    })
+                    .collect()
+            }
+        }
     }
 }
 
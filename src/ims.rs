@@ -1,6 +1,8 @@
 pub mod image;
 pub mod job;
 pub mod public_keys;
+pub mod recipe;
 pub mod s3;
+pub mod s3_transfer;
 #[cfg(test)]
 pub mod s3_test;
@@ -1,3 +1,5 @@
+pub mod bundle;
+pub mod manifest;
 pub mod mesa;
 pub mod shasta;
 pub mod r#struct;
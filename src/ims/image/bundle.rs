@@ -0,0 +1,536 @@
+//! Export IMS images into a single portable file (bundle) and import them into another CSM
+//! system.
+//!
+//! A bundle is a tar file with:
+//!  - metadata.json: the IMS image record exported and the list of artifacts (see BundleMetadata)
+//!  - manifest.json: the IMS manifest of the image exported
+//!  - one file per artifact in the manifest (eg kernel, initrd and rootfs)
+//!
+//! S3 operations use the `sts_value` provided by the caller, use `ims::s3::s3_auth` to get one
+//! for CSM or `ims::s3::sts_value_from_credentials` for any other S3 endpoint.
+
+use std::{
+    error::Error,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    common::boot_image_ops::BOOT_IMAGE_BUCKET,
+    ims::{
+        image::r#struct::{Image, ImsImageRecord2Update, ImsManifest, ImsManifestArtifact, Link},
+        s3::split_s3_url,
+        s3_transfer::{self, Checksum, TransferConfig},
+    },
+};
+
+pub const BUNDLE_METADATA_FILE_NAME: &str = "metadata.json";
+pub const BUNDLE_MANIFEST_FILE_NAME: &str = "manifest.json";
+pub const BUNDLE_FORMAT_VERSION: &str = "1";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundleArtifact {
+    /// Name of the file in the bundle and in S3, eg 'rootfs'
+    pub name: String,
+    /// IMS artifact type, eg 'application/vnd.cray.image.rootfs.squashfs'
+    pub r#type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundleMetadata {
+    pub version: String,
+    pub exported: String,
+    pub image: Image,
+    pub artifact_vec: Vec<BundleArtifact>,
+}
+
+/// Exports an IMS image into a bundle file. The artifacts are downloaded into a temporary
+/// directory next to the bundle before packing them, it needs enough space to store the image
+/// twice
+pub async fn export_image(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    sts_value: &Value,
+    image_id: &str,
+    bundle_path: &Path,
+    transfer_config: &TransferConfig,
+) -> Result<BundleMetadata, Box<dyn Error>> {
+    let image = crate::ims::image::mesa::http_client::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        Some(image_id),
+    )
+    .await?
+    .first()
+    .cloned()
+    .ok_or_else(|| format!("Image '{}' not found in IMS", image_id))?;
+
+    let manifest_path = image
+        .link
+        .as_ref()
+        .map(|link| link.path.clone())
+        .ok_or_else(|| format!("Image '{}' has no manifest", image_id))?;
+
+    let (manifest_bucket, manifest_key) = split_s3_url(&manifest_path)
+        .ok_or_else(|| format!("Invalid manifest path '{}'", manifest_path))?;
+
+    let image_work_dir_tmp = tempfile::Builder::new()
+        .prefix("export-")
+        .tempdir_in(get_work_dir(bundle_path))?;
+    let image_work_dir = image_work_dir_tmp.path().to_path_buf();
+
+    let manifest_file_path = image_work_dir.join(BUNDLE_MANIFEST_FILE_NAME);
+
+    s3_transfer::download_object(
+        sts_value,
+        &manifest_bucket,
+        &manifest_key,
+        &manifest_file_path,
+        &TransferConfig {
+            checksum_opt: None,
+            ..transfer_config.clone()
+        },
+    )
+    .await?;
+
+    let manifest: ImsManifest =
+        serde_json::from_str(&std::fs::read_to_string(&manifest_file_path)?)?;
+
+    let mut artifact_vec = Vec::new();
+
+    for artifact in &manifest.artifacts {
+        let (bucket, key) = split_s3_url(&artifact.link.path)
+            .ok_or_else(|| format!("Invalid artifact path '{}'", artifact.link.path))?;
+
+        let name = artifact.get_name();
+        validate_artifact_name(&name)?;
+
+        let md5 = artifact.md5.clone().filter(|md5| !md5.is_empty());
+
+        log::info!("Downloading artifact '{}'", artifact.link.path);
+
+        let transfer_outcome = s3_transfer::download_object(
+            sts_value,
+            &bucket,
+            &key,
+            &image_work_dir.join(&name),
+            &TransferConfig {
+                checksum_opt: md5.clone().map(Checksum::Md5),
+                ..transfer_config.clone()
+            },
+        )
+        .await?;
+
+        artifact_vec.push(BundleArtifact {
+            name,
+            r#type: artifact.r#type.clone(),
+            md5,
+            size: transfer_outcome.size,
+        });
+    }
+
+    let bundle_metadata = BundleMetadata {
+        version: BUNDLE_FORMAT_VERSION.to_string(),
+        exported: chrono::Utc::now().to_rfc3339(),
+        image,
+        artifact_vec,
+    };
+
+    std::fs::write(
+        image_work_dir.join(BUNDLE_METADATA_FILE_NAME),
+        serde_json::to_string_pretty(&bundle_metadata)?,
+    )?;
+
+    // Pack bundle
+    let file_name_vec: Vec<String> = [BUNDLE_METADATA_FILE_NAME, BUNDLE_MANIFEST_FILE_NAME]
+        .iter()
+        .map(|file_name| file_name.to_string())
+        .chain(
+            bundle_metadata
+                .artifact_vec
+                .iter()
+                .map(|artifact| artifact.name.clone()),
+        )
+        .collect();
+
+    let bundle_path_buf = bundle_path.to_path_buf();
+    let image_work_dir_clone = image_work_dir.clone();
+
+    tokio::task::spawn_blocking(move || {
+        write_bundle(&bundle_path_buf, &image_work_dir_clone, &file_name_vec)
+            .map_err(|error| error.to_string())
+    })
+    .await??;
+
+    image_work_dir_tmp.close()?;
+
+    log::info!(
+        "Image '{}' exported to '{}'",
+        image_id,
+        bundle_path.display()
+    );
+
+    Ok(bundle_metadata)
+}
+
+/// Imports an image from a bundle file. The artifacts are uploaded to S3 under the id of a new
+/// IMS image, a new manifest pointing to them is created and the IMS image is linked to it.
+/// The bundle is unpacked in a temporary directory next to it, it needs enough space to store
+/// the whole image. If the import fails, the new IMS image and its artifacts are deleted.
+/// Returns the new IMS image
+pub async fn import_image(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    sts_value: &Value,
+    bundle_path: &Path,
+    image_name_opt: Option<&str>,
+    transfer_config: &TransferConfig,
+) -> Result<Image, Box<dyn Error>> {
+    let bundle_work_dir_tmp = tempfile::Builder::new()
+        .prefix("bundle-")
+        .tempdir_in(get_work_dir(bundle_path))?;
+    let bundle_work_dir = bundle_work_dir_tmp.path().to_path_buf();
+
+    let bundle_path_buf = bundle_path.to_path_buf();
+    let bundle_work_dir_clone = bundle_work_dir.clone();
+
+    tokio::task::spawn_blocking(move || {
+        tar::Archive::new(File::open(&bundle_path_buf)?).unpack(&bundle_work_dir_clone)
+    })
+    .await??;
+
+    let bundle_metadata: BundleMetadata = serde_json::from_str(&std::fs::read_to_string(
+        bundle_work_dir.join(BUNDLE_METADATA_FILE_NAME),
+    )?)?;
+
+    for artifact in &bundle_metadata.artifact_vec {
+        validate_artifact_name(&artifact.name)?;
+    }
+
+    let manifest: ImsManifest = serde_json::from_str(&std::fs::read_to_string(
+        bundle_work_dir.join(BUNDLE_MANIFEST_FILE_NAME),
+    )?)?;
+
+    let image_name = image_name_opt
+        .unwrap_or(&bundle_metadata.image.name)
+        .to_string();
+
+    // Register new IMS image, we need its id to know where to store the artifacts
+    let new_image_value = crate::ims::image::utils::register_new_image(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &Image {
+            id: None,
+            created: None,
+            name: image_name.clone(),
+            link: None,
+            arch: bundle_metadata.image.arch.clone(),
+        },
+    )
+    .await?;
+
+    let new_image_id = new_image_value["id"]
+        .as_str()
+        .ok_or("IMS did not return the id of the new image")?
+        .to_string();
+
+    log::info!("New IMS image '{}' registered", new_image_id);
+
+    let link = match upload_bundle_artifacts(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        sts_value,
+        &bundle_work_dir,
+        &bundle_metadata,
+        manifest,
+        &new_image_id,
+        transfer_config,
+    )
+    .await
+    {
+        Ok(link) => link,
+        Err(error) => {
+            // Do not leave images without artifacts behind
+            if let Err(delete_error) = crate::ims::image::shasta::http_client::delete(
+                shasta_token,
+                shasta_base_url,
+                shasta_root_cert,
+                &new_image_id,
+            )
+            .await
+            {
+                log::warn!(
+                    "Could not delete image '{}': {}",
+                    new_image_id,
+                    delete_error
+                );
+            }
+
+            let key_vec = bundle_metadata
+                .artifact_vec
+                .iter()
+                .map(|artifact| artifact.name.as_str())
+                .chain([BUNDLE_MANIFEST_FILE_NAME])
+                .map(|name| format!("{}/{}", new_image_id, name));
+
+            for key in key_vec {
+                if let Err(remove_error) =
+                    crate::ims::s3::s3_remove_object(sts_value, &key, BOOT_IMAGE_BUCKET).await
+                {
+                    log::warn!("{}", remove_error);
+                }
+            }
+
+            return Err(error);
+        }
+    };
+
+    bundle_work_dir_tmp.close()?;
+
+    log::info!(
+        "Image '{}' imported as '{}'",
+        bundle_metadata.image.id.unwrap_or_default(),
+        new_image_id
+    );
+
+    Ok(Image {
+        id: Some(new_image_id),
+        created: new_image_value["created"]
+            .as_str()
+            .map(|created| created.to_string()),
+        name: image_name,
+        link: Some(link),
+        arch: bundle_metadata.image.arch,
+    })
+}
+
+/// Uploads the artifacts of an unpacked bundle and a new manifest pointing to them, then links
+/// the IMS image to the new manifest. Returns the link to the manifest
+#[allow(clippy::too_many_arguments)]
+async fn upload_bundle_artifacts(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    sts_value: &Value,
+    bundle_work_dir: &Path,
+    bundle_metadata: &BundleMetadata,
+    mut manifest: ImsManifest,
+    image_id: &str,
+    transfer_config: &TransferConfig,
+) -> Result<Link, Box<dyn Error>> {
+    let mut new_artifact_vec = Vec::new();
+
+    for artifact in &bundle_metadata.artifact_vec {
+        let key = format!("{}/{}", image_id, artifact.name);
+
+        log::info!("Uploading artifact 's3://{}/{}'", BOOT_IMAGE_BUCKET, key);
+
+        let transfer_outcome = s3_transfer::upload_object(
+            sts_value,
+            &bundle_work_dir.join(&artifact.name),
+            BOOT_IMAGE_BUCKET,
+            &key,
+            &TransferConfig {
+                checksum_opt: artifact.md5.clone().map(Checksum::Md5),
+                ..transfer_config.clone()
+            },
+        )
+        .await?;
+
+        new_artifact_vec.push(ImsManifestArtifact {
+            r#type: artifact.r#type.clone(),
+            link: Link {
+                path: format!("s3://{}/{}", BOOT_IMAGE_BUCKET, key),
+                etag: Some(transfer_outcome.etag),
+                r#type: "s3".to_string(),
+            },
+            md5: artifact.md5.clone(),
+        });
+    }
+
+    // Create new manifest
+    manifest.created = Some(chrono::Utc::now().to_rfc3339());
+    manifest.artifacts = new_artifact_vec;
+
+    let new_manifest_file_path = bundle_work_dir.join(format!("new-{}", BUNDLE_MANIFEST_FILE_NAME));
+    std::fs::write(
+        &new_manifest_file_path,
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+
+    let manifest_key = format!("{}/{}", image_id, BUNDLE_MANIFEST_FILE_NAME);
+
+    let transfer_outcome = s3_transfer::upload_object(
+        sts_value,
+        &new_manifest_file_path,
+        BOOT_IMAGE_BUCKET,
+        &manifest_key,
+        &TransferConfig {
+            checksum_opt: None,
+            ..transfer_config.clone()
+        },
+    )
+    .await?;
+
+    // Link IMS image to the new manifest
+    let link = Link {
+        path: format!("s3://{}/{}", BOOT_IMAGE_BUCKET, manifest_key),
+        etag: Some(transfer_outcome.etag),
+        r#type: "s3".to_string(),
+    };
+
+    crate::ims::image::mesa::utils::update_image(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        &image_id.to_string(),
+        &ImsImageRecord2Update {
+            link: link.clone(),
+            arch: bundle_metadata.image.arch.clone(),
+        },
+    )
+    .await?;
+
+    Ok(link)
+}
+
+/// Reads the metadata of a bundle without unpacking it
+pub fn get_bundle_metadata(bundle_path: &Path) -> Result<BundleMetadata, Box<dyn Error>> {
+    let mut archive = tar::Archive::new(File::open(bundle_path)?);
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+
+        if entry.path()? == PathBuf::from(BUNDLE_METADATA_FILE_NAME) {
+            return Ok(serde_json::from_reader(entry)?);
+        }
+    }
+
+    Err(format!(
+        "'{}' is not an image bundle, {} not found",
+        bundle_path.display(),
+        BUNDLE_METADATA_FILE_NAME
+    )
+    .into())
+}
+
+/// Artifact names come from the bundle metadata, they must be plain file names so they can't
+/// point outside the bundle directory or the image directory in S3
+fn validate_artifact_name(name: &str) -> Result<(), Box<dyn Error>> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(format!("Invalid artifact name '{}' in bundle", name).into());
+    }
+
+    Ok(())
+}
+
+fn get_work_dir(bundle_path: &Path) -> PathBuf {
+    bundle_path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .to_path_buf()
+}
+
+fn write_bundle(
+    bundle_path: &Path,
+    source_dir: &Path,
+    file_name_vec: &[String],
+) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = bundle_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut builder = tar::Builder::new(File::create(bundle_path)?);
+
+    for file_name in file_name_vec {
+        builder.append_path_with_name(source_dir.join(file_name), file_name)?;
+    }
+
+    builder.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{
+        get_bundle_metadata, validate_artifact_name, write_bundle, BundleArtifact, BundleMetadata,
+        BUNDLE_FORMAT_VERSION, BUNDLE_MANIFEST_FILE_NAME, BUNDLE_METADATA_FILE_NAME,
+    };
+    use crate::ims::image::r#struct::Image;
+
+    #[test]
+    fn test_bundle_metadata_round_trip() {
+        let source_dir = tempfile::tempdir().unwrap();
+
+        let bundle_metadata = BundleMetadata {
+            version: BUNDLE_FORMAT_VERSION.to_string(),
+            exported: chrono::Utc::now().to_rfc3339(),
+            image: Image {
+                id: Some("2105dd38-2c8e-48c5-8b3f-ca71367a977e".to_string()),
+                created: None,
+                name: "test-image".to_string(),
+                link: None,
+                arch: Some("x86_64".to_string()),
+            },
+            artifact_vec: vec![BundleArtifact {
+                name: "kernel".to_string(),
+                r#type: "application/vnd.cray.image.kernel".to_string(),
+                md5: None,
+                size: 6,
+            }],
+        };
+
+        std::fs::write(
+            source_dir.path().join(BUNDLE_METADATA_FILE_NAME),
+            serde_json::to_string(&bundle_metadata).unwrap(),
+        )
+        .unwrap();
+        std::fs::write(
+            source_dir.path().join(BUNDLE_MANIFEST_FILE_NAME),
+            r#"{"version": "1.0", "artifacts": []}"#,
+        )
+        .unwrap();
+        std::fs::write(source_dir.path().join("kernel"), "kernel").unwrap();
+
+        let bundle_path = source_dir.path().join("bundle.tar");
+
+        write_bundle(
+            &bundle_path,
+            source_dir.path(),
+            &[
+                BUNDLE_METADATA_FILE_NAME.to_string(),
+                BUNDLE_MANIFEST_FILE_NAME.to_string(),
+                "kernel".to_string(),
+            ],
+        )
+        .unwrap();
+
+        let bundle_metadata_read = get_bundle_metadata(&bundle_path).unwrap();
+
+        assert_eq!(bundle_metadata_read.image.name, "test-image");
+        assert_eq!(bundle_metadata_read.artifact_vec[0].name, "kernel");
+        assert!(get_bundle_metadata(&source_dir.path().join("kernel")).is_err());
+    }
+
+    #[test]
+    fn test_validate_artifact_name() {
+        assert!(validate_artifact_name("rootfs").is_ok());
+        assert!(validate_artifact_name("..").is_err());
+        assert!(validate_artifact_name("../../etc/passwd").is_err());
+        assert!(validate_artifact_name("/etc/passwd").is_err());
+        assert!(validate_artifact_name("").is_err());
+    }
+}
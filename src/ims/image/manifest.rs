@@ -0,0 +1,298 @@
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::ims::{
+    image::r#struct::{Image, ImsManifest, ImsManifestArtifact},
+    s3::{s3_head_object, split_s3_url},
+    s3_transfer::compute_object_md5,
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum ArtifactStatus {
+    Ok,
+    /// Object not found in S3
+    Missing,
+    /// Object exists in S3 but has no data
+    Empty,
+    /// Object in S3 is not the one referenced in the manifest
+    ETagMismatch { expected: String, actual: String },
+    ChecksumMismatch { expected: String, actual: String },
+    Error(String),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ArtifactVerification {
+    pub r#type: String,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<i64>,
+    pub status: ArtifactStatus,
+}
+
+/// Result of verifying the artifacts of an image
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImageVerification {
+    pub image_id: String,
+    pub image_name: String,
+    /// Error fetching the manifest (eg image without link or manifest not in S3)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manifest_error: Option<String>,
+    pub artifact_verification_vec: Vec<ArtifactVerification>,
+}
+
+impl ImageVerification {
+    pub fn is_valid(&self) -> bool {
+        self.manifest_error.is_none()
+            && self
+                .artifact_verification_vec
+                .iter()
+                .all(|artifact_verification| artifact_verification.status == ArtifactStatus::Ok)
+    }
+
+    /// Returns the artifacts with problems
+    pub fn get_broken_artifact_vec(&self) -> Vec<&ArtifactVerification> {
+        self.artifact_verification_vec
+            .iter()
+            .filter(|artifact_verification| artifact_verification.status != ArtifactStatus::Ok)
+            .collect()
+    }
+}
+
+/// Fetches the IMS manifest of an image from S3
+pub async fn get_manifest(sts_value: &Value, image: &Image) -> Result<ImsManifest, Box<dyn Error>> {
+    let manifest_path = image
+        .link
+        .as_ref()
+        .map(|link| link.path.clone())
+        .ok_or_else(|| {
+            format!(
+                "Image '{}' has no manifest",
+                image.id.clone().unwrap_or_default()
+            )
+        })?;
+
+    get_manifest_from_path(sts_value, &manifest_path).await
+}
+
+/// Fetches an IMS manifest from S3, eg 's3://boot-images/<image id>/manifest.json'
+pub async fn get_manifest_from_path(
+    sts_value: &Value,
+    manifest_path: &str,
+) -> Result<ImsManifest, Box<dyn Error>> {
+    let (bucket, key) = split_s3_url(manifest_path)
+        .ok_or_else(|| format!("Invalid manifest path '{}'", manifest_path))?;
+
+    let client = crate::ims::s3::setup_client(sts_value).await;
+
+    let get_object_output = client.get_object().bucket(bucket).key(key).send().await?;
+
+    let manifest_bytes = get_object_output.body.collect().await?.into_bytes();
+
+    Ok(serde_json::from_slice(&manifest_bytes)?)
+}
+
+/// Checks an artifact exists in S3, is not empty and matches the etag and md5 in the manifest.
+/// The md5 can be checked without downloading the object if it was uploaded in a single part
+/// (its ETag is the md5), otherwise the object needs to be streamed, this only happens if
+/// `deep` is true
+pub async fn verify_artifact(
+    sts_value: &Value,
+    artifact: &ImsManifestArtifact,
+    deep: bool,
+) -> ArtifactVerification {
+    let mut artifact_verification = ArtifactVerification {
+        r#type: artifact.r#type.clone(),
+        path: artifact.link.path.clone(),
+        size: None,
+        status: ArtifactStatus::Ok,
+    };
+
+    let (bucket, key) = match split_s3_url(&artifact.link.path) {
+        Some(bucket_key) => bucket_key,
+        None => {
+            artifact_verification.status =
+                ArtifactStatus::Error(format!("Invalid artifact path '{}'", artifact.link.path));
+            return artifact_verification;
+        }
+    };
+
+    let head_object_output = match s3_head_object(sts_value, &key, &bucket).await {
+        Ok(head_object_output) => head_object_output,
+        Err(_) => {
+            artifact_verification.status = ArtifactStatus::Missing;
+            return artifact_verification;
+        }
+    };
+
+    artifact_verification.size = head_object_output.content_length();
+
+    if head_object_output.content_length().unwrap_or_default() <= 0 {
+        artifact_verification.status = ArtifactStatus::Empty;
+        return artifact_verification;
+    }
+
+    let s3_etag = head_object_output
+        .e_tag()
+        .unwrap_or_default()
+        .trim_matches('"')
+        .to_string();
+
+    if let Some(expected_etag) = artifact
+        .link
+        .etag
+        .as_ref()
+        .map(|etag| etag.trim_matches('"'))
+        .filter(|etag| !etag.is_empty())
+    {
+        if expected_etag != s3_etag {
+            artifact_verification.status = ArtifactStatus::ETagMismatch {
+                expected: expected_etag.to_string(),
+                actual: s3_etag,
+            };
+            return artifact_verification;
+        }
+    }
+
+    if let Some(expected_md5) = artifact.md5.as_ref().filter(|md5| !md5.is_empty()) {
+        // Single part uploads have the md5 as ETag
+        let actual_md5_opt = if !s3_etag.contains('-') {
+            Some(s3_etag)
+        } else if deep {
+            match compute_object_md5(sts_value, &bucket, &key).await {
+                Ok(md5) => Some(md5),
+                Err(error) => {
+                    artifact_verification.status = ArtifactStatus::Error(error.to_string());
+                    return artifact_verification;
+                }
+            }
+        } else {
+            None
+        };
+
+        if let Some(actual_md5) = actual_md5_opt {
+            if *expected_md5 != actual_md5 {
+                artifact_verification.status = ArtifactStatus::ChecksumMismatch {
+                    expected: expected_md5.clone(),
+                    actual: actual_md5,
+                };
+            }
+        }
+    }
+
+    artifact_verification
+}
+
+/// Verifies all artifacts in the manifest of an image (see `verify_artifact`)
+pub async fn verify_image(sts_value: &Value, image: &Image, deep: bool) -> ImageVerification {
+    let mut image_verification = ImageVerification {
+        image_id: image.id.clone().unwrap_or_default(),
+        image_name: image.name.clone(),
+        manifest_error: None,
+        artifact_verification_vec: Vec::new(),
+    };
+
+    let manifest = match get_manifest(sts_value, image).await {
+        Ok(manifest) => manifest,
+        Err(error) => {
+            image_verification.manifest_error = Some(error.to_string());
+            return image_verification;
+        }
+    };
+
+    if manifest.artifacts.is_empty() {
+        image_verification.manifest_error = Some("Manifest has no artifacts".to_string());
+    }
+
+    for artifact in &manifest.artifacts {
+        image_verification
+            .artifact_verification_vec
+            .push(verify_artifact(sts_value, artifact, deep).await);
+    }
+
+    image_verification
+}
+
+/// Verifies a list of images and returns the ones with broken or missing artifacts. If
+/// `image_vec` is empty, all images in IMS are verified
+pub async fn get_broken_image_report(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    image_vec: &[Image],
+    deep: bool,
+) -> Result<Vec<ImageVerification>, Box<dyn Error>> {
+    let image_vec = if image_vec.is_empty() {
+        crate::ims::image::mesa::http_client::get_all(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+        )
+        .await?
+    } else {
+        image_vec.to_vec()
+    };
+
+    let sts_value =
+        crate::ims::s3::s3_auth(shasta_token, shasta_base_url, shasta_root_cert).await?;
+
+    let mut broken_image_vec = Vec::new();
+
+    for image in &image_vec {
+        log::debug!(
+            "Verifying image '{}' ({})",
+            image.name,
+            image.id.clone().unwrap_or_default()
+        );
+
+        let image_verification = verify_image(&sts_value, image, deep).await;
+
+        if !image_verification.is_valid() {
+            broken_image_vec.push(image_verification);
+        }
+    }
+
+    Ok(broken_image_vec)
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::ims::image::r#struct::ImsManifest;
+
+    #[test]
+    fn test_parse_ims_manifest() {
+        let manifest: ImsManifest = serde_json::from_str(
+            r#"{
+              "artifacts": [
+                {
+                  "link": {
+                    "etag": "c2c5d4b4b2d5e0e3a7b6f1f2b8c1a0d9-120",
+                    "path": "s3://boot-images/2105dd38-2c8e-48c5-8b3f-ca71367a977e/rootfs",
+                    "type": "s3"
+                  },
+                  "md5": "3fa5d1f1fd3e0a6d6a9ff6a3f2a7c2b1",
+                  "type": "application/vnd.cray.image.rootfs.squashfs"
+                },
+                {
+                  "link": {
+                    "etag": "9d1b8f0d1bfb9b36e5a1f0b8b5a7e6c4",
+                    "path": "s3://boot-images/2105dd38-2c8e-48c5-8b3f-ca71367a977e/kernel",
+                    "type": "s3"
+                  },
+                  "md5": "9d1b8f0d1bfb9b36e5a1f0b8b5a7e6c4",
+                  "type": "application/vnd.cray.image.kernel"
+                }
+              ],
+              "created": "2023-05-03 13:47:06.000000",
+              "version": "1.0"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(manifest.version, "1.0");
+        assert_eq!(manifest.artifacts.len(), 2);
+        assert_eq!(manifest.get_artifact("rootfs").unwrap().get_name(), "rootfs");
+        assert!(manifest.get_artifact("initrd").is_none());
+    }
+}
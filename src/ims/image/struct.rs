@@ -27,3 +27,43 @@ pub struct Image {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub arch: Option<String>,
 }
+
+/// IMS manifest (manifest.json) listing the artifacts of an image
+/// eg {"version": "1.0", "created": "2023-05-03 13:47:06.000000", "artifacts": [...]}
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ImsManifest {
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    pub artifacts: Vec<ImsManifestArtifact>,
+}
+
+impl ImsManifest {
+    /// Returns the artifact which type contains `artifact_type`, eg 'rootfs'
+    pub fn get_artifact(&self, artifact_type: &str) -> Option<&ImsManifestArtifact> {
+        self.artifacts
+            .iter()
+            .find(|artifact| artifact.r#type.contains(artifact_type))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ImsManifestArtifact {
+    /// eg 'application/vnd.cray.image.rootfs.squashfs'
+    pub r#type: String,
+    pub link: Link,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+}
+
+impl ImsManifestArtifact {
+    /// Returns the artifact file name, eg 'rootfs' for 's3://boot-images/<image id>/rootfs'
+    pub fn get_name(&self) -> String {
+        self.link
+            .path
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    }
+}
@@ -1,2 +1,3 @@
 pub mod http_client;
 pub mod r#struct;
+pub mod utils;
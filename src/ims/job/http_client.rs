@@ -2,9 +2,9 @@ use std::error::Error;
 
 use serde_json::Value;
 
-use super::r#struct::{Job, SshContainer};
+use super::r#struct::{Job, JobResponse};
 
-/// Get IMS job ref --> https://csm12-apidocs.svc.cscs.ch/paas/ims/operation/post_v3_job/
+/// Create IMS job to customize an image ref --> https://csm12-apidocs.svc.cscs.ch/paas/ims/operation/post_v3_job/
 pub async fn post(
     shasta_token: &str,
     shasta_base_url: &str,
@@ -13,26 +13,41 @@ pub async fn post(
     artifact_id: &str,
     public_key_id: &str,
 ) -> Result<Value, Box<dyn Error>> {
-    let ssh_container = SshContainer {
-        name: "jail".to_string(),
-        jail: true,
-    };
-
-    let ssh_container_list = vec![ssh_container];
-
-    let ims_job = Job {
-        job_type: "customize".to_string(),
-        image_root_archive_name: image_root_archive_name.to_string(),
-        kernel_file_name: Some("kernel".to_string()),
-        initrd_file_name: Some("initrd".to_string()),
-        kernel_parameters_file_name: None,
-        artifact_id: artifact_id.to_string(),
-        public_key_id: public_key_id.to_string(),
-        ssh_containers: Some(ssh_container_list),
-        enable_debug: Some(false),
-        buid_env_size: None,
-    };
+    let ims_job = Job::customize(artifact_id, image_root_archive_name, public_key_id)
+        .kernel_file_name("kernel")
+        .initrd_file_name("initrd")
+        .jail_ssh_container("jail")
+        .enable_debug(false)
+        .build();
+
+    post_job(shasta_token, shasta_base_url, shasta_root_cert, &ims_job).await
+}
 
+/// Create IMS job to build an image from a recipe ref --> https://csm12-apidocs.svc.cscs.ch/paas/ims/operation/post_v3_job/
+pub async fn post_create(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    image_root_archive_name: &str,
+    recipe_id: &str,
+    public_key_id: &str,
+) -> Result<Value, Box<dyn Error>> {
+    let ims_job = Job::create(recipe_id, image_root_archive_name, public_key_id)
+        .kernel_file_name("vmlinuz")
+        .initrd_file_name("initrd")
+        .enable_debug(false)
+        .build();
+
+    post_job(shasta_token, shasta_base_url, shasta_root_cert, &ims_job).await
+}
+
+/// Create IMS job ref --> https://csm12-apidocs.svc.cscs.ch/paas/ims/operation/post_v3_job/
+pub async fn post_job(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    ims_job: &Job,
+) -> Result<Value, Box<dyn Error>> {
     let client;
 
     let client_builder = reqwest::Client::builder()
@@ -55,7 +70,7 @@ pub async fn post(
     let resp = client
         .post(api_url)
         .bearer_auth(shasta_token)
-        .json(&ims_job)
+        .json(ims_job)
         .send()
         .await?;
 
@@ -70,7 +85,7 @@ pub async fn post(
     }
 }
 
-/// Create IMS job ref --> https://csm12-apidocs.svc.cscs.ch/paas/ims/operation/post_v3_job/
+/// Get IMS job ref --> https://csm12-apidocs.svc.cscs.ch/paas/ims/operation/get_v3_job/
 pub async fn get(
     shasta_token: &str,
     shasta_base_url: &str,
@@ -94,7 +109,7 @@ pub async fn get(
         client = client_builder.build()?;
     }
 
-    let api_url = shasta_base_url.to_owned() + "/ims/v3/jobs" + job_id;
+    let api_url = shasta_base_url.to_owned() + "/ims/v3/jobs/" + job_id;
 
     let resp = client.get(api_url).bearer_auth(shasta_token).send().await?;
 
@@ -108,3 +123,93 @@ pub async fn get(
         Err(response.into()) // Black magic conversion from Err(Box::new("my error msg")) which does not
     }
 }
+
+/// Get IMS job as a JobResponse
+pub async fn get_job(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    job_id: &str,
+) -> Result<JobResponse, Box<dyn Error>> {
+    let job_value = get(shasta_token, shasta_base_url, shasta_root_cert, job_id).await?;
+
+    Ok(serde_json::from_value(job_value)?)
+}
+
+/// Get all IMS jobs ref --> https://csm12-apidocs.svc.cscs.ch/paas/ims/operation/get_all_v3_jobs/
+pub async fn get_all(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+) -> Result<Vec<JobResponse>, Box<dyn Error>> {
+    let client;
+
+    let client_builder = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
+
+    // Build client
+    if std::env::var("SOCKS5").is_ok() {
+        // socks5 proxy
+        log::debug!("SOCKS5 enabled");
+        let socks5proxy = reqwest::Proxy::all(std::env::var("SOCKS5").unwrap())?;
+
+        // rest client to authenticate
+        client = client_builder.proxy(socks5proxy).build()?;
+    } else {
+        client = client_builder.build()?;
+    }
+
+    let api_url = shasta_base_url.to_owned() + "/ims/v3/jobs";
+
+    let resp = client.get(api_url).bearer_auth(shasta_token).send().await?;
+
+    if resp.status().is_success() {
+        Ok(resp.json::<Vec<JobResponse>>().await?)
+    } else {
+        let response: String = resp.text().await?;
+        log::error!("FAIL response: {:#?}", response);
+        Err(response.into())
+    }
+}
+
+/// Delete IMS job. IMS also removes the kubernetes resources of the job
+/// ref --> https://csm12-apidocs.svc.cscs.ch/paas/ims/operation/delete_v3_job/
+pub async fn delete(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    job_id: &str,
+) -> Result<(), Box<dyn Error>> {
+    let client;
+
+    let client_builder = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
+
+    // Build client
+    if std::env::var("SOCKS5").is_ok() {
+        // socks5 proxy
+        log::debug!("SOCKS5 enabled");
+        let socks5proxy = reqwest::Proxy::all(std::env::var("SOCKS5").unwrap())?;
+
+        // rest client to authenticate
+        client = client_builder.proxy(socks5proxy).build()?;
+    } else {
+        client = client_builder.build()?;
+    }
+
+    let api_url = shasta_base_url.to_owned() + "/ims/v3/jobs/" + job_id;
+
+    let resp = client
+        .delete(api_url)
+        .bearer_auth(shasta_token)
+        .send()
+        .await?;
+
+    if resp.status().is_success() {
+        Ok(())
+    } else {
+        let response: String = resp.text().await?;
+        log::error!("FAIL response: {:#?}", response);
+        Err(response.into())
+    }
+}
@@ -1,12 +1,19 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+/// Build a new image from an IMS recipe
+pub const JOB_TYPE_CREATE: &str = "create";
+/// Customize an existing IMS image
+pub const JOB_TYPE_CUSTOMIZE: &str = "customize";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct SshContainer {
     pub name: String,
     pub jail: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Job {
     pub job_type: String,
     pub image_root_archive_name: String,
@@ -22,6 +29,195 @@ pub struct Job {
     pub ssh_containers: Option<Vec<SshContainer>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub enable_debug: Option<bool>,
+    /// Size in Gb of the build environment
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_env_size: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub require_dkms: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+}
+
+impl Job {
+    /// Job to build a new image from an IMS recipe
+    pub fn create(recipe_id: &str, image_root_archive_name: &str, public_key_id: &str) -> JobBuilder {
+        JobBuilder::new(JOB_TYPE_CREATE, recipe_id, image_root_archive_name, public_key_id)
+    }
+
+    /// Job to customize an existing IMS image
+    pub fn customize(image_id: &str, image_root_archive_name: &str, public_key_id: &str) -> JobBuilder {
+        JobBuilder::new(JOB_TYPE_CUSTOMIZE, image_id, image_root_archive_name, public_key_id)
+    }
+}
+
+/// Builder for IMS jobs. Fields not set are not sent to IMS, so IMS defaults apply
+/// eg Job::customize(image_id, "my-image", public_key_id).jail_ssh_container("jail").enable_debug(true).build()
+#[derive(Debug, Clone, Default)]
+pub struct JobBuilder {
+    job: Job,
+}
+
+impl JobBuilder {
+    pub fn new(
+        job_type: &str,
+        artifact_id: &str,
+        image_root_archive_name: &str,
+        public_key_id: &str,
+    ) -> Self {
+        Self {
+            job: Job {
+                job_type: job_type.to_string(),
+                artifact_id: artifact_id.to_string(),
+                image_root_archive_name: image_root_archive_name.to_string(),
+                public_key_id: public_key_id.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn kernel_file_name(mut self, kernel_file_name: &str) -> Self {
+        self.job.kernel_file_name = Some(kernel_file_name.to_string());
+        self
+    }
+
+    pub fn initrd_file_name(mut self, initrd_file_name: &str) -> Self {
+        self.job.initrd_file_name = Some(initrd_file_name.to_string());
+        self
+    }
+
+    pub fn kernel_parameters_file_name(mut self, kernel_parameters_file_name: &str) -> Self {
+        self.job.kernel_parameters_file_name = Some(kernel_parameters_file_name.to_string());
+        self
+    }
+
+    /// Adds a SSH container, users can connect to it to customize the image
+    pub fn ssh_container(mut self, name: &str, jail: bool) -> Self {
+        self.job
+            .ssh_containers
+            .get_or_insert_with(Vec::new)
+            .push(SshContainer {
+                name: name.to_string(),
+                jail,
+            });
+        self
+    }
+
+    pub fn jail_ssh_container(self, name: &str) -> Self {
+        self.ssh_container(name, true)
+    }
+
+    pub fn enable_debug(mut self, enable_debug: bool) -> Self {
+        self.job.enable_debug = Some(enable_debug);
+        self
+    }
+
+    pub fn build_env_size(mut self, build_env_size: u32) -> Self {
+        self.job.build_env_size = Some(build_env_size);
+        self
+    }
+
+    pub fn require_dkms(mut self, require_dkms: bool) -> Self {
+        self.job.require_dkms = Some(require_dkms);
+        self
+    }
+
+    pub fn arch(mut self, arch: &str) -> Self {
+        self.job.arch = Some(arch.to_string());
+        self
+    }
+
+    pub fn build(self) -> Job {
+        self.job
+    }
+}
+
+/// IMS job status ref --> https://github.com/Cray-HPE/docs-csm/blob/release/1.5/api/ims.md#jobstatus
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    #[default]
+    Creating,
+    FetchingImage,
+    FetchingRecipe,
+    WaitingForRepos,
+    BuildingImage,
+    WaitingOnUser,
+    PackagingArtifacts,
+    Success,
+    Error,
+    #[serde(other)]
+    Unknown,
+}
+
+impl JobStatus {
+    /// Returns true if the job finished
+    pub fn is_final(&self) -> bool {
+        matches!(self, JobStatus::Success | JobStatus::Error)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ConnectionInfo {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SshContainerInfo {
+    pub name: String,
+    #[serde(default)]
+    pub jail: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// Connection info by network, eg 'cluster.local' and 'customer_access'
+    #[serde(default)]
+    pub connection_info: HashMap<String, ConnectionInfo>,
+}
+
+/// IMS job as returned by the API
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct JobResponse {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    pub job_type: String,
+    #[serde(default)]
+    pub status: JobStatus,
+    pub artifact_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_key_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_root_archive_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resultant_image_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kubernetes_job: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kubernetes_service: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kubernetes_configmap: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kubernetes_namespace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssh_containers: Option<Vec<SshContainerInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enable_debug: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build_env_size: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub buid_env_size: Option<u8>,
+    pub arch: Option<String>,
+}
+
+impl JobResponse {
+    /// Returns the host and port to SSH into the job container. Prefers the 'customer_access'
+    /// network (reachable from outside the cluster) over 'cluster.local'
+    pub fn get_ssh_connection_info(&self) -> Option<ConnectionInfo> {
+        let ssh_container = self.ssh_containers.as_ref()?.first()?;
+
+        ssh_container
+            .connection_info
+            .get("customer_access")
+            .or_else(|| ssh_container.connection_info.get("cluster.local"))
+            .cloned()
+    }
 }
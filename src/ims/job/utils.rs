@@ -0,0 +1,144 @@
+use std::{error::Error, time::Duration};
+
+use super::{
+    http_client,
+    r#struct::{JobResponse, JobStatus},
+};
+
+/// Polls an IMS job until it finishes (success or error) or waits for the user to connect to
+/// its SSH container (waiting_on_user). In the latter, the SSH connection info is available
+/// through JobResponse::get_ssh_connection_info.
+/// Returns an error if the job does not reach any of those states before `timeout_opt`
+pub async fn wait_for_job(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    job_id: &str,
+    poll_interval: Duration,
+    timeout_opt: Option<Duration>,
+) -> Result<JobResponse, Box<dyn Error>> {
+    let start = std::time::Instant::now();
+
+    let mut previous_status_opt: Option<JobStatus> = None;
+
+    loop {
+        let job =
+            http_client::get_job(shasta_token, shasta_base_url, shasta_root_cert, job_id).await?;
+
+        if previous_status_opt.as_ref() != Some(&job.status) {
+            log::info!("IMS job '{}' status: {:?}", job_id, job.status);
+            previous_status_opt = Some(job.status.clone());
+        }
+
+        if job.status.is_final() || job.status == JobStatus::WaitingOnUser {
+            return Ok(job);
+        }
+
+        if timeout_opt.is_some_and(|timeout| start.elapsed() >= timeout) {
+            return Err(format!(
+                "Timeout waiting for IMS job '{}', last status {:?}",
+                job_id, job.status
+            )
+            .into());
+        }
+
+        tokio::time::sleep(poll_interval).await;
+    }
+}
+
+/// Deletes the IMS jobs finished (success or error) more than `min_age` ago. Returns the ids of
+/// the jobs deleted. If `dry_run` is true, nothing is deleted
+pub async fn cleanup(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    min_age: chrono::Duration,
+    dry_run: bool,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let job_vec = http_client::get_all(shasta_token, shasta_base_url, shasta_root_cert).await?;
+
+    let now = chrono::Utc::now();
+
+    let job_id_vec: Vec<String> = job_vec
+        .into_iter()
+        .filter(|job| job.status.is_final())
+        .filter(|job| {
+            job.created
+                .as_ref()
+                .and_then(|created| chrono::DateTime::parse_from_rfc3339(created).ok())
+                .is_some_and(|created| now.signed_duration_since(created) >= min_age)
+        })
+        .map(|job| job.id)
+        .collect();
+
+    if !dry_run {
+        for job_id in &job_id_vec {
+            log::info!("Deleting IMS job '{}'", job_id);
+            http_client::delete(shasta_token, shasta_base_url, shasta_root_cert, job_id).await?;
+        }
+    }
+
+    Ok(job_id_vec)
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::ims::job::r#struct::{ConnectionInfo, Job, JobResponse, JobStatus};
+
+    #[test]
+    fn test_job_builder() {
+        let job_value = serde_json::to_value(
+            Job::customize("image-id", "my-image", "public-key-id")
+                .jail_ssh_container("jail")
+                .build_env_size(40)
+                .build(),
+        )
+        .unwrap();
+
+        assert_eq!(job_value["job_type"], "customize");
+        assert_eq!(job_value["build_env_size"], 40);
+        assert_eq!(job_value["ssh_containers"][0]["jail"], true);
+        assert!(job_value.get("enable_debug").is_none());
+    }
+
+    #[test]
+    fn test_job_response_ssh_connection_info() {
+        let job: JobResponse = serde_json::from_value(serde_json::json!({
+            "id": "ad5163d2-398d-4e93-94f0-2f439f114fe7",
+            "created": "2024-01-10T10:00:00.000000+00:00",
+            "job_type": "customize",
+            "status": "waiting_on_user",
+            "artifact_id": "image-id",
+            "ssh_containers": [{
+                "name": "jail",
+                "jail": true,
+                "status": "pending",
+                "connection_info": {
+                    "cluster.local": { "host": "cray-ims-ad5163d2-service.ims.svc.cluster.local", "port": 22 },
+                    "customer_access": { "host": "ad5163d2.ims.cmn.alps.cscs.ch", "port": 22 }
+                }
+            }]
+        }))
+        .unwrap();
+
+        assert_eq!(job.status, JobStatus::WaitingOnUser);
+        assert_eq!(
+            job.get_ssh_connection_info(),
+            Some(ConnectionInfo {
+                host: "ad5163d2.ims.cmn.alps.cscs.ch".to_string(),
+                port: 22
+            })
+        );
+
+        let job: JobResponse = serde_json::from_value(serde_json::json!({
+            "id": "ad5163d2-398d-4e93-94f0-2f439f114fe7",
+            "job_type": "create",
+            "status": "some_new_status",
+            "artifact_id": "recipe-id"
+        }))
+        .unwrap();
+
+        assert_eq!(job.status, JobStatus::Unknown);
+        assert!(job.get_ssh_connection_info().is_none());
+    }
+}
@@ -0,0 +1,141 @@
+use std::error::Error;
+
+use super::r#struct::{Recipe, RecipePatch};
+
+fn build_client(shasta_root_cert: &[u8]) -> Result<reqwest::Client, reqwest::Error> {
+    let client_builder = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
+
+    // Build client
+    if let Ok(socks5_env) = std::env::var("SOCKS5") {
+        // socks5 proxy
+        log::debug!("SOCKS5 enabled");
+        let socks5proxy = reqwest::Proxy::all(socks5_env)?;
+
+        // rest client to authenticate
+        client_builder.proxy(socks5proxy).build()
+    } else {
+        client_builder.build()
+    }
+}
+
+/// Get IMS recipes ref --> https://github.com/Cray-HPE/docs-csm/blob/release/1.5/api/ims.md#get_all_v3_recipes
+/// If recipe_id_opt is provided, the list returned will only have that recipe
+pub async fn get(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    recipe_id_opt: Option<&str>,
+) -> Result<Vec<Recipe>, Box<dyn Error>> {
+    let client = build_client(shasta_root_cert)?;
+
+    let api_url = if let Some(recipe_id) = recipe_id_opt {
+        shasta_base_url.to_owned() + "/ims/v3/recipes/" + recipe_id
+    } else {
+        shasta_base_url.to_owned() + "/ims/v3/recipes"
+    };
+
+    let resp = client.get(api_url).bearer_auth(shasta_token).send().await?;
+
+    if !resp.status().is_success() {
+        let response: String = resp.text().await?;
+        log::error!("FAIL response: {:#?}", response);
+        return Err(response.into());
+    }
+
+    let mut recipe_vec = if recipe_id_opt.is_some() {
+        vec![resp.json::<Recipe>().await?]
+    } else {
+        resp.json::<Vec<Recipe>>().await?
+    };
+
+    // Sort recipes by creation time order ASC
+    recipe_vec.sort_by(|a, b| a.created.cmp(&b.created));
+
+    Ok(recipe_vec)
+}
+
+/// Create IMS recipe ref --> https://github.com/Cray-HPE/docs-csm/blob/release/1.5/api/ims.md#post_v3_recipe
+pub async fn post(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    recipe: &Recipe,
+) -> Result<Recipe, Box<dyn Error>> {
+    let client = build_client(shasta_root_cert)?;
+
+    let api_url = shasta_base_url.to_owned() + "/ims/v3/recipes";
+
+    let resp = client
+        .post(api_url)
+        .bearer_auth(shasta_token)
+        .json(recipe)
+        .send()
+        .await?;
+
+    if resp.status().is_success() {
+        Ok(resp.json::<Recipe>().await?)
+    } else {
+        let response: String = resp.text().await?;
+        log::error!("FAIL response: {:#?}", response);
+        Err(response.into())
+    }
+}
+
+/// Update IMS recipe ref --> https://github.com/Cray-HPE/docs-csm/blob/release/1.5/api/ims.md#patch_v3_recipe
+pub async fn patch(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    recipe_id: &str,
+    recipe_patch: &RecipePatch,
+) -> Result<Recipe, Box<dyn Error>> {
+    let client = build_client(shasta_root_cert)?;
+
+    let api_url = shasta_base_url.to_owned() + "/ims/v3/recipes/" + recipe_id;
+
+    let resp = client
+        .patch(api_url)
+        .bearer_auth(shasta_token)
+        .json(recipe_patch)
+        .send()
+        .await?;
+
+    if resp.status().is_success() {
+        Ok(resp.json::<Recipe>().await?)
+    } else {
+        let response: String = resp.text().await?;
+        log::error!("FAIL response: {:#?}", response);
+        Err(response.into())
+    }
+}
+
+// Delete IMS recipe. First does a "soft delete", then a "permanent deletion"
+// soft delete --> https://github.com/Cray-HPE/docs-csm/blob/release/1.5/api/ims.md#delete_v3_recipe
+// permanent deletion --> https://github.com/Cray-HPE/docs-csm/blob/release/1.5/api/ims.md#delete_v3_deleted_recipe
+pub async fn delete(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    recipe_id: &str,
+) -> Result<(), Box<dyn Error>> {
+    let client = build_client(shasta_root_cert)?;
+
+    for api_url in [
+        shasta_base_url.to_owned() + "/ims/v3/recipes/" + recipe_id,
+        shasta_base_url.to_owned() + "/ims/v3/deleted/recipes/" + recipe_id,
+    ] {
+        let resp = client
+            .delete(api_url)
+            .bearer_auth(shasta_token)
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            log::debug!("{:#?}", resp);
+            return Err(resp.text().await?.into());
+        }
+    }
+
+    Ok(())
+}
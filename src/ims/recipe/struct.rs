@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ims::image::r#struct::Link;
+
+pub const RECIPE_TYPE_KIWI_NG: &str = "kiwi-ng";
+pub const RECIPE_TYPE_PACKER: &str = "packer";
+
+/// Template variable replaced by IMS in the recipe when building the image
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct TemplateVariable {
+    pub key: String,
+    pub value: String,
+}
+
+/// IMS recipe ref --> https://github.com/Cray-HPE/docs-csm/blob/release/1.5/api/ims.md#recipe
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Recipe {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link: Option<Link>,
+    /// eg 'kiwi-ng'
+    pub recipe_type: String,
+    /// eg 'sles15'
+    pub linux_distribution: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template_dictionary: Option<Vec<TemplateVariable>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub require_dkms: Option<bool>,
+}
+
+impl Recipe {
+    pub fn new(name: &str, recipe_type: &str, linux_distribution: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            recipe_type: recipe_type.to_string(),
+            linux_distribution: linux_distribution.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Adds a template variable or updates its value if it already exists
+    pub fn set_template_variable(&mut self, key: &str, value: &str) {
+        let template_dictionary = self.template_dictionary.get_or_insert_with(Vec::new);
+
+        match template_dictionary
+            .iter_mut()
+            .find(|template_variable| template_variable.key == key)
+        {
+            Some(template_variable) => template_variable.value = value.to_string(),
+            None => template_dictionary.push(TemplateVariable {
+                key: key.to_string(),
+                value: value.to_string(),
+            }),
+        }
+    }
+}
+
+/// Fields of a recipe that can be updated
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RecipePatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link: Option<Link>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template_dictionary: Option<Vec<TemplateVariable>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub require_dkms: Option<bool>,
+}
@@ -0,0 +1,108 @@
+use std::{error::Error, path::Path};
+
+use serde_json::Value;
+
+use crate::ims::{
+    image::r#struct::Link,
+    s3_transfer::{self, Checksum, TransferConfig},
+};
+
+use super::{
+    http_client,
+    r#struct::{Recipe, RecipePatch},
+};
+
+/// Bucket where IMS stores the recipe archives
+pub const RECIPE_BUCKET: &str = "ims";
+
+/// Uploads a recipe archive (eg a kiwi-ng description packed as .tar.gz) to S3 and links it to
+/// the IMS recipe. Returns the link to the archive
+pub async fn upload_recipe_archive(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    sts_value: &Value,
+    recipe_id: &str,
+    archive_path: &Path,
+) -> Result<Link, Box<dyn Error>> {
+    let key = format!("recipes/{}/recipe.tar.gz", recipe_id);
+
+    let transfer_outcome = s3_transfer::upload_object(
+        sts_value,
+        archive_path,
+        RECIPE_BUCKET,
+        &key,
+        &TransferConfig {
+            // Checks the archive against the ETag of the object uploaded
+            checksum_opt: Some(Checksum::ETag),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    let link = Link {
+        path: format!("s3://{}/{}", RECIPE_BUCKET, key),
+        etag: Some(transfer_outcome.etag),
+        r#type: "s3".to_string(),
+    };
+
+    http_client::patch(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        recipe_id,
+        &RecipePatch {
+            link: Some(link.clone()),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    Ok(link)
+}
+
+/// Registers a new recipe in IMS and uploads its archive. Returns the new recipe
+pub async fn create_recipe_from_archive(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    sts_value: &Value,
+    recipe: &Recipe,
+    archive_path: &Path,
+) -> Result<Recipe, Box<dyn Error>> {
+    let mut new_recipe =
+        http_client::post(shasta_token, shasta_base_url, shasta_root_cert, recipe).await?;
+
+    let recipe_id = new_recipe
+        .id
+        .clone()
+        .ok_or("IMS did not return the id of the new recipe")?;
+
+    let link = match upload_recipe_archive(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        sts_value,
+        &recipe_id,
+        archive_path,
+    )
+    .await
+    {
+        Ok(link) => link,
+        Err(error) => {
+            // Do not leave recipes without archive behind
+            if let Err(delete_error) =
+                http_client::delete(shasta_token, shasta_base_url, shasta_root_cert, &recipe_id)
+                    .await
+            {
+                log::warn!("Could not delete recipe '{}': {}", recipe_id, delete_error);
+            }
+
+            return Err(error);
+        }
+    };
+
+    new_recipe.link = Some(link);
+
+    Ok(new_recipe)
+}
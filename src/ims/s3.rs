@@ -1,15 +1,14 @@
 use aws_config::SdkConfig;
 use hyper::client::HttpConnector;
 use std::error::Error;
-use std::fs::File;
-use std::io::Write;
 use std::path::Path;
 
 use serde_json::Value;
 
 use anyhow::Result;
-use aws_sdk_s3::{primitives::ByteStream, Client};
-use indicatif::{ProgressBar,ProgressStyle};
+use aws_sdk_s3::{
+    config::Credentials, operation::head_object::HeadObjectOutput, primitives::ByteStream, Client,
+};
 
 pub const BAR_FORMAT: &str = "[{elapsed_precise}] {bar:40.cyan/blue} ({bytes_per_sec}) {bytes:>7}/{total_bytes:7} {msg} [ETA {eta}]";
 // Get a token for S3 and return the result
@@ -74,12 +73,60 @@ pub async fn s3_auth(
     }
 }
 
-async fn setup_client(sts_value: &Value) -> Client {
+/// Builds the same value returned by `s3_auth` from static credentials. Useful to work against
+/// an S3 endpoint outside CSM (eg a local MinIO used as a stand-in for the CSM S3 service)
+pub fn sts_value_from_credentials(
+    endpoint_url: &str,
+    access_key_id: &str,
+    secret_access_key: &str,
+) -> Value {
+    serde_json::json!({
+        "Credentials": {
+            "EndpointURL": endpoint_url,
+            "AccessKeyId": access_key_id,
+            "SecretAccessKey": secret_access_key,
+        }
+    })
+}
+
+/// Splits an S3 url into bucket and key
+/// eg "s3://boot-images/<image id>/manifest.json" --> ("boot-images", "<image id>/manifest.json")
+pub fn split_s3_url(s3_url: &str) -> Option<(String, String)> {
+    s3_url
+        .strip_prefix("s3://")
+        .and_then(|path| path.split_once('/'))
+        .map(|(bucket, key)| (bucket.to_string(), key.to_string()))
+}
+
+/// Returns the S3 credentials in the value returned by `s3_auth` or `sts_value_from_credentials`
+fn get_credentials(sts_value: &Value) -> Option<Credentials> {
+    let credentials_value = &sts_value["Credentials"];
+
+    Some(Credentials::new(
+        credentials_value["AccessKeyId"].as_str()?,
+        credentials_value["SecretAccessKey"].as_str()?,
+        credentials_value["SessionToken"]
+            .as_str()
+            .map(str::to_string),
+        None,
+        "sts",
+    ))
+}
+
+pub(crate) async fn setup_client(sts_value: &Value) -> Client {
     use aws_smithy_runtime::client::http::hyper_014::HyperClientBuilder;
 
     // Default provider fallback to us-east-1 since CSM doesn't use the concept of regions
     let region_provider =
         aws_config::meta::region::RegionProviderChain::default_provider().or_else("us-east-1");
+
+    // Credentials are passed to the client rather than through the AWS environment variables
+    // so clients with different credentials can be used at the same time
+    let config_loader = match get_credentials(sts_value) {
+        Some(credentials) => aws_config::from_env().credentials_provider(credentials),
+        None => aws_config::from_env(),
+    };
+
     let config: SdkConfig;
 
     if let Ok(socks5_env) = std::env::var("SOCKS5") {
@@ -103,7 +150,7 @@ async fn setup_client(sts_value: &Value) -> Client {
         //     .build(socks_http_connector);
         let http_client = HyperClientBuilder::new().build(socks_http_connector);
 
-        config = aws_config::from_env()
+        config = config_loader
             .region(region_provider)
             .http_client(http_client)
             .endpoint_url(sts_value["Credentials"]["EndpointURL"].as_str().unwrap())
@@ -112,7 +159,7 @@ async fn setup_client(sts_value: &Value) -> Client {
             .load()
             .await;
     } else {
-        config = aws_config::from_env()
+        config = config_loader
             .region(region_provider)
             .endpoint_url(sts_value["Credentials"]["EndpointURL"].as_str().unwrap())
             .app_name(aws_config::AppName::new("manta").unwrap())
@@ -130,6 +177,25 @@ async fn setup_client(sts_value: &Value) -> Client {
     );
     client
 }
+/// Gets the metadata of a given object in S3 without downloading it
+/// path of the object: s3://bucket/key
+pub async fn s3_head_object(
+    sts_value: &Value,
+    key: &str,
+    bucket: &str,
+) -> Result<HeadObjectOutput, Box<dyn Error>> {
+    let client = setup_client(sts_value).await;
+
+    match client.head_object().bucket(bucket).key(key).send().await {
+        Ok(head_object_output) => Ok(head_object_output),
+        Err(error) => Err(format!(
+            "Error, unable to get object 's3://{}/{}' metadata from s3. Error msg: {}",
+            bucket, key, error
+        )
+        .into()),
+    }
+}
+
 /// Gets the size of a given object in S3
 /// path of the object: s3://bucket/key
 /// returns i64 or error
@@ -138,15 +204,10 @@ pub async fn s3_get_object_size(
     key: &str,
     bucket: &str,
 ) -> Result<i64, Box<dyn Error>> {
-    let client = setup_client(sts_value).await;
-    let _object = match client.get_object()
-        .bucket(bucket)
-        .key(key)
-        .send()
-        .await {
-        Ok(object) => return Ok(object.content_length().clone().unwrap()),
-        Err(e) => panic!("Error, unable to get object size from s3. Error msg: {}", e)
-    };
+    s3_head_object(sts_value, key, bucket)
+        .await?
+        .content_length()
+        .ok_or_else(|| format!("Object 's3://{}/{}' has no size", bucket, key).into())
 }
 
 /// Gets an object from S3
@@ -167,48 +228,20 @@ pub async fn s3_download_object(
     bucket: &str,
     destination_path: &str,
 ) -> Result<String, Box<dyn Error>> {
-    let client = setup_client(sts_value).await;
-
-
-    let filename = Path::new(object_path).file_name().unwrap();
+    let filename = Path::new(object_path)
+        .file_name()
+        .ok_or_else(|| format!("Object path '{}' has no file name", object_path))?;
     let file_path = Path::new(destination_path).join(filename);
-    log::debug!("Create directory '{}'", destination_path);
 
-    match std::fs::create_dir_all(destination_path) {
-        Ok(_) => log::debug!("Created directory '{}' successfully", destination_path),
-        Err(error) => panic!("Error creating directory {}: {}", destination_path, error),
-    };
-    let mut file = match File::create(&file_path) {
-        Ok(file) => {
-            log::debug!(
-                "Created file '{}' successfully",
-                &file_path.to_string_lossy()
-            );
-            file
-        }
-        Err(error) => panic!(
-            "Error creating file {}: {}",
-            &file_path.to_string_lossy(),
-            error
-        ),
-    };
-
-    let mut object = client
-        .get_object()
-        .bucket(bucket)
-        .key(object_path)
-        .send()
-        .await?;
+    super::s3_transfer::download_object(
+        sts_value,
+        bucket,
+        object_path,
+        &file_path,
+        &super::s3_transfer::TransferConfig::default(),
+    )
+    .await?;
 
-    let bar_size = object.content_length().clone().unwrap();
-    let bar = ProgressBar::new(bar_size as u64);
-    bar.set_style(ProgressStyle::with_template(BAR_FORMAT).unwrap());
-
-    while let Some(bytes) = object.body.try_next().await? {
-        let bytes = file.write(&bytes)?;
-        bar.inc(bytes as u64);
-    }
-    bar.finish();
     Ok(file_path.to_string_lossy().to_string())
 }
 
@@ -276,9 +309,52 @@ pub async fn s3_remove_object(
             log::debug!("Cleaned file '{}' successfully", &object_path);
             Ok(String::from("client"))
         }
-        Err(error) => panic!("Error cleaning file {}: {}", &object_path, error),
-        //
+        Err(error) => Err(format!(
+            "Error, unable to remove object 's3://{}/{}'. Error msg: {}",
+            bucket, object_path, error
+        )
+        .into()),
+    }
+}
+
+/// Lists the keys of the objects in a bucket which start with `prefix`
+/// eg prefix "<image id>/" returns ["<image id>/kernel", "<image id>/initrd", ...]
+pub async fn s3_list_objects(
+    sts_value: &Value,
+    bucket: &str,
+    prefix: &str,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let client = setup_client(sts_value).await;
+
+    let mut key_vec = Vec::new();
+    let mut continuation_token_opt: Option<String> = None;
+
+    loop {
+        let list_objects_output = client
+            .list_objects_v2()
+            .bucket(bucket)
+            .prefix(prefix)
+            .set_continuation_token(continuation_token_opt)
+            .send()
+            .await?;
+
+        key_vec.extend(
+            list_objects_output
+                .contents()
+                .iter()
+                .filter_map(|object| object.key().map(|key| key.to_string())),
+        );
+
+        continuation_token_opt = list_objects_output
+            .next_continuation_token()
+            .map(|continuation_token| continuation_token.to_string());
+
+        if continuation_token_opt.is_none() {
+            break;
+        }
     }
+
+    Ok(key_vec)
 }
 
 /// Uploads an object to S3 using the multipart method
@@ -297,101 +373,14 @@ pub async fn s3_multipart_upload_object(
     bucket: &str,
     file_path: &str,
 ) -> Result<String, Box<dyn Error>> {
-    use aws_sdk_s3::operation::create_multipart_upload::CreateMultipartUploadOutput;
-    use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
-    use aws_smithy_types::byte_stream::Length;
-
-    let client = setup_client(sts_value).await;
-
-    //In bytes, minimum chunk size of 5MB. Increase CHUNK_SIZE to send larger chunks.
-    const CHUNK_SIZE: u64 = 1024 * 1024 * 5;
-    const MAX_CHUNKS: u64 = 10000;
-
-    // create multipart upload
-    let multipart_upload_res: CreateMultipartUploadOutput = client
-        .create_multipart_upload()
-        .bucket(bucket)
-        .key(object_path)
-        .send()
-        .await
-        .unwrap();
-
-    let upload_id = multipart_upload_res.upload_id().unwrap();
-
-    // Get details of the upload, this is needed because multipart uploads
-    // are tricky and have a minimum chunk size of 5MB
-    let path = Path::new(&file_path);
-    let file_size = std::fs::metadata(path)
-        .expect("it exists I swear")
-        .len();
-
-    let mut chunk_count = (file_size / CHUNK_SIZE) + 1;
-    let mut size_of_last_chunk = file_size % CHUNK_SIZE;
-    if size_of_last_chunk == 0 {
-        size_of_last_chunk = CHUNK_SIZE;
-        chunk_count -= 1;
-    }
-
-    let bar = ProgressBar::new(file_size);
-    bar.set_style(ProgressStyle::with_template(BAR_FORMAT).unwrap());
-
-    if file_size == 0 {
-        panic!("Bad file size.");
-    }
-    if chunk_count > MAX_CHUNKS {
-        panic!("Too many chunks! Try increasing your chunk size.")
-    }
-
-    let mut upload_parts: Vec<CompletedPart> = Vec::new();
-
-    for chunk_index in 0..chunk_count {
-        let this_chunk = if chunk_count - 1 == chunk_index {
-            size_of_last_chunk
-        } else {
-            CHUNK_SIZE
-        };
-        let stream = ByteStream::read_from()
-            .path(path)
-            .offset(chunk_index * CHUNK_SIZE)
-            .length(Length::Exact(this_chunk))
-            .build()
-            .await
-            .unwrap();
-        //Chunk index needs to start at 0, but part numbers start at 1.
-        let part_number = (chunk_index as i32) + 1;
-        let upload_part_res = client
-            .upload_part()
-            .key(object_path)
-            .bucket(bucket)
-            .upload_id(upload_id)
-            .body(stream)
-            .part_number(part_number)
-            .send()
-            .await?;
-        upload_parts.push(
-            CompletedPart::builder()
-                .e_tag(upload_part_res.e_tag.unwrap_or_default())
-                .part_number(part_number)
-                .build(),
-        );
-        bar.inc(this_chunk);
-    }
-    // complete the multipart upload
-    let completed_multipart_upload: CompletedMultipartUpload = CompletedMultipartUpload::builder()
-        .set_parts(Some(upload_parts))
-        .build();
-
-    let _complete_multipart_upload_res = client
-        .complete_multipart_upload()
-        .bucket(bucket)
-        .key(object_path)
-        .multipart_upload(completed_multipart_upload)
-        .upload_id(upload_id)
-        .send()
-        .await
-        .unwrap();
-
-    bar.finish();
-
-    Ok(_complete_multipart_upload_res.e_tag.clone().unwrap())
+    let transfer_outcome = super::s3_transfer::upload_object(
+        sts_value,
+        Path::new(file_path),
+        bucket,
+        object_path,
+        &super::s3_transfer::TransferConfig::default(),
+    )
+    .await?;
+
+    Ok(transfer_outcome.etag)
 }
@@ -0,0 +1,887 @@
+//! Transfer engine for large objects (eg squashfs images) between the local filesystem and S3.
+//!
+//! Objects are split in parts which are transferred concurrently (multipart upload and ranged
+//! download). The parts already transferred are recorded in a local state file so an
+//! interrupted transfer can be resumed by calling the same function again. Once the transfer
+//! finishes, the local file can be verified against a MD5 or SHA-256 checksum (eg from the IMS
+//! artifact manifest) or against the S3 ETag. After an upload, the object in S3 is also checked
+//! against the local file using the ETag returned by `head_object`.
+
+use std::{
+    error::Error,
+    fs::File,
+    io::{Read, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use aws_sdk_s3::{
+    primitives::ByteStream,
+    types::{CompletedMultipartUpload, CompletedPart},
+};
+use aws_smithy_types::byte_stream::Length;
+use indicatif::{ProgressBar, ProgressStyle};
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use tokio::{
+    io::{AsyncSeekExt, AsyncWriteExt},
+    sync::{Mutex, Semaphore},
+    task::JoinSet,
+};
+
+use super::s3::{setup_client, BAR_FORMAT};
+
+/// S3 does not accept parts smaller than 5MB (except the last one)
+pub const MIN_PART_SIZE: u64 = 1024 * 1024 * 5;
+/// S3 does not accept more than 10000 parts per object
+pub const MAX_PART_COUNT: u64 = 10000;
+
+/// Checksum used to verify a transfer
+#[derive(Debug, Clone, PartialEq)]
+pub enum Checksum {
+    /// MD5 hex digest of the whole object, eg the 'md5' field in IMS artifact manifests
+    Md5(String),
+    /// SHA-256 hex digest of the whole object
+    Sha256(String),
+    /// Compare the local file against the ETag returned by S3. Objects uploaded with multipart
+    /// can only be verified if they were uploaded with the same part size
+    ETag,
+}
+
+/// Transfer settings
+#[derive(Debug, Clone)]
+pub struct TransferConfig {
+    /// Size in bytes of each part, minimum 5MB
+    pub part_size: u64,
+    /// Number of parts transferred concurrently
+    pub parallelism: usize,
+    /// Directory where the state files are stored. If None, the state file is stored next to
+    /// the local file
+    pub state_dir_opt: Option<PathBuf>,
+    pub checksum_opt: Option<Checksum>,
+    /// Progress bar to report the bytes transferred. Share the same progress bar across
+    /// different transfers to get aggregated progress. If None, a new one is created
+    pub progress_bar_opt: Option<ProgressBar>,
+}
+
+impl Default for TransferConfig {
+    fn default() -> Self {
+        Self {
+            part_size: 1024 * 1024 * 16,
+            parallelism: 8,
+            state_dir_opt: None,
+            checksum_opt: None,
+            progress_bar_opt: None,
+        }
+    }
+}
+
+/// Result of a transfer
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TransferOutcome {
+    pub bucket: String,
+    pub key: String,
+    pub size: u64,
+    pub etag: String,
+    /// Number of parts reused from a previous (interrupted) transfer
+    pub resumed_part_count: usize,
+}
+
+/// Parts of a multipart upload already uploaded
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct UploadState {
+    bucket: String,
+    key: String,
+    upload_id: String,
+    file_size: u64,
+    file_modified_secs: u64,
+    part_size: u64,
+    /// Tuples like (part number, etag)
+    completed_part_vec: Vec<(i32, String)>,
+}
+
+/// Parts of a ranged download already downloaded
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct DownloadState {
+    bucket: String,
+    key: String,
+    etag: String,
+    object_size: u64,
+    part_size: u64,
+    /// Indexes of the parts downloaded (starting from 0)
+    completed_part_vec: Vec<u64>,
+}
+
+/// Uploads a local file to S3. Files bigger than `part_size` are uploaded using multipart
+/// uploads with `parallelism` parts being uploaded concurrently. If a previous upload of the
+/// same file to the same object was interrupted, the upload is resumed
+pub async fn upload_object(
+    sts_value: &Value,
+    file_path: &Path,
+    bucket: &str,
+    key: &str,
+    transfer_config: &TransferConfig,
+) -> Result<TransferOutcome, Box<dyn Error>> {
+    validate_transfer_config(transfer_config)?;
+
+    let client = setup_client(sts_value).await;
+
+    let file_metadata = std::fs::metadata(file_path)?;
+    let file_size = file_metadata.len();
+    let file_modified_secs = file_metadata
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+
+    let progress_bar = get_progress_bar(transfer_config, file_size);
+
+    // Small (or empty) files are uploaded in a single request
+    if file_size <= transfer_config.part_size {
+        let put_object_output = client
+            .put_object()
+            .bucket(bucket)
+            .key(key)
+            .body(ByteStream::from_path(file_path).await?)
+            .send()
+            .await?;
+
+        progress_bar.inc(file_size);
+        finish_progress_bar(transfer_config, &progress_bar);
+
+        let transfer_outcome = TransferOutcome {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            size: file_size,
+            etag: put_object_output
+                .e_tag()
+                .unwrap_or_default()
+                .trim_matches('"')
+                .to_string(),
+            resumed_part_count: 0,
+        };
+
+        verify_transfer(file_path, &transfer_outcome, transfer_config).await?;
+        verify_uploaded_object(&client, file_path, &transfer_outcome, transfer_config).await?;
+
+        return Ok(transfer_outcome);
+    }
+
+    let part_count = file_size.div_ceil(transfer_config.part_size);
+
+    if part_count > MAX_PART_COUNT {
+        return Err(format!(
+            "File '{}' needs {} parts but S3 accepts a maximum of {}. Please increase the part size",
+            file_path.display(),
+            part_count,
+            MAX_PART_COUNT
+        )
+        .into());
+    }
+
+    let state_file_path = get_state_file_path(transfer_config, file_path, bucket, key, "upload");
+
+    // Reuse previous upload if it is for the same file and part size
+    let upload_state = match read_state_file::<UploadState>(&state_file_path) {
+        Some(upload_state)
+            if upload_state.bucket == bucket
+                && upload_state.key == key
+                && upload_state.file_size == file_size
+                && upload_state.file_modified_secs == file_modified_secs
+                && upload_state.part_size == transfer_config.part_size =>
+        {
+            log::info!(
+                "Resuming upload of '{}' ({} parts already uploaded)",
+                file_path.display(),
+                upload_state.completed_part_vec.len()
+            );
+            upload_state
+        }
+        _ => {
+            let create_multipart_upload_output = client
+                .create_multipart_upload()
+                .bucket(bucket)
+                .key(key)
+                .send()
+                .await?;
+
+            UploadState {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+                upload_id: create_multipart_upload_output
+                    .upload_id()
+                    .ok_or("S3 did not return a multipart upload id")?
+                    .to_string(),
+                file_size,
+                file_modified_secs,
+                part_size: transfer_config.part_size,
+                completed_part_vec: Vec::new(),
+            }
+        }
+    };
+
+    write_state_file(&state_file_path, &upload_state)?;
+
+    let resumed_part_count = upload_state.completed_part_vec.len();
+    let upload_id = upload_state.upload_id.clone();
+
+    let completed_part_number_vec: Vec<i32> = upload_state
+        .completed_part_vec
+        .iter()
+        .map(|(part_number, _)| *part_number)
+        .collect();
+
+    let upload_state = Arc::new(Mutex::new(upload_state));
+
+    let semaphore = Arc::new(Semaphore::new(transfer_config.parallelism));
+
+    let mut tasks = JoinSet::new();
+
+    for part_index in 0..part_count {
+        // Part numbers start at 1
+        let part_number = part_index as i32 + 1;
+        let offset = part_index * transfer_config.part_size;
+        let length = transfer_config.part_size.min(file_size - offset);
+
+        if completed_part_number_vec.contains(&part_number) {
+            progress_bar.inc(length);
+            continue;
+        }
+
+        let permit = semaphore.clone().acquire_owned().await?;
+
+        let client = client.clone();
+        let bucket = bucket.to_string();
+        let key = key.to_string();
+        let upload_id = upload_id.clone();
+        let file_path = file_path.to_path_buf();
+        let state_file_path = state_file_path.clone();
+        let upload_state = upload_state.clone();
+        let progress_bar = progress_bar.clone();
+
+        tasks.spawn(async move {
+            let _permit = permit;
+
+            let byte_stream = ByteStream::read_from()
+                .path(&file_path)
+                .offset(offset)
+                .length(Length::Exact(length))
+                .build()
+                .await
+                .map_err(|error| error.to_string())?;
+
+            let upload_part_output = client
+                .upload_part()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .part_number(part_number)
+                .body(byte_stream)
+                .send()
+                .await
+                .map_err(|error| format!("Part {} failed: {}", part_number, error))?;
+
+            progress_bar.inc(length);
+
+            let mut upload_state = upload_state.lock().await;
+            upload_state.completed_part_vec.push((
+                part_number,
+                upload_part_output.e_tag().unwrap_or_default().to_string(),
+            ));
+            write_state_file(&state_file_path, &*upload_state).map_err(|error| error.to_string())
+        });
+    }
+
+    join_all(&mut tasks).await?;
+
+    let mut completed_part_vec = upload_state.lock().await.completed_part_vec.clone();
+    completed_part_vec.sort_by_key(|(part_number, _)| *part_number);
+
+    let completed_multipart_upload = CompletedMultipartUpload::builder()
+        .set_parts(Some(
+            completed_part_vec
+                .into_iter()
+                .map(|(part_number, etag)| {
+                    CompletedPart::builder()
+                        .part_number(part_number)
+                        .e_tag(etag)
+                        .build()
+                })
+                .collect(),
+        ))
+        .build();
+
+    let complete_multipart_upload_output = client
+        .complete_multipart_upload()
+        .bucket(bucket)
+        .key(key)
+        .upload_id(upload_id)
+        .multipart_upload(completed_multipart_upload)
+        .send()
+        .await?;
+
+    finish_progress_bar(transfer_config, &progress_bar);
+
+    let transfer_outcome = TransferOutcome {
+        bucket: bucket.to_string(),
+        key: key.to_string(),
+        size: file_size,
+        etag: complete_multipart_upload_output
+            .e_tag()
+            .unwrap_or_default()
+            .trim_matches('"')
+            .to_string(),
+        resumed_part_count,
+    };
+
+    remove_state_file(&state_file_path);
+
+    verify_transfer(file_path, &transfer_outcome, transfer_config).await?;
+    verify_uploaded_object(&client, file_path, &transfer_outcome, transfer_config).await?;
+
+    Ok(transfer_outcome)
+}
+
+/// Downloads an object from S3 to `file_path` using ranged requests with `parallelism` parts
+/// being downloaded concurrently. If a previous download of the same object to the same file was
+/// interrupted and the object did not change (same ETag), the download is resumed
+pub async fn download_object(
+    sts_value: &Value,
+    bucket: &str,
+    key: &str,
+    file_path: &Path,
+    transfer_config: &TransferConfig,
+) -> Result<TransferOutcome, Box<dyn Error>> {
+    validate_transfer_config(transfer_config)?;
+
+    let client = setup_client(sts_value).await;
+
+    let head_object_output = client.head_object().bucket(bucket).key(key).send().await?;
+
+    let object_size = head_object_output
+        .content_length()
+        .unwrap_or_default()
+        .max(0) as u64;
+    let etag = head_object_output
+        .e_tag()
+        .unwrap_or_default()
+        .trim_matches('"')
+        .to_string();
+
+    if let Some(parent) = file_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let state_file_path = get_state_file_path(transfer_config, file_path, bucket, key, "download");
+
+    // Reuse previous download if the object did not change and the file is still there
+    let download_state = match read_state_file::<DownloadState>(&state_file_path) {
+        Some(download_state)
+            if download_state.bucket == bucket
+                && download_state.key == key
+                && download_state.etag == etag
+                && download_state.object_size == object_size
+                && download_state.part_size == transfer_config.part_size
+                && std::fs::metadata(file_path)
+                    .is_ok_and(|metadata| metadata.len() == object_size) =>
+        {
+            log::info!(
+                "Resuming download of 's3://{}/{}' ({} parts already downloaded)",
+                bucket,
+                key,
+                download_state.completed_part_vec.len()
+            );
+            download_state
+        }
+        _ => {
+            // Allocate the whole file so each part can be written at its offset
+            File::create(file_path)?.set_len(object_size)?;
+
+            DownloadState {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+                etag: etag.clone(),
+                object_size,
+                part_size: transfer_config.part_size,
+                completed_part_vec: Vec::new(),
+            }
+        }
+    };
+
+    write_state_file(&state_file_path, &download_state)?;
+
+    let resumed_part_count = download_state.completed_part_vec.len();
+    let completed_part_index_vec = download_state.completed_part_vec.clone();
+
+    let download_state = Arc::new(Mutex::new(download_state));
+
+    let progress_bar = get_progress_bar(transfer_config, object_size);
+
+    let semaphore = Arc::new(Semaphore::new(transfer_config.parallelism));
+
+    let mut tasks = JoinSet::new();
+
+    for part_index in 0..object_size.div_ceil(transfer_config.part_size) {
+        let offset = part_index * transfer_config.part_size;
+        let length = transfer_config.part_size.min(object_size - offset);
+
+        if completed_part_index_vec.contains(&part_index) {
+            progress_bar.inc(length);
+            continue;
+        }
+
+        let permit = semaphore.clone().acquire_owned().await?;
+
+        let client = client.clone();
+        let bucket = bucket.to_string();
+        let key = key.to_string();
+        let file_path = file_path.to_path_buf();
+        let state_file_path = state_file_path.clone();
+        let download_state = download_state.clone();
+        let progress_bar = progress_bar.clone();
+
+        tasks.spawn(async move {
+            let _permit = permit;
+
+            let mut get_object_output = client
+                .get_object()
+                .bucket(bucket)
+                .key(key)
+                .range(format!("bytes={}-{}", offset, offset + length - 1))
+                .send()
+                .await
+                .map_err(|error| format!("Part {} failed: {}", part_index, error))?;
+
+            let mut file = tokio::fs::OpenOptions::new()
+                .write(true)
+                .open(&file_path)
+                .await
+                .map_err(|error| error.to_string())?;
+
+            file.seek(SeekFrom::Start(offset))
+                .await
+                .map_err(|error| error.to_string())?;
+
+            while let Some(bytes) = get_object_output
+                .body
+                .try_next()
+                .await
+                .map_err(|error| format!("Part {} failed: {}", part_index, error))?
+            {
+                file.write_all(&bytes)
+                    .await
+                    .map_err(|error| error.to_string())?;
+                progress_bar.inc(bytes.len() as u64);
+            }
+
+            file.flush().await.map_err(|error| error.to_string())?;
+
+            let mut download_state = download_state.lock().await;
+            download_state.completed_part_vec.push(part_index);
+            write_state_file(&state_file_path, &*download_state).map_err(|error| error.to_string())
+        });
+    }
+
+    join_all(&mut tasks).await?;
+
+    finish_progress_bar(transfer_config, &progress_bar);
+
+    remove_state_file(&state_file_path);
+
+    let transfer_outcome = TransferOutcome {
+        bucket: bucket.to_string(),
+        key: key.to_string(),
+        size: object_size,
+        etag,
+        resumed_part_count,
+    };
+
+    verify_transfer(file_path, &transfer_outcome, transfer_config).await?;
+
+    Ok(transfer_outcome)
+}
+
+/// Returns the MD5 hex digest of an object in S3. The object is streamed, nothing is stored in
+/// the local filesystem
+pub async fn compute_object_md5(
+    sts_value: &Value,
+    bucket: &str,
+    key: &str,
+) -> Result<String, Box<dyn Error>> {
+    let client = setup_client(sts_value).await;
+
+    let mut get_object_output = client.get_object().bucket(bucket).key(key).send().await?;
+
+    let mut hasher = Md5::new();
+
+    while let Some(bytes) = get_object_output.body.try_next().await? {
+        hasher.update(&bytes);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Returns the MD5 hex digest of a file
+pub fn compute_file_md5(file_path: &Path) -> Result<String, Box<dyn Error>> {
+    let mut hasher = Md5::new();
+    read_file_in_chunks(file_path, |bytes| hasher.update(bytes))?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Returns the SHA-256 hex digest of a file
+pub fn compute_file_sha256(file_path: &Path) -> Result<String, Box<dyn Error>> {
+    let mut hasher = Sha256::new();
+    read_file_in_chunks(file_path, |bytes| hasher.update(bytes))?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Returns the ETag S3 assigns to an object uploaded with multipart using `part_size` parts,
+/// that is the MD5 of the concatenation of the MD5 of each part followed by the number of parts
+/// eg "d41d8cd98f00b204e9800998ecf8427e-3"
+pub fn compute_file_multipart_etag(
+    file_path: &Path,
+    part_size: u64,
+) -> Result<String, Box<dyn Error>> {
+    let mut file = File::open(file_path)?;
+
+    let mut part_digest_vec: Vec<u8> = Vec::new();
+    let mut part_count = 0;
+
+    loop {
+        let mut part_hasher = Md5::new();
+        let bytes_read = std::io::copy(
+            &mut (&mut file).take(part_size),
+            &mut HashWriter(&mut part_hasher),
+        )?;
+
+        if bytes_read == 0 && part_count > 0 {
+            break;
+        }
+
+        part_digest_vec.extend(part_hasher.finalize());
+        part_count += 1;
+
+        if bytes_read < part_size {
+            break;
+        }
+    }
+
+    Ok(format!(
+        "{:x}-{}",
+        Md5::digest(&part_digest_vec),
+        part_count
+    ))
+}
+
+/// Checks a local file matches an S3 ETag. Returns None if the ETag belongs to a multipart
+/// upload with a different part size and therefore can't be verified
+pub fn file_matches_etag(
+    file_path: &Path,
+    etag: &str,
+    part_size: u64,
+) -> Result<Option<bool>, Box<dyn Error>> {
+    let etag = etag.trim_matches('"');
+
+    match etag.split_once('-') {
+        None => Ok(Some(compute_file_md5(file_path)? == etag)),
+        Some((_, part_count)) => {
+            let local_etag = compute_file_multipart_etag(file_path, part_size)?;
+
+            if local_etag.ends_with(&format!("-{}", part_count)) {
+                Ok(Some(local_etag == etag))
+            } else {
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Verifies the local file against the checksum in the transfer config (if any). On uploads,
+/// `Checksum::Md5` and `Checksum::Sha256` only check the local file, `verify_uploaded_object`
+/// checks the object
+async fn verify_transfer(
+    file_path: &Path,
+    transfer_outcome: &TransferOutcome,
+    transfer_config: &TransferConfig,
+) -> Result<(), Box<dyn Error>> {
+    let checksum = match &transfer_config.checksum_opt {
+        Some(checksum) => checksum.clone(),
+        None => return Ok(()),
+    };
+
+    let file_path_buf = file_path.to_path_buf();
+    let etag = transfer_outcome.etag.clone();
+    let part_size = transfer_config.part_size;
+
+    // Hashing big files is CPU bound
+    let verification_rslt = tokio::task::spawn_blocking(move || {
+        let is_valid = match &checksum {
+            Checksum::Md5(md5) => {
+                Some(compute_file_md5(&file_path_buf).map_err(|e| e.to_string())? == *md5)
+            }
+            Checksum::Sha256(sha256) => {
+                Some(compute_file_sha256(&file_path_buf).map_err(|e| e.to_string())? == *sha256)
+            }
+            Checksum::ETag => {
+                file_matches_etag(&file_path_buf, &etag, part_size).map_err(|e| e.to_string())?
+            }
+        };
+
+        Ok::<(Checksum, Option<bool>), String>((checksum, is_valid))
+    })
+    .await?;
+
+    match verification_rslt? {
+        (_, Some(true)) => {
+            log::debug!(
+                "File '{}' verified against 's3://{}/{}'",
+                file_path.display(),
+                transfer_outcome.bucket,
+                transfer_outcome.key
+            );
+            Ok(())
+        }
+        (checksum, Some(false)) => Err(format!(
+            "Checksum mismatch between '{}' and 's3://{}/{}' ({:?})",
+            file_path.display(),
+            transfer_outcome.bucket,
+            transfer_outcome.key,
+            checksum
+        )
+        .into()),
+        (_, None) => {
+            log::warn!(
+                "Could not verify '{}' against ETag '{}', object was uploaded with a different part size",
+                file_path.display(),
+                transfer_outcome.etag
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Verifies an uploaded object matches the local file by comparing the ETag of the object in S3
+/// with the one computed from the local file. Nothing is verified if the transfer config has no
+/// checksum or if it is `Checksum::ETag` (already verified by `verify_transfer`)
+async fn verify_uploaded_object(
+    client: &aws_sdk_s3::Client,
+    file_path: &Path,
+    transfer_outcome: &TransferOutcome,
+    transfer_config: &TransferConfig,
+) -> Result<(), Box<dyn Error>> {
+    if matches!(transfer_config.checksum_opt, None | Some(Checksum::ETag)) {
+        return Ok(());
+    }
+
+    let head_object_output = client
+        .head_object()
+        .bucket(&transfer_outcome.bucket)
+        .key(&transfer_outcome.key)
+        .send()
+        .await?;
+
+    let object_etag = head_object_output
+        .e_tag()
+        .unwrap_or_default()
+        .trim_matches('"')
+        .to_string();
+
+    let file_path_buf = file_path.to_path_buf();
+    let part_size = transfer_config.part_size;
+
+    // Hashing big files is CPU bound
+    let is_valid_opt = tokio::task::spawn_blocking(move || {
+        file_matches_etag(&file_path_buf, &object_etag, part_size).map_err(|e| e.to_string())
+    })
+    .await??;
+
+    if is_valid_opt == Some(true) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Object 's3://{}/{}' does not match '{}'",
+            transfer_outcome.bucket,
+            transfer_outcome.key,
+            file_path.display()
+        )
+        .into())
+    }
+}
+
+fn validate_transfer_config(transfer_config: &TransferConfig) -> Result<(), Box<dyn Error>> {
+    if transfer_config.part_size < MIN_PART_SIZE {
+        return Err(format!(
+            "Part size {} is too small, minimum part size is {} bytes",
+            transfer_config.part_size, MIN_PART_SIZE
+        )
+        .into());
+    }
+
+    if transfer_config.parallelism == 0 {
+        return Err("Parallelism must be greater than 0".into());
+    }
+
+    Ok(())
+}
+
+fn get_progress_bar(transfer_config: &TransferConfig, size: u64) -> ProgressBar {
+    match &transfer_config.progress_bar_opt {
+        Some(progress_bar) => {
+            // Shared progress bar, add this transfer size to the total
+            progress_bar.inc_length(size);
+            progress_bar.clone()
+        }
+        None => {
+            let progress_bar = ProgressBar::new(size);
+            progress_bar.set_style(ProgressStyle::with_template(BAR_FORMAT).unwrap());
+            progress_bar
+        }
+    }
+}
+
+/// Finishes the progress bar unless it is shared with other transfers
+fn finish_progress_bar(transfer_config: &TransferConfig, progress_bar: &ProgressBar) {
+    if transfer_config.progress_bar_opt.is_none() {
+        progress_bar.finish();
+    }
+}
+
+fn get_state_file_path(
+    transfer_config: &TransferConfig,
+    file_path: &Path,
+    bucket: &str,
+    key: &str,
+    direction: &str,
+) -> PathBuf {
+    let state_file_name = format!(
+        "{}_{}.{}.state.json",
+        bucket,
+        key.replace('/', "_"),
+        direction
+    );
+
+    match &transfer_config.state_dir_opt {
+        Some(state_dir) => state_dir.join(state_file_name),
+        None => file_path
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join(format!(".{}", state_file_name)),
+    }
+}
+
+fn read_state_file<T: for<'de> Deserialize<'de>>(state_file_path: &Path) -> Option<T> {
+    std::fs::read_to_string(state_file_path)
+        .ok()
+        .and_then(|file_content| serde_json::from_str(&file_content).ok())
+}
+
+fn write_state_file<T: Serialize>(state_file_path: &Path, state: &T) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = state_file_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    std::fs::write(state_file_path, serde_json::to_string(state)?)?;
+
+    Ok(())
+}
+
+fn remove_state_file(state_file_path: &Path) {
+    if let Err(error) = std::fs::remove_file(state_file_path) {
+        log::warn!(
+            "Could not remove transfer state file '{}': {}",
+            state_file_path.display(),
+            error
+        );
+    }
+}
+
+/// Waits for all parts. The first failure aborts the remaining parts, the ones already
+/// transferred are kept in the state file so the transfer can be resumed
+async fn join_all(tasks: &mut JoinSet<Result<(), String>>) -> Result<(), Box<dyn Error>> {
+    while let Some(task_rslt) = tasks.join_next().await {
+        if let Err(error) = task_rslt? {
+            tasks.abort_all();
+            return Err(error.into());
+        }
+    }
+
+    Ok(())
+}
+
+fn read_file_in_chunks<F: FnMut(&[u8])>(
+    file_path: &Path,
+    mut process: F,
+) -> Result<(), Box<dyn Error>> {
+    let mut file = File::open(file_path)?;
+    let mut buffer = vec![0; 1024 * 1024 * 8];
+
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+
+        if bytes_read == 0 {
+            break;
+        }
+
+        process(&buffer[..bytes_read]);
+    }
+
+    Ok(())
+}
+
+/// Adapter to feed a hasher through std::io::copy
+struct HashWriter<'a>(&'a mut Md5);
+
+impl std::io::Write for HashWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::io::Write;
+
+    use super::{compute_file_md5, compute_file_multipart_etag, file_matches_etag};
+
+    #[test]
+    fn test_compute_file_etag() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&[b'a'; 10]).unwrap();
+
+        let md5 = compute_file_md5(file.path()).unwrap();
+
+        assert_eq!(md5, "e09c80c42fda55f9d992e59ca6b3307d");
+        assert_eq!(
+            file_matches_etag(file.path(), &format!("\"{}\"", md5), 4).unwrap(),
+            Some(true)
+        );
+
+        // 3 parts: 4 + 4 + 2 bytes
+        let multipart_etag = compute_file_multipart_etag(file.path(), 4).unwrap();
+
+        assert!(multipart_etag.ends_with("-3"));
+        assert_eq!(
+            file_matches_etag(file.path(), &multipart_etag, 4).unwrap(),
+            Some(true)
+        );
+        // Different part size, can't be verified
+        assert_eq!(
+            file_matches_etag(file.path(), &multipart_etag, 5).unwrap(),
+            None
+        );
+
+        // Empty files have 1 part
+        let empty_file = tempfile::NamedTempFile::new().unwrap();
+        assert!(compute_file_multipart_etag(empty_file.path(), 4)
+            .unwrap()
+            .ends_with("-1"));
+    }
+}
@@ -8,3 +8,4 @@ pub mod config;
 pub mod hsm;
 pub mod ims;
 pub mod node;
+pub mod sls;
@@ -1,4 +1,8 @@
+pub mod boot_phase;
 pub mod console;
+pub mod console_log;
+pub mod csm_node;
+pub mod resolver;
 pub mod r#struct;
 pub mod traits;
 pub mod utils;
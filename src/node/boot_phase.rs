@@ -0,0 +1,516 @@
+use std::{collections::HashMap, error::Error, path::Path, time::Duration};
+
+use chrono::{DateTime, TimeZone, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::console_log::{ConsoleEvent, ConsoleLine};
+
+/// Boot phases in the order a node goes through them
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BootPhase {
+    /// Firmware power on self test
+    Post,
+    /// Network boot (PXE/iPXE) downloading kernel and initrd
+    Pxe,
+    Kernel,
+    /// initramfs (dracut) mounting the rootfs
+    Dracut,
+    Systemd,
+    /// Login prompt, the node finished booting
+    Login,
+}
+
+/// Regular expression identifying the console lines of a boot phase
+#[derive(Debug, Clone)]
+pub struct BootPhaseRule {
+    pub boot_phase: BootPhase,
+    pub regex: Regex,
+}
+
+impl BootPhaseRule {
+    pub fn new(boot_phase: BootPhase, pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            boot_phase,
+            regex: Regex::new(pattern)?,
+        })
+    }
+}
+
+/// Kernel messages start with the time since boot, eg '[    0.000000] Linux version ...'
+const KERNEL_LINE_PATTERN: &str = r"^\s*\[\s*\d+\.\d+\]";
+
+/// Output of systemd running inside the initrd (dracut), eg
+/// 'Welcome to SUSE Linux Enterprise 15 SP4 dracut-055 (Initramfs)!' or
+/// '[  OK  ] Reached target Initrd Root Device.'
+const INITRD_LINE_PATTERN: &str = r"(?i)(\(Initramfs\)|Initrd|in initrd|dracut)";
+
+/// systemd leaving the initrd for the real root filesystem
+const SWITCH_ROOT_PATTERN: &str = r"(?i)switch(ing)? root";
+
+/// Rules matching the console output of HPE/Cray nodes. POST patterns only match firmware
+/// output, the kernel also prints 'BIOS', 'UEFI' or 'EDK II' while booting (eg
+/// 'BIOS-provided physical RAM map' or 'efi: EFI v2.70 by EDK II'). 'systemd[1]' messages are
+/// not used for systemd since they are printed from the initrd as well
+pub fn get_default_boot_phase_rule_vec() -> Vec<BootPhaseRule> {
+    [
+        (
+            BootPhase::Post,
+            r"(?i)(POST code|UEFI Interactive Shell|BIOS (Version|Date|Revision)\b|American Megatrends|Press (<|\[)?(F2|F10|F12|DEL|ESC))",
+        ),
+        (
+            BootPhase::Pxe,
+            r"(?i)(iPXE|\bPXE\b|DHCP|Downloading NBP|net\d+: [0-9a-f:]+ using|http://\S+/(kernel|initrd))",
+        ),
+        (
+            BootPhase::Kernel,
+            r"(Linux version \d|Booting Linux|Kernel command line:)",
+        ),
+        (
+            BootPhase::Dracut,
+            r"(?i)(dracut(-[a-z-]+)?(\[\d+\])?:|Starting dracut|Mounting (root|/sysroot)|\(Initramfs\)!|Reached target Initrd|Running in initrd)",
+        ),
+        (
+            BootPhase::Systemd,
+            r"(Welcome to .*!|Reached target|\[\s*OK\s*\] |Switching root)",
+        ),
+        (BootPhase::Login, r"login:\s*$"),
+    ]
+    .into_iter()
+    .map(|(boot_phase, pattern)| BootPhaseRule::new(boot_phase, pattern).unwrap())
+    .collect()
+}
+
+/// Maximum time a node can stay in each boot phase before it is considered stalled
+pub fn get_default_boot_phase_timeout_map() -> HashMap<BootPhase, Duration> {
+    HashMap::from([
+        (BootPhase::Post, Duration::from_secs(600)),
+        (BootPhase::Pxe, Duration::from_secs(300)),
+        (BootPhase::Kernel, Duration::from_secs(120)),
+        (BootPhase::Dracut, Duration::from_secs(600)),
+        (BootPhase::Systemd, Duration::from_secs(600)),
+    ])
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BootPhaseDuration {
+    pub boot_phase: BootPhase,
+    pub start: DateTime<Utc>,
+    /// None while the node is still in this phase
+    pub end_opt: Option<DateTime<Utc>>,
+}
+
+/// Boot progress of a node
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NodeBootProgress {
+    pub xname: String,
+    pub boot_phase_duration_vec: Vec<BootPhaseDuration>,
+    pub last_line_opt: Option<String>,
+    /// Time the node started being watched (see `BootPhaseClassifier::watch_node_vec`), used to
+    /// detect nodes without any console output
+    #[serde(default)]
+    pub watch_start_opt: Option<DateTime<Utc>>,
+}
+
+impl NodeBootProgress {
+    pub fn new(xname: &str) -> Self {
+        Self {
+            xname: xname.to_string(),
+            boot_phase_duration_vec: Vec::new(),
+            last_line_opt: None,
+            watch_start_opt: None,
+        }
+    }
+
+    pub fn get_boot_phase(&self) -> Option<BootPhase> {
+        self.boot_phase_duration_vec
+            .last()
+            .map(|boot_phase_duration| boot_phase_duration.boot_phase)
+    }
+
+    /// Time the node has been in its current phase
+    pub fn get_boot_phase_elapsed(&self, now: DateTime<Utc>) -> Option<Duration> {
+        self.boot_phase_duration_vec
+            .last()
+            .and_then(|boot_phase_duration| (now - boot_phase_duration.start).to_std().ok())
+    }
+
+    /// Returns true if the node has been longer than the timeout in its current phase. A watched
+    /// node without any boot phase is stalled once the POST timeout passed since it started
+    /// being watched
+    pub fn is_stalled(
+        &self,
+        now: DateTime<Utc>,
+        boot_phase_timeout_map: &HashMap<BootPhase, Duration>,
+    ) -> bool {
+        let (boot_phase, elapsed_opt) = match self.get_boot_phase() {
+            Some(boot_phase) => (boot_phase, self.get_boot_phase_elapsed(now)),
+            None => (
+                BootPhase::Post,
+                self.watch_start_opt
+                    .and_then(|watch_start| (now - watch_start).to_std().ok()),
+            ),
+        };
+
+        elapsed_opt.is_some_and(|elapsed| {
+            boot_phase_timeout_map
+                .get(&boot_phase)
+                .is_some_and(|timeout| elapsed > *timeout)
+        })
+    }
+
+    fn enter_boot_phase(&mut self, boot_phase: BootPhase, timestamp: DateTime<Utc>) {
+        // A node going back to POST is rebooting, previous boot is discarded
+        if boot_phase == BootPhase::Post && self.get_boot_phase() > Some(BootPhase::Post) {
+            self.boot_phase_duration_vec.clear();
+        }
+
+        if let Some(boot_phase_duration) = self.boot_phase_duration_vec.last_mut() {
+            boot_phase_duration.end_opt = Some(timestamp);
+        }
+
+        self.boot_phase_duration_vec.push(BootPhaseDuration {
+            boot_phase,
+            start: timestamp,
+            end_opt: None,
+        });
+    }
+}
+
+/// Tracks the boot phase of nodes from their console output. Works both with live console logs
+/// (see `node::console_log::tail_console_log`) and with recorded ones
+#[derive(Debug, Clone)]
+pub struct BootPhaseClassifier {
+    pub boot_phase_rule_vec: Vec<BootPhaseRule>,
+    pub boot_phase_timeout_map: HashMap<BootPhase, Duration>,
+    node_boot_progress_map: HashMap<String, NodeBootProgress>,
+    kernel_line_regex: Regex,
+    initrd_line_regex: Regex,
+    switch_root_regex: Regex,
+}
+
+impl Default for BootPhaseClassifier {
+    fn default() -> Self {
+        Self::new(
+            get_default_boot_phase_rule_vec(),
+            get_default_boot_phase_timeout_map(),
+        )
+    }
+}
+
+impl BootPhaseClassifier {
+    pub fn new(
+        boot_phase_rule_vec: Vec<BootPhaseRule>,
+        boot_phase_timeout_map: HashMap<BootPhase, Duration>,
+    ) -> Self {
+        Self {
+            boot_phase_rule_vec,
+            boot_phase_timeout_map,
+            node_boot_progress_map: HashMap::new(),
+            kernel_line_regex: Regex::new(KERNEL_LINE_PATTERN).unwrap(),
+            initrd_line_regex: Regex::new(INITRD_LINE_PATTERN).unwrap(),
+            switch_root_regex: Regex::new(SWITCH_ROOT_PATTERN).unwrap(),
+        }
+    }
+
+    /// Starts watching nodes, so the ones which do not print anything are reported as stalled
+    pub fn watch_node_vec(&mut self, xname_vec: &[String], start: DateTime<Utc>) {
+        for xname in xname_vec {
+            self.node_boot_progress_map
+                .entry(xname.clone())
+                .or_insert_with(|| NodeBootProgress::new(xname))
+                .watch_start_opt
+                .get_or_insert(start);
+        }
+    }
+
+    /// Returns the most advanced boot phase matching a console line. Kernel messages never
+    /// match phases before the kernel and initrd output never matches phases after dracut
+    pub fn classify_line(&self, line: &str) -> Option<BootPhase> {
+        let is_kernel_line = self.kernel_line_regex.is_match(line);
+        let is_initrd_line = self.initrd_line_regex.is_match(line);
+
+        self.boot_phase_rule_vec
+            .iter()
+            .filter(|boot_phase_rule| {
+                (!is_kernel_line || boot_phase_rule.boot_phase >= BootPhase::Kernel)
+                    && (!is_initrd_line || boot_phase_rule.boot_phase <= BootPhase::Dracut)
+            })
+            .filter(|boot_phase_rule| boot_phase_rule.regex.is_match(line))
+            .map(|boot_phase_rule| boot_phase_rule.boot_phase)
+            .max()
+    }
+
+    /// Updates the boot progress of a node. Returns the new boot phase if the node moved to
+    /// another phase. Nodes only move forward, except when going back to POST (reboot). systemd
+    /// also runs in the initrd, so nodes in dracut only move to systemd after switching root
+    pub fn process_line(&mut self, console_line: &ConsoleLine) -> Option<BootPhase> {
+        let boot_phase_opt = self.classify_line(&console_line.line);
+
+        let node_boot_progress = self
+            .node_boot_progress_map
+            .entry(console_line.xname.clone())
+            .or_insert_with(|| NodeBootProgress::new(&console_line.xname));
+
+        node_boot_progress.last_line_opt = Some(console_line.line.clone());
+
+        let boot_phase = boot_phase_opt?;
+        let current_boot_phase_opt = node_boot_progress.get_boot_phase();
+
+        if current_boot_phase_opt == Some(BootPhase::Dracut)
+            && boot_phase == BootPhase::Systemd
+            && !self.switch_root_regex.is_match(&console_line.line)
+        {
+            return None;
+        }
+
+        if Some(boot_phase) > current_boot_phase_opt
+            || (boot_phase == BootPhase::Post && current_boot_phase_opt > Some(BootPhase::Post))
+        {
+            node_boot_progress.enter_boot_phase(boot_phase, console_line.timestamp);
+            Some(boot_phase)
+        } else {
+            None
+        }
+    }
+
+    /// Processes events coming from `node::console_log::ConsoleCapture`
+    pub fn process_console_event(&mut self, console_event: &ConsoleEvent) -> Option<BootPhase> {
+        match console_event {
+            ConsoleEvent::Line(console_line) => self.process_line(console_line),
+            _ => None,
+        }
+    }
+
+    /// Processes a console recorded in asciinema format (see
+    /// `node::console_log::AsciinemaRecorder`)
+    pub fn process_asciinema_file(
+        &mut self,
+        path: &Path,
+        xname: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let content = std::fs::read_to_string(path)?;
+        let mut line_iter = content.lines();
+
+        let header: Value = serde_json::from_str(line_iter.next().ok_or("Empty recording")?)?;
+        let start = Utc
+            .timestamp_opt(header["timestamp"].as_i64().unwrap_or_default(), 0)
+            .single()
+            .ok_or("Invalid recording timestamp")?;
+
+        for event_line in line_iter {
+            let event: Value = serde_json::from_str(event_line)?;
+
+            if event[1].as_str() != Some("o") {
+                continue;
+            }
+
+            let timestamp = start
+                + chrono::Duration::milliseconds(
+                    (event[0].as_f64().unwrap_or_default() * 1000.0) as i64,
+                );
+
+            for line in event[2].as_str().unwrap_or_default().lines() {
+                self.process_line(&ConsoleLine {
+                    xname: xname.to_string(),
+                    timestamp,
+                    line: line.trim_end_matches('\r').to_string(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get_node_boot_progress(&self, xname: &str) -> Option<&NodeBootProgress> {
+        self.node_boot_progress_map.get(xname)
+    }
+
+    /// Returns the boot progress of all nodes sorted by xname
+    pub fn get_node_boot_progress_vec(&self) -> Vec<&NodeBootProgress> {
+        let mut node_boot_progress_vec: Vec<&NodeBootProgress> =
+            self.node_boot_progress_map.values().collect();
+
+        node_boot_progress_vec.sort_by(|a, b| a.xname.cmp(&b.xname));
+
+        node_boot_progress_vec
+    }
+
+    /// Returns the nodes which have been longer than the timeout in their current boot phase
+    pub fn get_stalled_node_vec(&self, now: DateTime<Utc>) -> Vec<&NodeBootProgress> {
+        self.get_node_boot_progress_vec()
+            .into_iter()
+            .filter(|node_boot_progress| {
+                node_boot_progress.is_stalled(now, &self.boot_phase_timeout_map)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use chrono::{Duration, Utc};
+
+    use super::{BootPhase, BootPhaseClassifier};
+    use crate::node::console_log::ConsoleLine;
+
+    #[test]
+    fn test_boot_phase_classifier() {
+        let mut boot_phase_classifier = BootPhaseClassifier::default();
+        let start = Utc::now();
+
+        let console_line_vec = [
+            (0, "UEFI Interactive Shell v2.2"),
+            (30, "iPXE initialising devices...ok"),
+            (
+                60,
+                "[    0.000000] Linux version 5.14.21-150400.24.46_12.0.83-cray_shasta_c",
+            ),
+            (
+                62,
+                "[    3.012345] dracut-pre-udev[612]: modprobe: FATAL: Module nvme not found",
+            ),
+            // Kernel messages keep coming during dracut, node must not go back
+            (
+                63,
+                "[    3.512345] usb 1-1: new high-speed USB device number 2",
+            ),
+        ];
+
+        for (seconds, line) in console_line_vec {
+            boot_phase_classifier.process_line(&ConsoleLine {
+                xname: "x1000c0s0b0n0".to_string(),
+                timestamp: start + Duration::seconds(seconds),
+                line: line.to_string(),
+            });
+        }
+
+        let node_boot_progress = boot_phase_classifier
+            .get_node_boot_progress("x1000c0s0b0n0")
+            .unwrap();
+
+        assert_eq!(node_boot_progress.get_boot_phase(), Some(BootPhase::Dracut));
+        assert_eq!(node_boot_progress.boot_phase_duration_vec.len(), 4);
+        assert_eq!(
+            node_boot_progress.boot_phase_duration_vec[1].end_opt,
+            Some(start + Duration::seconds(60))
+        );
+
+        // Dracut timeout is 10 minutes
+        assert!(boot_phase_classifier
+            .get_stalled_node_vec(start + Duration::seconds(300))
+            .is_empty());
+        assert_eq!(
+            boot_phase_classifier
+                .get_stalled_node_vec(start + Duration::seconds(900))
+                .len(),
+            1
+        );
+
+        assert_eq!(
+            boot_phase_classifier.classify_line("nid001313 login: "),
+            Some(BootPhase::Login)
+        );
+    }
+
+    #[test]
+    fn test_boot_phase_classifier_kernel_messages() {
+        let mut boot_phase_classifier = BootPhaseClassifier::default();
+        let start = Utc::now();
+
+        let console_line_vec = [
+            "UEFI Interactive Shell v2.2",
+            "iPXE initialising devices...ok",
+            "[    0.000000] Linux version 5.14.21-150400.24.46_12.0.83-cray_shasta_c",
+            "[    0.000000] BIOS-provided physical RAM map:",
+            "[    0.000000] BIOS-e820: [mem 0x0000000000000000-0x000000000009ffff] usable",
+            "[    0.000000] efi: EFI v2.70 by EDK II",
+            "[    0.000000] DMI: HPE ProLiant XL225n Gen10 Plus, BIOS A46 04/29/2021",
+            "[    0.012345] Kernel command line: BOOT_IMAGE=kernel root=craycps-s3:s3://boot-images/1a2b/rootfs:7e3c:dvs",
+            "[    1.234567] Trying to unpack rootfs image as initramfs...",
+            "efi: EFI v2.70 by EDK II",
+        ];
+
+        for (seconds, line) in console_line_vec.into_iter().enumerate() {
+            boot_phase_classifier.process_line(&ConsoleLine {
+                xname: "x1000c0s0b0n0".to_string(),
+                timestamp: start + Duration::seconds(seconds as i64),
+                line: line.to_string(),
+            });
+        }
+
+        let node_boot_progress = boot_phase_classifier
+            .get_node_boot_progress("x1000c0s0b0n0")
+            .unwrap();
+
+        assert_eq!(node_boot_progress.get_boot_phase(), Some(BootPhase::Kernel));
+        assert_eq!(node_boot_progress.boot_phase_duration_vec.len(), 3);
+    }
+
+    #[test]
+    fn test_boot_phase_classifier_initrd() {
+        let mut boot_phase_classifier = BootPhaseClassifier::default();
+        let start = Utc::now();
+
+        boot_phase_classifier.watch_node_vec(
+            &["x1000c0s0b0n0".to_string(), "x1000c0s0b0n1".to_string()],
+            start,
+        );
+
+        let console_line_vec = [
+            "[    0.000000] Linux version 5.14.21-150400.24.46_12.0.83-cray_shasta_c",
+            "[    2.915311] systemd[1]: systemd 249.12+suse.150.g6fa2ce5d8a running in system mode (+PAM +AUDIT +SELINUX)",
+            "[    2.940141] systemd[1]: Running in initrd.",
+            "Welcome to SUSE Linux Enterprise High Performance Computing 15 SP4 dracut-055+suse.331.g05b9ccb7-150400.3.8.1 (Initramfs)!",
+            "[  OK  ] Started Journal Service.",
+            "[  OK  ] Reached target Basic System.",
+            "         Starting dracut initqueue hook...",
+            "[  OK  ] Started dracut pre-udev hook.",
+            "[  OK  ] Reached target Initrd Root Device.",
+        ];
+
+        for (seconds, line) in console_line_vec.into_iter().enumerate() {
+            boot_phase_classifier.process_line(&ConsoleLine {
+                xname: "x1000c0s0b0n0".to_string(),
+                timestamp: start + Duration::seconds(200 + seconds as i64),
+                line: line.to_string(),
+            });
+        }
+
+        assert_eq!(
+            boot_phase_classifier
+                .get_node_boot_progress("x1000c0s0b0n0")
+                .unwrap()
+                .get_boot_phase(),
+            Some(BootPhase::Dracut)
+        );
+
+        // Node without console output is stalled once the POST timeout passed
+        let stalled_xname_vec: Vec<&str> = boot_phase_classifier
+            .get_stalled_node_vec(start + Duration::seconds(700))
+            .into_iter()
+            .map(|node_boot_progress| node_boot_progress.xname.as_str())
+            .collect();
+        assert_eq!(stalled_xname_vec, vec!["x1000c0s0b0n1"]);
+
+        for line in [
+            "[  OK  ] Reached target Switch Root.",
+            "Welcome to SUSE Linux Enterprise High Performance Computing 15 SP4!",
+        ] {
+            boot_phase_classifier.process_line(&ConsoleLine {
+                xname: "x1000c0s0b0n0".to_string(),
+                timestamp: start + Duration::seconds(220),
+                line: line.to_string(),
+            });
+        }
+
+        assert_eq!(
+            boot_phase_classifier
+                .get_node_boot_progress("x1000c0s0b0n0")
+                .unwrap()
+                .get_boot_phase(),
+            Some(BootPhase::Systemd)
+        );
+    }
+}
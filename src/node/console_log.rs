@@ -0,0 +1,315 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::core::v1::Pod;
+use kube::{api::AttachParams, Api};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    sync::mpsc,
+    task::JoinSet,
+};
+
+use super::{console::get_console_pod_name, utils::validate_xname_format};
+
+/// Directory in the cray-console-node pods where conman stores the console logs
+pub const CONSOLE_LOG_DIR: &str = "/var/log/conman";
+
+/// Line printed in a node's console
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ConsoleLine {
+    pub xname: String,
+    pub timestamp: DateTime<Utc>,
+    pub line: String,
+}
+
+/// Pattern to look for in the console logs, eg ("kernel_panic", "Kernel panic") or
+/// ("login", "login:")
+#[derive(Debug, Clone)]
+pub struct ConsoleTrigger {
+    pub name: String,
+    pub regex: Regex,
+}
+
+impl ConsoleTrigger {
+    pub fn new(name: &str, pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            name: name.to_string(),
+            regex: Regex::new(pattern)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConsoleEvent {
+    Line(ConsoleLine),
+    /// A line matched a trigger
+    Trigger {
+        trigger_name: String,
+        console_line: ConsoleLine,
+    },
+    /// Console log of a node stopped. Error message if the log could not be read
+    Closed {
+        xname: String,
+        error_opt: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct ConsoleCaptureConfig {
+    /// Number of lines already in the console log to return before following it
+    pub backscroll_lines: usize,
+    pub console_trigger_vec: Vec<ConsoleTrigger>,
+    /// If provided, the console of each node is recorded as '<xname>.cast' in asciinema format
+    pub recording_dir_opt: Option<PathBuf>,
+}
+
+impl Default for ConsoleCaptureConfig {
+    fn default() -> Self {
+        Self {
+            backscroll_lines: 100,
+            console_trigger_vec: Vec::new(),
+            recording_dir_opt: None,
+        }
+    }
+}
+
+/// Console logs being followed. Dropping it stops following the console logs
+pub struct ConsoleCapture {
+    receiver: mpsc::Receiver<ConsoleEvent>,
+    task_set: JoinSet<()>,
+}
+
+impl ConsoleCapture {
+    /// Returns the next console event of any node, None once all console logs are closed
+    pub async fn next_event(&mut self) -> Option<ConsoleEvent> {
+        self.receiver.recv().await
+    }
+
+    pub fn stop(&mut self) {
+        self.task_set.abort_all();
+    }
+}
+
+/// Follows the console logs of a list of nodes concurrently. Console logs are read from the
+/// conman log files in the cray-console-node pods, so nothing is sent to the nodes
+pub async fn tail_console_log(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    client: kube::Client,
+    xname_vec: &[String],
+    console_capture_config: &ConsoleCaptureConfig,
+) -> Result<ConsoleCapture, Box<dyn Error>> {
+    // Accept NIDs and aliases too
+    let xname_vec = &crate::node::resolver::resolve_xname_vec(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+    )
+    .await?;
+
+    // xnames are used in commands run in the console pods and in recording file names
+    if let Some(xname) = xname_vec.iter().find(|xname| !validate_xname_format(xname)) {
+        return Err(format!("'{}' is not a valid xname", xname).into());
+    }
+
+    let pods_api: Api<Pod> = Api::namespaced(client, "services");
+
+    if let Some(recording_dir) = &console_capture_config.recording_dir_opt {
+        std::fs::create_dir_all(recording_dir)?;
+    }
+
+    let (sender, receiver) = mpsc::channel(1024);
+    let mut task_set = JoinSet::new();
+
+    for xname in xname_vec {
+        let console_pod_name = get_console_pod_name(&pods_api, xname).await?;
+
+        let recorder_opt = match &console_capture_config.recording_dir_opt {
+            Some(recording_dir) => Some(AsciinemaRecorder::new(
+                &recording_dir.join(format!("{}.cast", xname)),
+                xname,
+            )?),
+            None => None,
+        };
+
+        task_set.spawn(follow_console_log(
+            pods_api.clone(),
+            console_pod_name,
+            xname.clone(),
+            console_capture_config.backscroll_lines,
+            console_capture_config.console_trigger_vec.clone(),
+            recorder_opt,
+            sender.clone(),
+        ));
+    }
+
+    Ok(ConsoleCapture { receiver, task_set })
+}
+
+async fn follow_console_log(
+    pods_api: Api<Pod>,
+    console_pod_name: String,
+    xname: String,
+    backscroll_lines: usize,
+    console_trigger_vec: Vec<ConsoleTrigger>,
+    mut recorder_opt: Option<AsciinemaRecorder>,
+    sender: mpsc::Sender<ConsoleEvent>,
+) {
+    let backscroll_lines = backscroll_lines.to_string();
+    let console_log_path = format!("{}/console.{}", CONSOLE_LOG_DIR, xname);
+
+    let attached_rslt = pods_api
+        .exec(
+            &console_pod_name,
+            vec![
+                "tail",
+                "-n",
+                backscroll_lines.as_str(),
+                "-F",
+                console_log_path.as_str(),
+            ],
+            &AttachParams::default()
+                .container("cray-console-node")
+                .stdin(false)
+                .stdout(true)
+                .stderr(false),
+        )
+        .await;
+
+    let mut attached = match attached_rslt {
+        Ok(attached) => attached,
+        Err(error) => {
+            let _ = sender
+                .send(ConsoleEvent::Closed {
+                    xname,
+                    error_opt: Some(error.to_string()),
+                })
+                .await;
+            return;
+        }
+    };
+
+    let mut error_opt = None;
+
+    if let Some(stdout) = attached.stdout() {
+        let mut line_stream = BufReader::new(stdout).lines();
+
+        loop {
+            let line = match line_stream.next_line().await {
+                Ok(Some(line)) => line.trim_end_matches('\r').to_string(),
+                Ok(None) => break,
+                Err(error) => {
+                    error_opt = Some(error.to_string());
+                    break;
+                }
+            };
+
+            if let Some(recorder) = recorder_opt.as_mut() {
+                if let Err(error) = recorder.write_output(&format!("{}\r\n", line)) {
+                    log::warn!("Could not record console of node '{}': {}", xname, error);
+                    recorder_opt = None;
+                }
+            }
+
+            let console_line = ConsoleLine {
+                xname: xname.clone(),
+                timestamp: Utc::now(),
+                line,
+            };
+
+            for console_trigger in &console_trigger_vec {
+                if console_trigger.regex.is_match(&console_line.line) {
+                    let _ = sender
+                        .send(ConsoleEvent::Trigger {
+                            trigger_name: console_trigger.name.clone(),
+                            console_line: console_line.clone(),
+                        })
+                        .await;
+                }
+            }
+
+            // Receiver dropped, nobody is interested in this console anymore
+            if sender.send(ConsoleEvent::Line(console_line)).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    let _ = sender.send(ConsoleEvent::Closed { xname, error_opt }).await;
+}
+
+/// Writes a terminal session in asciinema v2 format
+/// ref --> https://docs.asciinema.org/manual/asciicast/v2/
+pub struct AsciinemaRecorder {
+    file: File,
+    start: Instant,
+}
+
+impl AsciinemaRecorder {
+    pub fn new(path: &Path, title: &str) -> Result<Self, Box<dyn Error>> {
+        let mut file = File::create(path)?;
+
+        let header = serde_json::json!({
+            "version": 2,
+            "width": 80,
+            "height": 24,
+            "timestamp": Utc::now().timestamp(),
+            "title": title,
+        });
+
+        writeln!(file, "{}", header)?;
+
+        Ok(Self {
+            file,
+            start: Instant::now(),
+        })
+    }
+
+    /// Records text printed to the terminal
+    pub fn write_output(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        let event = serde_json::json!([self.start.elapsed().as_secs_f64(), "o", text]);
+
+        writeln!(self.file, "{}", event)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use serde_json::Value;
+
+    use super::AsciinemaRecorder;
+
+    #[test]
+    fn test_asciinema_recorder() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("x1000c0s0b0n0.cast");
+
+        let mut recorder = AsciinemaRecorder::new(&path, "x1000c0s0b0n0").unwrap();
+        recorder.write_output("login: \r\n").unwrap();
+        drop(recorder);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let line_vec: Vec<Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(line_vec.len(), 2);
+        assert_eq!(line_vec[0]["version"], 2);
+        assert_eq!(line_vec[0]["title"], "x1000c0s0b0n0");
+        assert_eq!(line_vec[1][1], "o");
+        assert_eq!(line_vec[1][2], "login: \r\n");
+    }
+}
@@ -0,0 +1,182 @@
+use std::{error::Error, path::Path};
+
+use async_trait::async_trait;
+use kube::api::AttachedProcess;
+use serde_json::Value;
+
+use crate::common::{
+    boot_image_ops::{BOOT_ARTIFACT_NAME_VEC, BOOT_IMAGE_BUCKET},
+    power_ops::{self, PowerAction, PowerConfig, PowerReport},
+};
+
+use super::{
+    r#struct::{CsmClient, NodeStatus},
+    traits::Node,
+    utils,
+};
+
+/// Node managed through CSM
+#[derive(Debug, Clone)]
+pub struct CsmNode {
+    pub csm_client: CsmClient,
+    pub xname: String,
+}
+
+impl CsmNode {
+    pub fn new(csm_client: CsmClient, xname: &str) -> Self {
+        Self {
+            csm_client,
+            xname: xname.to_string(),
+        }
+    }
+
+    async fn power(&self, action: PowerAction, force: bool) -> Result<PowerReport, Box<dyn Error>> {
+        power_ops::power(
+            &self.csm_client.shasta_token,
+            &self.csm_client.shasta_base_url,
+            &self.csm_client.shasta_root_cert,
+            action,
+            std::slice::from_ref(&self.xname),
+            &PowerConfig {
+                force,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+}
+
+#[async_trait(?Send)]
+impl Node for CsmNode {
+    fn get_xname(&self) -> &str {
+        &self.xname
+    }
+
+    async fn power_off(&self, force: bool) -> Result<PowerReport, Box<dyn Error>> {
+        self.power(PowerAction::Off, force).await
+    }
+
+    async fn power_on(&self) -> Result<PowerReport, Box<dyn Error>> {
+        self.power(PowerAction::On, false).await
+    }
+
+    async fn reset(&self, force: bool) -> Result<PowerReport, Box<dyn Error>> {
+        self.power(PowerAction::Reset, force).await
+    }
+
+    async fn get_power_status(&self) -> Result<String, Box<dyn Error>> {
+        utils::get_power_state_vec(
+            &self.csm_client.shasta_token,
+            &self.csm_client.shasta_base_url,
+            &self.csm_client.shasta_root_cert,
+            std::slice::from_ref(&self.xname),
+        )
+        .await?
+        .pop()
+        .map(|(_, power_state)| power_state)
+        .ok_or_else(|| format!("No power status found for node '{}'", self.xname).into())
+    }
+
+    async fn connect_to_console(&self) -> Result<AttachedProcess, Box<dyn Error>> {
+        let k8s_client_source = self
+            .csm_client
+            .k8s_client_source_opt
+            .as_ref()
+            .ok_or("K8s client source missing, can't connect to node's console")?;
+
+        super::console::get_container_attachment_to_conman(&self.xname, k8s_client_source).await
+    }
+
+    async fn get_boot_config(&self) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(utils::get_boot_config_vec(
+            &self.csm_client.shasta_token,
+            &self.csm_client.shasta_base_url,
+            &self.csm_client.shasta_root_cert,
+            std::slice::from_ref(&self.xname),
+        )
+        .await?
+        .pop()
+        .and_then(|(_, boot_config_opt)| boot_config_opt))
+    }
+
+    async fn get_desired_config(&self) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(utils::get_desired_config_vec(
+            &self.csm_client.shasta_token,
+            &self.csm_client.shasta_base_url,
+            &self.csm_client.shasta_root_cert,
+            std::slice::from_ref(&self.xname),
+        )
+        .await?
+        .pop()
+        .and_then(|(_, desired_config_opt)| desired_config_opt))
+    }
+
+    async fn get_status(&self) -> Result<NodeStatus, Box<dyn Error>> {
+        utils::get_status_vec(
+            &self.csm_client.shasta_token,
+            &self.csm_client.shasta_base_url,
+            &self.csm_client.shasta_root_cert,
+            std::slice::from_ref(&self.xname),
+        )
+        .await?
+        .pop()
+        .map(|(_, node_status)| node_status)
+        .ok_or_else(|| format!("No status found for node '{}'", self.xname).into())
+    }
+
+    async fn get_details(&self) -> Result<Value, Box<dyn Error>> {
+        let node_details = utils::get_node_details(
+            &self.csm_client.shasta_token,
+            &self.csm_client.shasta_base_url,
+            &self.csm_client.shasta_root_cert,
+            vec![self.xname.clone()],
+        )
+        .await
+        .pop()
+        .ok_or_else(|| format!("No details found for node '{}'", self.xname))?;
+
+        Ok(serde_json::to_value(node_details)?)
+    }
+
+    async fn download_boot_image(
+        &self,
+        destination_path: &Path,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let image_id = crate::bss::http_client::get_boot_parameters(
+            &self.csm_client.shasta_token,
+            &self.csm_client.shasta_base_url,
+            &self.csm_client.shasta_root_cert,
+            std::slice::from_ref(&self.xname),
+        )
+        .await?
+        .first()
+        .and_then(|boot_parameters| boot_parameters.get_boot_image_id())
+        .ok_or_else(|| format!("No boot image found for node '{}'", self.xname))?;
+
+        let sts_value = crate::ims::s3::s3_auth(
+            &self.csm_client.shasta_token,
+            &self.csm_client.shasta_base_url,
+            &self.csm_client.shasta_root_cert,
+        )
+        .await?;
+
+        let destination_path = destination_path.join(&image_id);
+        std::fs::create_dir_all(&destination_path)?;
+
+        let mut file_path_vec = Vec::new();
+
+        for boot_artifact_name in BOOT_ARTIFACT_NAME_VEC {
+            file_path_vec.push(
+                crate::ims::s3::s3_download_object(
+                    &sts_value,
+                    &format!("{}/{}", image_id, boot_artifact_name),
+                    BOOT_IMAGE_BUCKET,
+                    &destination_path.to_string_lossy(),
+                )
+                .await?,
+            );
+        }
+
+        Ok(file_path_vec)
+    }
+}
@@ -0,0 +1,388 @@
+use std::{collections::HashMap, error::Error, path::Path};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{hsm, sls};
+
+use super::utils::validate_xname_format;
+
+/// Env var with the path of the cache file used by `NodeResolver::from_env_cache`
+pub const CACHE_FILE_ENV_VAR_NAME: &str = "MESA_NODE_RESOLVER_CACHE_FILE";
+/// Max age of the cache file used by `NodeResolver::from_env_cache`
+pub const CACHE_MAX_AGE_SECS: i64 = 3600;
+
+/// Maps the different ways users refer to a node (xname, NID, NID range or SLS alias) to
+/// canonical xnames and back.
+/// The information is taken from HSM components (NID) and SLS hardware (aliases) and can be
+/// stored in a local file to avoid fetching all HSM components every time.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct NodeResolver {
+    /// Creation time of the data (rfc3339), used to decide if a cache file is stale
+    pub created: String,
+    pub xname_nid_map: HashMap<String, u64>,
+    pub alias_xname_map: HashMap<String, String>,
+}
+
+impl NodeResolver {
+    /// Creates a resolver from HSM components (value returned by
+    /// hsm::component_status::shasta::http_client::get under "Components") and SLS hardware
+    /// entries
+    pub fn new(hsm_component_value_vec: &[Value], sls_hardware_value_vec: &[Value]) -> Self {
+        let xname_nid_map = hsm_component_value_vec
+            .iter()
+            .filter(|component| component["Type"].as_str().is_some_and(|r#type| r#type == "Node"))
+            .filter_map(|component| {
+                component["ID"]
+                    .as_str()
+                    .zip(component["NID"].as_u64())
+                    .map(|(xname, nid)| (xname.to_string(), nid))
+            })
+            .collect();
+
+        let alias_xname_map = sls::utils::get_xname_alias_tuple_vec(sls_hardware_value_vec)
+            .into_iter()
+            .map(|(xname, alias)| (alias, xname))
+            .collect();
+
+        Self {
+            created: chrono::Utc::now().to_rfc3339(),
+            xname_nid_map,
+            alias_xname_map,
+        }
+    }
+
+    /// Fetches HSM components and SLS hardware and creates a resolver
+    pub async fn from_csm(
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+    ) -> Result<Self, Box<dyn Error>> {
+        // Get all HSM components (an empty list of xnames returns all of them)
+        let hsm_component_value = hsm::component_status::shasta::http_client::get(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &[],
+        )
+        .await?;
+
+        let sls_hardware_value_vec = sls::http_client::get_hardware_node_vec(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+        )
+        .await
+        .unwrap_or_else(|error| {
+            log::warn!("Could not fetch SLS hardware, node aliases won't be resolved: {}", error);
+            Vec::new()
+        });
+
+        Ok(Self::new(
+            hsm_component_value["Components"]
+                .as_array()
+                .unwrap_or(&Vec::new()),
+            &sls_hardware_value_vec,
+        ))
+    }
+
+    /// Same as `from_csm` but reads the data from a local cache file if it is younger than
+    /// `max_age_secs`. Otherwise data is fetched from CSM and the cache file is refreshed
+    pub async fn from_csm_with_cache(
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+        cache_file_path: &Path,
+        max_age_secs: i64,
+    ) -> Result<Self, Box<dyn Error>> {
+        if let Ok(node_resolver) = Self::from_cache_file(cache_file_path) {
+            let is_fresh = chrono::DateTime::parse_from_rfc3339(&node_resolver.created)
+                .is_ok_and(|created| {
+                    chrono::Utc::now().signed_duration_since(created).num_seconds() < max_age_secs
+                });
+
+            if is_fresh {
+                log::debug!(
+                    "Using node resolver cache file '{}'",
+                    cache_file_path.display()
+                );
+                return Ok(node_resolver);
+            }
+        }
+
+        let node_resolver = Self::from_csm(shasta_token, shasta_base_url, shasta_root_cert).await?;
+
+        if let Err(error) = node_resolver.write_cache_file(cache_file_path) {
+            log::warn!(
+                "Could not write node resolver cache file '{}': {}",
+                cache_file_path.display(),
+                error
+            );
+        }
+
+        Ok(node_resolver)
+    }
+
+    /// Same as `from_csm_with_cache` using the cache file configured through env var
+    /// MESA_NODE_RESOLVER_CACHE_FILE. Same as `from_csm` if the env var is not set
+    pub async fn from_env_cache(
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+    ) -> Result<Self, Box<dyn Error>> {
+        match std::env::var(CACHE_FILE_ENV_VAR_NAME)
+            .ok()
+            .filter(|path| !path.is_empty())
+        {
+            Some(cache_file_path) => {
+                Self::from_csm_with_cache(
+                    shasta_token,
+                    shasta_base_url,
+                    shasta_root_cert,
+                    Path::new(&cache_file_path),
+                    CACHE_MAX_AGE_SECS,
+                )
+                .await
+            }
+            None => Self::from_csm(shasta_token, shasta_base_url, shasta_root_cert).await,
+        }
+    }
+
+    pub fn from_cache_file(cache_file_path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file_content = std::fs::read_to_string(cache_file_path)?;
+
+        Ok(serde_json::from_str(&file_content)?)
+    }
+
+    pub fn write_cache_file(&self, cache_file_path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = cache_file_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(cache_file_path, serde_json::to_string(self)?)?;
+
+        Ok(())
+    }
+
+    /// Translates a hosts expression into a list of xnames. The hosts expression is a comma
+    /// separated list where each element can be:
+    ///  - an xname, eg x1003c1s7b0n0
+    ///  - a NID, eg nid001234
+    ///  - a NID range, eg nid[001000-001063] or nid[001000-001003,001010]
+    ///  - an alias registered in SLS, eg uan01
+    ///
+    /// The list returned keeps the order of the expression and has no duplicates
+    pub fn resolve(&self, hosts_expression: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut xname_vec: Vec<String> = Vec::new();
+
+        for host in split_hosts_expression(hosts_expression) {
+            let host_xname_vec: Vec<String> = if validate_xname_format(&host) {
+                vec![host]
+            } else if let Some(xname) = self.alias_xname_map.get(&host) {
+                vec![xname.clone()]
+            } else if host.starts_with("nid") {
+                expand_nid_expression(&host)?
+                    .iter()
+                    .map(|nid| {
+                        self.get_xname_from_nid(*nid)
+                            .ok_or_else(|| format!("NID {} not found in HSM", nid))
+                    })
+                    .collect::<Result<Vec<String>, String>>()?
+            } else {
+                return Err(format!("Could not resolve '{}' to an xname", host).into());
+            };
+
+            for xname in host_xname_vec {
+                if !xname_vec.contains(&xname) {
+                    xname_vec.push(xname);
+                }
+            }
+        }
+
+        Ok(xname_vec)
+    }
+
+    pub fn get_xname_from_nid(&self, nid: u64) -> Option<String> {
+        self.xname_nid_map
+            .iter()
+            .find(|(_, node_nid)| **node_nid == nid)
+            .map(|(xname, _)| xname.clone())
+    }
+
+    /// Returns the NID of a node in the same format used by get_node_details, eg 'nid001234'
+    pub fn get_nid(&self, xname: &str) -> Option<String> {
+        self.xname_nid_map
+            .get(xname)
+            .map(|nid| format!("nid{:0>6}", nid))
+    }
+
+    /// Returns the list of aliases of a node registered in SLS
+    pub fn get_alias_vec(&self, xname: &str) -> Vec<String> {
+        let mut alias_vec: Vec<String> = self
+            .alias_xname_map
+            .iter()
+            .filter(|(_, node_xname)| node_xname.as_str() == xname)
+            .map(|(alias, _)| alias.clone())
+            .collect();
+
+        alias_vec.sort();
+
+        alias_vec
+    }
+}
+
+/// Convenience function to translate a hosts expression (see NodeResolver::resolve) into a list
+/// of xnames fetching the node information from CSM
+pub async fn get_xname_vec_from_hosts_expression(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    hosts_expression: &str,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    NodeResolver::from_env_cache(shasta_token, shasta_base_url, shasta_root_cert)
+        .await?
+        .resolve(hosts_expression)
+}
+
+/// Translates a list of hosts (xnames, NIDs, NID ranges or aliases) into xnames. Node
+/// information is only fetched from CSM if some host is not an xname already. Used by the public
+/// functions taking a list of xnames so they accept the other forms as well
+pub async fn resolve_xname_vec(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    host_vec: &[String],
+) -> Result<Vec<String>, Box<dyn Error>> {
+    if host_vec.iter().all(|host| validate_xname_format(host)) {
+        return Ok(host_vec.to_vec());
+    }
+
+    NodeResolver::from_env_cache(shasta_token, shasta_base_url, shasta_root_cert)
+        .await?
+        .resolve(&host_vec.join(","))
+}
+
+/// Splits a hosts expression by ',' ignoring the commas inside NID ranges
+/// eg "x1003c1s7b0n0,nid[000001,000003-000004]" --> ["x1003c1s7b0n0", "nid[000001,000003-000004]"]
+pub fn split_hosts_expression(hosts_expression: &str) -> Vec<String> {
+    let mut host_vec = Vec::new();
+    let mut host = String::new();
+    let mut depth = 0;
+
+    for c in hosts_expression.chars() {
+        match c {
+            '[' => {
+                depth += 1;
+                host.push(c);
+            }
+            ']' => {
+                depth -= 1;
+                host.push(c);
+            }
+            ',' if depth == 0 => {
+                host_vec.push(host.trim().to_string());
+                host = String::new();
+            }
+            _ => host.push(c),
+        }
+    }
+
+    host_vec.push(host.trim().to_string());
+
+    host_vec.retain(|host| !host.is_empty());
+
+    host_vec
+}
+
+/// Translates a NID expression into a list of NIDs
+/// eg "nid001234" --> [1234]
+/// eg "nid[001000-001002,001010]" --> [1000, 1001, 1002, 1010]
+pub fn expand_nid_expression(nid_expression: &str) -> Result<Vec<u64>, Box<dyn Error>> {
+    let nid_body = nid_expression
+        .strip_prefix("nid")
+        .ok_or_else(|| format!("'{}' is not a NID", nid_expression))?;
+
+    let range_list = if let Some(range_list) = nid_body
+        .strip_prefix('[')
+        .and_then(|nid_body| nid_body.strip_suffix(']'))
+    {
+        range_list
+    } else {
+        nid_body
+    };
+
+    let mut nid_vec = Vec::new();
+
+    for range in range_list.split(',').map(|range| range.trim()) {
+        if let Some((start, end)) = range.split_once('-') {
+            let start: u64 = start
+                .parse()
+                .map_err(|_| format!("Invalid NID range '{}'", range))?;
+            let end: u64 = end
+                .parse()
+                .map_err(|_| format!("Invalid NID range '{}'", range))?;
+
+            if start > end {
+                return Err(format!("Invalid NID range '{}'", range).into());
+            }
+
+            nid_vec.extend(start..=end);
+        } else {
+            nid_vec.push(
+                range
+                    .parse()
+                    .map_err(|_| format!("Invalid NID '{}'", range))?,
+            );
+        }
+    }
+
+    Ok(nid_vec)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{expand_nid_expression, split_hosts_expression, NodeResolver};
+
+    #[test]
+    fn test_expand_nid_expression() {
+        assert_eq!(expand_nid_expression("nid001234").unwrap(), vec![1234]);
+        assert_eq!(
+            expand_nid_expression("nid[001000-001002,001010]").unwrap(),
+            vec![1000, 1001, 1002, 1010]
+        );
+        assert!(expand_nid_expression("nid[001002-001000]").is_err());
+        assert!(expand_nid_expression("uan01").is_err());
+    }
+
+    #[test]
+    fn test_resolve_hosts_expression() {
+        let hsm_component_value_vec = vec![
+            serde_json::json!({"ID": "x1003c1s7b0n0", "Type": "Node", "NID": 1000}),
+            serde_json::json!({"ID": "x1003c1s7b0n1", "Type": "Node", "NID": 1001}),
+            serde_json::json!({"ID": "x1003c1s7b1n0", "Type": "Node", "NID": 1002}),
+        ];
+        let sls_hardware_value_vec = vec![serde_json::json!({
+            "Xname": "x1003c1s7b1n0",
+            "ExtraProperties": { "Aliases": ["uan01"], "NID": 1002 }
+        })];
+
+        let node_resolver = NodeResolver::new(&hsm_component_value_vec, &sls_hardware_value_vec);
+
+        assert_eq!(
+            split_hosts_expression("nid[001000-001001], uan01"),
+            vec!["nid[001000-001001]", "uan01"]
+        );
+        assert_eq!(
+            node_resolver
+                .resolve("nid[001000-001001],uan01,x1003c1s7b0n0")
+                .unwrap(),
+            vec!["x1003c1s7b0n0", "x1003c1s7b0n1", "x1003c1s7b1n0"]
+        );
+        assert_eq!(
+            node_resolver.get_nid("x1003c1s7b0n1"),
+            Some("nid001001".to_string())
+        );
+        assert_eq!(node_resolver.get_alias_vec("x1003c1s7b1n0"), vec!["uan01"]);
+        assert!(node_resolver.resolve("nid009999").is_err());
+    }
+}
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::common::kubernetes::K8sClientSource;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NodeDetails {
     pub xname: String,
@@ -12,3 +14,127 @@ pub struct NodeDetails {
     pub boot_image_id: String,
     pub boot_configuration: String,
 }
+
+/// Credentials and endpoints needed to operate CSM
+#[derive(Debug, Clone)]
+pub struct CsmClient {
+    pub shasta_token: String,
+    pub shasta_base_url: String,
+    pub shasta_root_cert: Vec<u8>,
+    /// Needed to access the nodes console through k8s
+    pub k8s_client_source_opt: Option<K8sClientSource>,
+}
+
+impl CsmClient {
+    pub fn new(shasta_token: &str, shasta_base_url: &str, shasta_root_cert: &[u8]) -> Self {
+        Self {
+            shasta_token: shasta_token.to_string(),
+            shasta_base_url: shasta_base_url.to_string(),
+            shasta_root_cert: shasta_root_cert.to_vec(),
+            k8s_client_source_opt: None,
+        }
+    }
+}
+
+/// Overall status of a node or a cluster
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum NodeStatus {
+    Off,
+    /// Powered on but not 'Ready' in HSM yet
+    Booting,
+    /// 'Ready' in HSM and CFS is configuring it
+    Configuring,
+    /// 'Ready' in HSM and CFS finished (or has nothing to do)
+    Standby,
+}
+
+impl NodeStatus {
+    /// Status of a node given its power state, HSM state and CFS configuration status
+    pub fn new(
+        power_state_opt: Option<&str>,
+        hsm_state_opt: Option<&str>,
+        configuration_status_opt: Option<&str>,
+    ) -> Self {
+        match (power_state_opt, hsm_state_opt, configuration_status_opt) {
+            (Some("off"), _, _) | (_, Some("Off"), _) => NodeStatus::Off,
+            (_, Some("Ready"), Some("pending")) => NodeStatus::Configuring,
+            (_, Some("Ready"), _) => NodeStatus::Standby,
+            _ => NodeStatus::Booting,
+        }
+    }
+
+    /// Status of a group of nodes. A cluster is OFF only if all its nodes are OFF, otherwise the
+    /// least advanced status of the nodes running is returned
+    pub fn aggregate(node_status_vec: &[NodeStatus]) -> Self {
+        let running_node_status_vec: Vec<&NodeStatus> = node_status_vec
+            .iter()
+            .filter(|node_status| **node_status != NodeStatus::Off)
+            .collect();
+
+        if running_node_status_vec.is_empty() {
+            NodeStatus::Off
+        } else if running_node_status_vec.contains(&&NodeStatus::Booting) {
+            NodeStatus::Booting
+        } else if running_node_status_vec.contains(&&NodeStatus::Configuring) {
+            NodeStatus::Configuring
+        } else {
+            NodeStatus::Standby
+        }
+    }
+}
+
+impl std::fmt::Display for NodeStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            NodeStatus::Off => "OFF",
+            NodeStatus::Booting => "BOOTING",
+            NodeStatus::Configuring => "CONFIGURING",
+            NodeStatus::Standby => "STANDBY",
+        };
+
+        write!(f, "{}", status)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::NodeStatus;
+
+    #[test]
+    fn test_node_status() {
+        assert_eq!(
+            NodeStatus::new(Some("off"), Some("Off"), Some("configured")),
+            NodeStatus::Off
+        );
+        assert_eq!(
+            NodeStatus::new(Some("on"), Some("On"), Some("configured")),
+            NodeStatus::Booting
+        );
+        assert_eq!(
+            NodeStatus::new(Some("on"), Some("Ready"), Some("pending")),
+            NodeStatus::Configuring
+        );
+        assert_eq!(
+            NodeStatus::new(Some("on"), Some("Ready"), Some("configured")),
+            NodeStatus::Standby
+        );
+
+        assert_eq!(
+            NodeStatus::aggregate(&[NodeStatus::Off, NodeStatus::Off]),
+            NodeStatus::Off
+        );
+        assert_eq!(
+            NodeStatus::aggregate(&[
+                NodeStatus::Off,
+                NodeStatus::Standby,
+                NodeStatus::Configuring
+            ]),
+            NodeStatus::Configuring
+        );
+        assert_eq!(
+            NodeStatus::aggregate(&[NodeStatus::Standby, NodeStatus::Booting]),
+            NodeStatus::Booting
+        );
+    }
+}
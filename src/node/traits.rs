@@ -1,52 +1,65 @@
-use std::error::Error;
+use std::{error::Error, path::Path};
 
+use async_trait::async_trait;
+use kube::api::AttachedProcess;
 use serde_json::Value;
 
+use crate::common::power_ops::PowerReport;
+
+use super::r#struct::NodeStatus;
+
+#[async_trait(?Send)]
 pub trait Node {
+    fn get_xname(&self) -> &str;
     /// Shuts down a node
-    fn power_off() -> Result<(), Box<dyn Error>>;
+    async fn power_off(&self, force: bool) -> Result<PowerReport, Box<dyn Error>>;
     // Start a node
-    fn power_on() -> Result<(), Box<dyn Error>>;
+    async fn power_on(&self) -> Result<PowerReport, Box<dyn Error>>;
     /// Restart a node
-    fn reset() -> Result<(), Box<dyn Error>>;
+    async fn reset(&self, force: bool) -> Result<PowerReport, Box<dyn Error>>;
     /// Get node's power status
-    fn get_power_status() -> Result<String, Box<dyn Error>>;
+    async fn get_power_status(&self) -> Result<String, Box<dyn Error>>;
     /// Connect to node's console
-    fn connect_to_console() -> Result<(), Box<dyn Error>>;
+    async fn connect_to_console(&self) -> Result<AttachedProcess, Box<dyn Error>>;
     /// Get CFS configuration name related to the image used to boot the node
-    fn get_boot_config() -> Option<String>;
+    async fn get_boot_config(&self) -> Result<Option<String>, Box<dyn Error>>;
     /// Get CFS configuration assigned to configure the node
-    fn get_desired_config() -> Option<String>;
+    async fn get_desired_config(&self) -> Result<Option<String>, Box<dyn Error>>;
     /// Get node status (OFF, BOOTING, CONFIGURING, STANDBY)
-    fn get_status() -> Result<String, Box<dyn Error>>;
+    async fn get_status(&self) -> Result<NodeStatus, Box<dyn Error>>;
     /// Get node's details like:
     /// CFS configuration used to create boot image
     /// CFS configuration to configure the node
     /// Power status
     /// If node is configured
     /// Current CFS session and current layer running (if any)
-    fn get_details() -> Result<Value, Box<dyn Error>>;
-    /// Download boot image
-    fn download_boot_image(); // TODO
+    async fn get_details(&self) -> Result<Value, Box<dyn Error>>;
+    /// Download boot image (kernel, initrd and rootfs). Returns the path of the files downloaded
+    async fn download_boot_image(
+        &self,
+        destination_path: &Path,
+    ) -> Result<Vec<String>, Box<dyn Error>>;
 }
 
+#[async_trait(?Send)]
 pub trait Cluster {
+    fn get_hsm_group_name(&self) -> &str;
     /// Shuts down all nodes of a cluster
-    fn power_off() -> Result<(), Box<dyn Error>>;
+    async fn power_off(&self, force: bool) -> Result<PowerReport, Box<dyn Error>>;
     /// Start all nodes of a cluster
-    fn power_on() -> Result<(), Box<dyn Error>>;
+    async fn power_on(&self) -> Result<PowerReport, Box<dyn Error>>;
     /// Restarts all nodes of a cluster
-    fn reset() -> Result<(), Box<dyn Error>>;
+    async fn reset(&self, force: bool) -> Result<PowerReport, Box<dyn Error>>;
     /// Get power state for all nodes in a cluster
-    fn get_power_state() -> Result<(), Box<dyn Error>>;
+    async fn get_power_state(&self) -> Result<Vec<(String, String)>, Box<dyn Error>>;
     /// Get all CFS configuration related to each node of a cluster
-    fn get_boot_config() -> Option<Vec<(String, String)>>;
+    async fn get_boot_config(&self) -> Result<Vec<(String, Option<String>)>, Box<dyn Error>>;
     /// Get CFS configurations related to each node of a cluster
-    fn get_desired_config() -> Option<Vec<(String, String)>>;
+    async fn get_desired_config(&self) -> Result<Vec<(String, Option<String>)>, Box<dyn Error>>;
     /// Get overall cluster status (OFF, BOOTING, CONFIGURING, STANDBY)
-    fn get_status() -> Result<String, Box<dyn Error>>;
+    async fn get_status(&self) -> Result<NodeStatus, Box<dyn Error>>;
     /// Get cluster details
-    fn get_details() -> Result<Value, Box<dyn Error>>;
-    /// Migrate cluster
-    fn migrate() -> Result<(), Box<dyn Error>>;
+    async fn get_details(&self) -> Result<Value, Box<dyn Error>>;
+    /// Migrate cluster to nodes from another HSM group
+    async fn migrate(&self, pool_hsm_group_name: &str) -> Result<(), Box<dyn Error>>;
 }
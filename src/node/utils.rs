@@ -1,9 +1,11 @@
+use std::error::Error;
+
 use regex::Regex;
 use serde_json::Value;
 
-use crate::{bss, cfs, hsm};
+use crate::{bss, capmc, cfs, common::power_ops, hsm};
 
-use super::r#struct::NodeDetails;
+use super::r#struct::{NodeDetails, NodeStatus};
 
 pub fn validate_xname_format(xname: &str) -> bool {
     let xname_re = Regex::new(r"^x\d{4}c[0-7]s([0-9]|[1-5][0-9]|6[0-4])b[0-1]n[0-7]$").unwrap();
@@ -260,3 +262,196 @@ pub fn string_vec_to_multi_line_string(nodes: Option<&Vec<String>>, num_columns:
 
     members
 }
+
+/// Returns a list of tuples like (xname, power state) eg ("x1000c0s0b0n0", "on")
+pub async fn get_power_state_vec(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    // Accept NIDs and aliases too
+    let xname_vec = &super::resolver::resolve_xname_vec(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+    )
+    .await?;
+
+    let power_state_map = power_ops::get_power_state_map(
+        &capmc::http_client::node_power_status::post(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &xname_vec.to_vec(),
+        )
+        .await?,
+    );
+
+    Ok(xname_vec
+        .iter()
+        .map(|xname| {
+            (
+                xname.clone(),
+                power_state_map.get(xname).cloned().unwrap_or_default(),
+            )
+        })
+        .collect())
+}
+
+/// Returns a list of tuples like (xname, status) with the status (OFF, BOOTING, CONFIGURING,
+/// STANDBY) of each node
+pub async fn get_status_vec(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+) -> Result<Vec<(String, NodeStatus)>, Box<dyn Error>> {
+    // Accept NIDs and aliases too
+    let xname_vec = &super::resolver::resolve_xname_vec(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+    )
+    .await?;
+
+    let power_state_map = power_ops::get_power_state_map(
+        &capmc::http_client::node_power_status::post(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            &xname_vec.to_vec(),
+        )
+        .await?,
+    );
+
+    let hsm_state_map = hsm::component_status::shasta::utils::get_hsm_state_map(
+        &hsm::component_status::shasta::http_client::get(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            xname_vec,
+        )
+        .await?,
+    );
+
+    let configuration_status_map = cfs::component::mesa::utils::get_cfs_configuration_status_map(
+        &cfs::component::mesa::http_client::get(
+            shasta_token,
+            shasta_base_url,
+            shasta_root_cert,
+            xname_vec,
+        )
+        .await?,
+    );
+
+    Ok(xname_vec
+        .iter()
+        .map(|xname| {
+            (
+                xname.clone(),
+                NodeStatus::new(
+                    power_state_map.get(xname).map(String::as_str),
+                    hsm_state_map.get(xname).map(String::as_str),
+                    configuration_status_map.get(xname).map(String::as_str),
+                ),
+            )
+        })
+        .collect())
+}
+
+/// Returns a list of tuples like (xname, CFS configuration name) with the CFS configuration used
+/// to build the image each node boots
+pub async fn get_boot_config_vec(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+) -> Result<Vec<(String, Option<String>)>, Box<dyn Error>> {
+    // Accept NIDs and aliases too
+    let xname_vec = &super::resolver::resolve_xname_vec(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+    )
+    .await?;
+
+    let boot_parameters_vec = bss::http_client::get_boot_parameters(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+    )
+    .await?;
+
+    let cfs_session_vec = cfs::session::mesa::http_client::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        None,
+        Some(true),
+    )
+    .await?;
+
+    Ok(xname_vec
+        .iter()
+        .map(|xname| {
+            let cfs_configuration_name_opt = boot_parameters_vec
+                .iter()
+                .find(|boot_parameters| boot_parameters.hosts.contains(xname))
+                .and_then(|boot_parameters| boot_parameters.get_boot_image_id())
+                .and_then(|image_id| {
+                    cfs::session::mesa::utils::find_cfs_session_related_to_image_id(
+                        &cfs_session_vec,
+                        &image_id,
+                    )
+                })
+                .and_then(|cfs_session| cfs_session.get_configuration_name());
+
+            (xname.clone(), cfs_configuration_name_opt)
+        })
+        .collect())
+}
+
+/// Returns a list of tuples like (xname, CFS configuration name) with the CFS configuration
+/// assigned to configure each node
+pub async fn get_desired_config_vec(
+    shasta_token: &str,
+    shasta_base_url: &str,
+    shasta_root_cert: &[u8],
+    xname_vec: &[String],
+) -> Result<Vec<(String, Option<String>)>, Box<dyn Error>> {
+    // Accept NIDs and aliases too
+    let xname_vec = &super::resolver::resolve_xname_vec(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+    )
+    .await?;
+
+    let cfs_component_vec = cfs::component::mesa::http_client::get(
+        shasta_token,
+        shasta_base_url,
+        shasta_root_cert,
+        xname_vec,
+    )
+    .await?;
+
+    Ok(xname_vec
+        .iter()
+        .map(|xname| {
+            let desired_config_opt = cfs_component_vec
+                .iter()
+                .find(|cfs_component| cfs_component["id"].as_str() == Some(xname.as_str()))
+                .and_then(|cfs_component| cfs_component["desiredConfig"].as_str())
+                .filter(|desired_config| !desired_config.is_empty())
+                .map(str::to_string);
+
+            (xname.clone(), desired_config_opt)
+        })
+        .collect())
+}
@@ -0,0 +1,69 @@
+pub mod http_client {
+
+    use serde_json::Value;
+
+    /// Get list of hardware of type node from SLS ref --> https://github.com/Cray-HPE/docs-csm/blob/release/1.5/api/sls.md#get-searchhardware
+    pub async fn get_hardware_node_vec(
+        shasta_token: &str,
+        shasta_base_url: &str,
+        shasta_root_cert: &[u8],
+    ) -> Result<Vec<Value>, reqwest::Error> {
+        let client_builder = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(shasta_root_cert)?);
+
+        // Build client
+        let client = if let Ok(socks5_env) = std::env::var("SOCKS5") {
+            // socks5 proxy
+            log::debug!("SOCKS5 enabled");
+            let socks5proxy = reqwest::Proxy::all(socks5_env)?;
+
+            // rest client to authenticate
+            client_builder.proxy(socks5proxy).build()?
+        } else {
+            client_builder.build()?
+        };
+
+        let api_url = shasta_base_url.to_owned() + "/sls/v1/search/hardware";
+
+        let response_rslt = client
+            .get(api_url)
+            .query(&[("type", "comptype_node")])
+            .bearer_auth(shasta_token)
+            .send()
+            .await;
+
+        match response_rslt {
+            Ok(response) => response.error_for_status()?.json::<Vec<Value>>().await,
+            Err(error) => Err(error),
+        }
+    }
+}
+
+pub mod utils {
+
+    use serde_json::Value;
+
+    /// Returns a list of tuples like (xname, alias) from a list of SLS hardware entries. A node
+    /// may have more than one alias (eg 'nid001234' and 'uan01'), in that case there will be one
+    /// tuple per alias
+    pub fn get_xname_alias_tuple_vec(sls_hardware_value_vec: &[Value]) -> Vec<(String, String)> {
+        sls_hardware_value_vec
+            .iter()
+            .flat_map(|sls_hardware_value| {
+                let xname = sls_hardware_value["Xname"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string();
+
+                sls_hardware_value
+                    .pointer("/ExtraProperties/Aliases")
+                    .and_then(|aliases| aliases.as_array().cloned())
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(move |alias| {
+                        alias.as_str().map(|alias| (xname.clone(), alias.to_string()))
+                    })
+            })
+            .collect()
+    }
+}